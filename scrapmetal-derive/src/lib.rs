@@ -6,10 +6,10 @@ extern crate syn;
 extern crate quote;
 
 use proc_macro::TokenStream;
-use quote::Tokens;
+use quote::{ToTokens, Tokens};
 
-#[proc_macro_derive(Term)]
-pub fn derive_into_heap(input: TokenStream) -> TokenStream {
+#[proc_macro_derive(Term, attributes(scrap))]
+pub fn derive_term(input: TokenStream) -> TokenStream {
     let source = input.to_string();
     let ast = syn::parse_derive_input(&source).unwrap();
     let expanded = impl_term(&ast);
@@ -20,63 +20,521 @@ pub fn derive_into_heap(input: TokenStream) -> TokenStream {
     expanded.parse().unwrap()
 }
 
-fn impl_term(ast: &syn::DeriveInput) -> Tokens {
-    match ast.body {
-        syn::Body::Struct(ref data) => impl_term_for_struct(ast, data),
-        syn::Body::Enum(ref variants) => impl_term_for_enum(ast, variants),
+#[proc_macro_derive(TermMeta, attributes(scrap))]
+pub fn derive_term_meta(input: TokenStream) -> TokenStream {
+    let source = input.to_string();
+    let ast = syn::parse_derive_input(&source).unwrap();
+    let expanded = impl_term_meta(&ast);
+    expanded.parse().unwrap()
+}
+
+/// Get all the meta items nested within `#[scrap(...)]` attributes.
+fn scrap_meta_items(attrs: &[syn::Attribute]) -> Vec<&syn::MetaItem> {
+    attrs
+        .iter()
+        .filter_map(|attr| match attr.value {
+            syn::MetaItem::List(ref ident, ref items) if ident == "scrap" => Some(items),
+            _ => None,
+        })
+        .flatten()
+        .map(|item| match *item {
+            syn::NestedMetaItem::MetaItem(ref meta) => meta,
+            syn::NestedMetaItem::Literal(_) => {
+                panic!("#[scrap(...)] expects attributes, not literals")
+            }
+        })
+        .collect()
+}
+
+/// The options given to the type itself with `#[scrap(...)]`.
+#[derive(Default)]
+struct ContainerAttrs {
+    /// The where clause predicates to use instead of the inferred `T: Term`
+    /// bounds.
+    bound: Option<String>,
+
+    /// The foreign type this definition mirrors, if any.
+    remote: Option<syn::Path>,
+
+    /// Whether traversals should see straight through this wrapper type, to
+    /// its only traversed field.
+    transparent: bool,
+}
+
+impl ContainerAttrs {
+    fn new(ast: &syn::DeriveInput) -> ContainerAttrs {
+        let mut attrs = ContainerAttrs::default();
+        for meta in scrap_meta_items(&ast.attrs) {
+            match *meta {
+                syn::MetaItem::NameValue(ref ident, syn::Lit::Str(ref bound, _))
+                    if ident == "bound" =>
+                {
+                    attrs.bound = Some(bound.clone())
+                }
+                syn::MetaItem::NameValue(ref ident, syn::Lit::Str(ref remote, _))
+                    if ident == "remote" =>
+                {
+                    attrs.remote = Some(syn::parse_path(remote).unwrap_or_else(|e| {
+                        panic!("invalid #[scrap(remote = \"{}\")]: {}", remote, e)
+                    }))
+                }
+                syn::MetaItem::Word(ref ident) if ident == "transparent" => {
+                    attrs.transparent = true
+                }
+                syn::MetaItem::List(ref ident, _) if ident == "order" => {
+                    // Handled by `Variant::order`.
+                    if let syn::Body::Enum(_) = ast.body {
+                        panic!("#[scrap(order(...))] goes on each enum variant, not the enum")
+                    }
+                }
+                ref meta => panic!("unknown container attribute: #[scrap({})]", meta.name()),
+            }
+        }
+        attrs
     }
 }
 
-fn impl_term_for_struct(ast: &syn::DeriveInput, data: &syn::VariantData) -> Tokens {
-    match *data {
-        syn::VariantData::Struct(ref fields) => impl_term_for_struct_struct(ast, fields),
-        syn::VariantData::Tuple(ref fields) => impl_term_for_tuple_struct(ast, fields),
-        syn::VariantData::Unit => impl_term_for_unit_struct(ast),
+/// The options given to a field with `#[scrap(...)]`.
+#[derive(Default)]
+struct FieldAttrs {
+    /// Never traverse into this field, and don't require that it be a `Term`.
+    skip: bool,
+
+    /// Visit this field as a whole, but never traverse into it, and don't
+    /// require that it be a `Term`.
+    leaf: bool,
+
+    /// Traverse this field of a foreign type through the given
+    /// `#[scrap(remote = "...")]` mirror definition.
+    with: Option<syn::Path>,
+}
+
+impl FieldAttrs {
+    fn new(field: &syn::Field) -> FieldAttrs {
+        let mut attrs = FieldAttrs::default();
+        for meta in scrap_meta_items(&field.attrs) {
+            match *meta {
+                syn::MetaItem::Word(ref ident) if ident == "skip" => attrs.skip = true,
+                syn::MetaItem::Word(ref ident) if ident == "leaf" => attrs.leaf = true,
+                syn::MetaItem::NameValue(ref ident, syn::Lit::Str(ref with, _))
+                    if ident == "with" =>
+                {
+                    attrs.with = Some(syn::parse_path(with).unwrap_or_else(|e| {
+                        panic!("invalid #[scrap(with = \"{}\")]: {}", with, e)
+                    }))
+                }
+                ref meta => panic!("unknown field attribute: #[scrap({})]", meta.name()),
+            }
+        }
+        attrs
+    }
+
+    /// Does this field's type need to be a `Term`?
+    fn is_term(&self) -> bool {
+        !self.skip && !self.leaf
+    }
+
+    /// Wrap the field's value in a `Leaf`, or a `Remote` if it is traversed
+    /// through a mirror definition, so that it can be passed to the traversal.
+    fn wrap(&self, value: &syn::Ident, ctor: &str) -> Tokens {
+        let ctor = syn::Ident::new(ctor);
+        match self.with {
+            _ if self.leaf => quote! { ::scrapmetal::Leaf::#ctor(#value) },
+            Some(ref with) => quote! { ::scrapmetal::Remote::<_, #with>::#ctor(#value) },
+            None => quote! { #value },
+        }
     }
 }
 
-fn impl_term_for_struct_struct(ast: &syn::DeriveInput, fields: &[syn::Field]) -> Tokens {
-    let name = &ast.ident;
-    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+/// A single constructor of the type we are deriving `Term` for: either the
+/// struct itself, or one of the enum's variants.
+struct Variant<'a> {
+    path: Tokens,
+    ident: Option<&'a syn::Ident>,
+    attrs: &'a [syn::Attribute],
+    data: &'a syn::VariantData,
+}
+
+impl<'a> Variant<'a> {
+    fn fields(&self) -> &'a [syn::Field] {
+        match *self.data {
+            syn::VariantData::Struct(ref fields) | syn::VariantData::Tuple(ref fields) => fields,
+            syn::VariantData::Unit => &[],
+        }
+    }
+
+    /// The identifiers we bind each field to when destructuring this
+    /// variant. These are never the field names themselves, so that they can't
+    /// shadow the generated methods' parameters.
+    fn bindings(&self) -> Vec<syn::Ident> {
+        (0..self.fields().len())
+            .map(|i| syn::Ident::new(format!("__field{}", i)))
+            .collect()
+    }
+
+    /// The name of the `i`th field, using its index for tuple fields.
+    fn field_name(&self, i: usize) -> String {
+        match self.fields()[i].ident {
+            Some(ref ident) => ident.to_string(),
+            None => i.to_string(),
+        }
+    }
 
-    let transforms: Vec<_> = fields.iter()
-        .map(|f| {
-            quote! {
-                #f.ident : f.transform(self.#f.ident) ,
+    /// The indices of the fields that are traversed, in the order they are
+    /// traversed. By default this is declaration order, but fields named in
+    /// `#[scrap(order(...))]` are traversed first, in the given order.
+    fn order(&self) -> Vec<usize> {
+        let mut order = vec![];
+        for meta in scrap_meta_items(self.attrs) {
+            match *meta {
+                syn::MetaItem::List(ref ident, ref items) if ident == "order" => {
+                    for item in items {
+                        let name = match *item {
+                            syn::NestedMetaItem::MetaItem(syn::MetaItem::Word(ref w)) => {
+                                w.to_string()
+                            }
+                            syn::NestedMetaItem::Literal(syn::Lit::Int(i, _)) => i.to_string(),
+                            _ => panic!("#[scrap(order(...))] expects field names or indices"),
+                        };
+                        let i = (0..self.fields().len())
+                            .find(|&i| self.field_name(i) == name)
+                            .unwrap_or_else(|| {
+                                panic!("#[scrap(order(...))] names unknown field `{}`", name)
+                            });
+                        if FieldAttrs::new(&self.fields()[i]).skip || order.contains(&i) {
+                            panic!("#[scrap(order(...))] can't traverse field `{}`", name);
+                        }
+                        order.push(i);
+                    }
+                }
+                // Structs share their attributes with `ContainerAttrs`.
+                _ if self.ident.is_none() => {}
+                ref meta => panic!("unknown variant attribute: #[scrap({})]", meta.name()),
             }
-        })
-        .collect();
+        }
+
+        let rest: Vec<_> = (0..self.fields().len())
+            .filter(|i| !order.contains(i) && !FieldAttrs::new(&self.fields()[*i]).skip)
+            .collect();
+        order.extend(rest);
+        order
+    }
 
-    let queries: Vec<_> = fields.iter()
-        .map(|f| {
-            quote! {
-                let r = q.query(&self.#f.ident);
-                each(q, r);
+    /// The bindings and attributes of only those fields that are traversed, in
+    /// the order they are traversed, or in the reverse of that order.
+    fn traversed(&self, rev: bool) -> Vec<(syn::Ident, FieldAttrs)> {
+        let bindings = self.bindings();
+        let mut order = self.order();
+        if rev {
+            order.reverse();
+        }
+        order
+            .into_iter()
+            .map(|i| (bindings[i].clone(), FieldAttrs::new(&self.fields()[i])))
+            .collect()
+    }
+
+    /// Build either a pattern or an expression for this variant, with
+    /// `values[i]` in the position of the `i`th field.
+    fn build(&self, values: &[Tokens]) -> Tokens {
+        let path = &self.path;
+        match *self.data {
+            syn::VariantData::Struct(ref fields) => {
+                let names: Vec<_> = fields.iter().map(|f| &f.ident).collect();
+                quote! { #path { #( #names : #values ),* } }
             }
-        })
-        .collect();
+            syn::VariantData::Tuple(_) => quote! { #path ( #( #values ),* ) },
+            syn::VariantData::Unit => quote! { #path },
+        }
+    }
 
-    let mutations: Vec<_> = fields.iter()
-        .map(|f| {
-            quote! {
-                let r = m.mutate(&mut self.#f.indent);
-                each(m, r);
+    /// A pattern matching this variant without binding any of its fields.
+    fn wildcard_pattern(&self) -> Tokens {
+        let path = &self.path;
+        match *self.data {
+            syn::VariantData::Struct(_) => quote! { #path { .. } },
+            syn::VariantData::Tuple(_) => quote! { #path ( .. ) },
+            syn::VariantData::Unit => quote! { #path },
+        }
+    }
+
+    /// The names of the fields that are traversed, in the order they are
+    /// traversed.
+    fn child_names(&self) -> Vec<String> {
+        self.order()
+            .into_iter()
+            .map(|i| self.field_name(i))
+            .collect()
+    }
+
+    fn pattern(&self, mode: Tokens) -> Tokens {
+        let values: Vec<_> = self.bindings()
+            .into_iter()
+            .map(|b| quote! { #mode #b })
+            .collect();
+        self.build(&values)
+    }
+
+    fn transform_arm(&self, rev: bool) -> Tokens {
+        let pattern = self.pattern(quote! {});
+        // Transform each field in traversal order, rebinding it to its new
+        // value, and then rebuild the variant from the new values.
+        let transforms: Vec<_> = self.traversed(rev)
+            .into_iter()
+            .map(|(b, attrs)| if attrs.leaf || attrs.with.is_some() {
+                let value = attrs.wrap(&b, "new");
+                quote! { let #b = f.transform(#value).into_inner(); }
+            } else {
+                quote! { let #b = f.transform(#b); }
+            })
+            .collect();
+        let values: Vec<_> = self.bindings().into_iter().map(|b| quote! { #b }).collect();
+        let expr = self.build(&values);
+        quote! {
+            #pattern => {
+                #( #transforms )*
+                #expr
             }
-        })
-        .collect();
+        }
+    }
+
+    fn query_arm(&self, rev: bool) -> Tokens {
+        let pattern = self.pattern(quote! { ref });
+        let values: Vec<_> = self.traversed(rev)
+            .into_iter()
+            .map(|(b, attrs)| attrs.wrap(&b, "from_ref"))
+            .collect();
+        quote! {
+            #pattern => {
+                #(
+                    let r = q.query(#values);
+                    each(q, r);
+                )*
+            }
+        }
+    }
+
+    fn mutation_arm(&self, rev: bool) -> Tokens {
+        let pattern = self.pattern(quote! { ref mut });
+        let values: Vec<_> = self.traversed(rev)
+            .into_iter()
+            .map(|(b, attrs)| attrs.wrap(&b, "from_mut"))
+            .collect();
+        quote! {
+            #pattern => {
+                #(
+                    let r = m.mutate(#values);
+                    each(m, r);
+                )*
+            }
+        }
+    }
+}
+
+/// The constructors of the type we are deriving `Term` for. For remote
+/// derives, these construct the foreign type rather than the mirror.
+fn variants<'a>(ast: &'a syn::DeriveInput) -> Vec<Variant<'a>> {
+    let name = match ContainerAttrs::new(ast).remote {
+        Some(remote) => {
+            // Drop any generic arguments, which can't be used in patterns.
+            let segments: Vec<_> = remote.segments.iter().map(|s| &s.ident).collect();
+            let global = if remote.global { quote! { :: } } else { quote! {} };
+            quote! { #global #( #segments )::* }
+        }
+        None => {
+            let ident = &ast.ident;
+            quote! { #ident }
+        }
+    };
+    match ast.body {
+        syn::Body::Struct(ref data) => vec![
+            Variant {
+                path: quote! { #name },
+                ident: None,
+                attrs: &ast.attrs,
+                data,
+            },
+        ],
+        syn::Body::Enum(ref variants) => variants
+            .iter()
+            .map(|v| {
+                let variant_ident = &v.ident;
+                Variant {
+                    path: quote! { #name :: #variant_ident },
+                    ident: Some(variant_ident),
+                    attrs: &v.attrs,
+                    data: &v.data,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Does the given type mention the given type parameter anywhere?
+fn mentions(ty: &syn::Ty, param: &syn::Ident) -> bool {
+    let mut tokens = Tokens::new();
+    ty.to_tokens(&mut tokens);
+    tokens
+        .as_str()
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .any(|word| word == param.as_ref())
+}
+
+/// Add bounds to the where clause of the type's generics: either the user
+/// provided `#[scrap(bound = "...")]` predicates, or `T: Term` for every type
+/// parameter `T` that appears in a traversed field.
+fn bounded_generics(ast: &syn::DeriveInput, variants: &[Variant]) -> syn::Generics {
+    let mut generics = ast.generics.clone();
+
+    let predicates = match ContainerAttrs::new(ast).bound {
+        Some(ref bound) if bound.trim().is_empty() => vec![],
+        Some(ref bound) => {
+            syn::parse_where_clause(&format!("where {}", bound))
+                .unwrap_or_else(|e| panic!("invalid #[scrap(bound = \"{}\")]: {}", bound, e))
+                .predicates
+        }
+        None => {
+            let traversed_tys: Vec<_> = variants
+                .iter()
+                .flat_map(|v| v.fields())
+                .filter(|field| FieldAttrs::new(field).is_term())
+                .map(|field| &field.ty)
+                .collect();
+            ast.generics
+                .ty_params
+                .iter()
+                .filter(|param| traversed_tys.iter().any(|ty| mentions(ty, &param.ident)))
+                .map(|param| {
+                    syn::parse_where_clause(&format!("where {}: ::scrapmetal::Term", param.ident))
+                        .unwrap()
+                        .predicates
+                        .remove(0)
+                })
+                .collect()
+        }
+    };
+
+    generics.where_clause.predicates.extend(predicates);
+    generics
+}
+
+fn impl_term(ast: &syn::DeriveInput) -> Tokens {
+    let name = &ast.ident;
+    let variants = variants(ast);
+    let generics = bounded_generics(ast, &variants);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let transforms: Vec<_> = variants.iter().map(|v| v.transform_arm(false)).collect();
+    let queries: Vec<_> = variants.iter().map(|v| v.query_arm(false)).collect();
+    let mutations: Vec<_> = variants.iter().map(|v| v.mutation_arm(false)).collect();
+    let rev_transforms: Vec<_> = variants.iter().map(|v| v.transform_arm(true)).collect();
+    let rev_queries: Vec<_> = variants.iter().map(|v| v.query_arm(true)).collect();
+    let rev_mutations: Vec<_> = variants.iter().map(|v| v.mutation_arm(true)).collect();
+
+    let attrs = ContainerAttrs::new(ast);
+    if attrs.transparent {
+        return impl_transparent(ast, &variants, &generics);
+    }
+
+    let remote = match attrs.remote {
+        Some(remote) => remote,
+        None => {
+            return quote! {
+                impl #impl_generics ::scrapmetal::Term for #name #ty_generics
+                    #where_clause
+                {
+                    #[inline]
+                    #[allow(unused_variables)]
+                    #[allow(unused_mut)]
+                    fn map_one_transform<F>(self, f: &mut F) -> Self
+                    where
+                        F: ::scrapmetal::GenericTransform,
+                    {
+                        match self {
+                            #( #transforms )*
+                        }
+                    }
+
+                    #[inline]
+                    #[allow(unused_variables)]
+                    #[allow(unused_mut)]
+                    fn map_one_query<Q, R, F>(&self, q: &mut Q, mut each: F)
+                    where
+                        Q: ::scrapmetal::GenericQuery<R>,
+                        F: FnMut(&mut Q, R),
+                    {
+                        match *self {
+                            #( #queries )*
+                        }
+                    }
+
+                    #[inline]
+                    #[allow(unused_variables)]
+                    #[allow(unused_mut)]
+                    fn map_one_mutation<M, R, F>(&mut self, m: &mut M, mut each: F)
+                    where
+                        M: ::scrapmetal::GenericMutate<R>,
+                        F: FnMut(&mut M, R),
+                    {
+                        match *self {
+                            #( #mutations )*
+                        }
+                    }
+
+                    #[inline]
+                    #[allow(unused_variables)]
+                    #[allow(unused_mut)]
+                    fn map_one_transform_rev<F>(self, f: &mut F) -> Self
+                    where
+                        F: ::scrapmetal::GenericTransform,
+                    {
+                        match self {
+                            #( #rev_transforms )*
+                        }
+                    }
+
+                    #[inline]
+                    #[allow(unused_variables)]
+                    #[allow(unused_mut)]
+                    fn map_one_query_rev<Q, R, F>(&self, q: &mut Q, mut each: F)
+                    where
+                        Q: ::scrapmetal::GenericQuery<R>,
+                        F: FnMut(&mut Q, R),
+                    {
+                        match *self {
+                            #( #rev_queries )*
+                        }
+                    }
+
+                    #[inline]
+                    #[allow(unused_variables)]
+                    #[allow(unused_mut)]
+                    fn map_one_mutation_rev<M, R, F>(&mut self, m: &mut M, mut each: F)
+                    where
+                        M: ::scrapmetal::GenericMutate<R>,
+                        F: FnMut(&mut M, R),
+                    {
+                        match *self {
+                            #( #rev_mutations )*
+                        }
+                    }
+                }
+            };
+        }
+    };
 
     quote! {
-        impl #impl_generics ::scrapmetal::Term for #name #ty_generics
+        impl #impl_generics ::scrapmetal::RemoteTerm<#remote> for #name #ty_generics
             #where_clause
         {
             #[inline]
             #[allow(unused_variables)]
             #[allow(unused_mut)]
-            fn map_one_transform<F>(self, f: &mut F) -> Self
+            fn map_one_transform<F>(this: #remote, f: &mut F) -> #remote
             where
                 F: ::scrapmetal::GenericTransform,
             {
-                Self {
+                match this {
                     #( #transforms )*
                 }
             }
@@ -84,389 +542,273 @@ fn impl_term_for_struct_struct(ast: &syn::DeriveInput, fields: &[syn::Field]) ->
             #[inline]
             #[allow(unused_variables)]
             #[allow(unused_mut)]
-            fn map_one_query<Q, R, F>(&self, q: &mut Q, mut each: F)
+            fn map_one_query<Q, R, F>(this: &#remote, q: &mut Q, mut each: F)
             where
                 Q: ::scrapmetal::GenericQuery<R>,
                 F: FnMut(&mut Q, R),
             {
-                #( #queries )*
+                match *this {
+                    #( #queries )*
+                }
             }
 
             #[inline]
             #[allow(unused_variables)]
             #[allow(unused_mut)]
-            fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+            fn map_one_mutation<M, R, F>(this: &mut #remote, m: &mut M, mut each: F)
             where
                 M: ::scrapmetal::GenericMutate<R>,
                 F: FnMut(&mut M, R),
             {
-                #( #mutations )*
-            }
-        }
-    }
-}
-
-fn impl_term_for_tuple_struct(ast: &syn::DeriveInput, fields: &[syn::Field]) -> Tokens {
-    let name = &ast.ident;
-    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
-
-    let fields: Vec<_> = (0..fields.len()).map(syn::Ident::new).collect();
-
-    let transforms: Vec<_> = fields.iter()
-        .map(|i| {
-            quote! {
-                f.transform(self.#i) ,
-            }
-        })
-        .collect();
-
-    let queries: Vec<_> = fields.iter()
-        .map(|i| {
-            quote! {
-                let r = q.query(&self.#i);
-                each(q, r);
-            }
-        })
-        .collect();
-
-    let mutations: Vec<_> = fields.iter()
-        .map(|i| {
-            quote! {
-                let r = m.mutate(&mut self.#i);
-                each(m, r);
+                match *this {
+                    #( #mutations )*
+                }
             }
-        })
-        .collect();
 
-    quote! {
-        impl #impl_generics ::scrapmetal::Term for #name #ty_generics
-            #where_clause
-        {
             #[inline]
             #[allow(unused_variables)]
             #[allow(unused_mut)]
-            fn map_one_transform<F>(self, f: &mut F) -> Self
+            fn map_one_transform_rev<F>(this: #remote, f: &mut F) -> #remote
             where
                 F: ::scrapmetal::GenericTransform,
             {
-                #name ( #( #transforms )* )
+                match this {
+                    #( #rev_transforms )*
+                }
             }
 
             #[inline]
             #[allow(unused_variables)]
             #[allow(unused_mut)]
-            fn map_one_query<Q, R, F>(&self, q: &mut Q, mut each: F)
+            fn map_one_query_rev<Q, R, F>(this: &#remote, q: &mut Q, mut each: F)
             where
                 Q: ::scrapmetal::GenericQuery<R>,
                 F: FnMut(&mut Q, R),
             {
-                #( #queries )*
+                match *this {
+                    #( #rev_queries )*
+                }
             }
 
             #[inline]
             #[allow(unused_variables)]
             #[allow(unused_mut)]
-            fn map_one_mutation<M, R, F>(&mut self, m: &mut M, mut each: F)
+            fn map_one_mutation_rev<M, R, F>(this: &mut #remote, m: &mut M, mut each: F)
             where
                 M: ::scrapmetal::GenericMutate<R>,
                 F: FnMut(&mut M, R),
             {
-                #( #mutations )*
+                match *this {
+                    #( #rev_mutations )*
+                }
             }
         }
     }
 }
 
-fn impl_term_for_unit_struct(ast: &syn::DeriveInput) -> Tokens {
-    let name = &ast.ident;
-    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
-
-    quote! {
-        impl #impl_generics ::scrapmetal::Term for #name #ty_generics
-            #where_clause
-        {
-            #[inline(always)]
-            fn map_one_transform<F>(self, _: &mut F) -> Self
-            where
-                F: ::scrapmetal::GenericTransform,
-            {
-                self
-            }
-
-            #[inline(always)]
-            fn map_one_query<Q, R, F>(&self, _: &mut Q, _: F)
-            where
-                Q: ::scrapmetal::GenericQuery<R>,
-                F: FnMut(&mut Q, R),
-            {}
-
-            #[inline(always)]
-            fn map_one_mutation<M, R, F>(&mut self, _: &mut M, _: F)
-            where
-                M: ::scrapmetal::GenericMutate<R>,
-                F: FnMut(&mut M, R),
-            {}
-        }
+fn impl_term_meta(ast: &syn::DeriveInput) -> Tokens {
+    let attrs = ContainerAttrs::new(ast);
+    if attrs.remote.is_some() {
+        panic!("#[derive(TermMeta)] does not support #[scrap(remote = \"...\")]");
+    }
+    if attrs.transparent {
+        panic!("#[derive(TermMeta)] does not support #[scrap(transparent)]");
     }
-}
 
-fn impl_term_for_enum(ast: &syn::DeriveInput, variants: &[syn::Variant]) -> Tokens {
     let name = &ast.ident;
-    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let type_name = name.to_string();
+    let variants = variants(ast);
+    let generics = bounded_generics(ast, &variants);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    let transforms: Vec<_> = variants.iter()
+    let variant_names: Vec<_> = variants
+        .iter()
         .map(|v| {
-            let variant_ident = &v.ident;
-            match v.data {
-                syn::VariantData::Struct(ref fields) => {
-                    let field_names: Vec<_> = fields.iter()
-                        .map(|f| {
-                            let ident = &f.ident;
-                            quote! {
-                                #ident ,
-                            }
-                        })
-                        .collect();
-
-                    let field_transforms: Vec<_> = fields.iter()
-                        .map(|f| {
-                            let ident = &f.ident;
-                            quote! {
-                                #ident : f.transform( #ident ) ,
-                            }
-                        })
-                        .collect();
-
-                    quote! {
-                        #name :: #variant_ident { #( #field_names )* } => {
-                            #name :: #variant_ident { #( #field_transforms )* }
-                        }
-                    }
-                }
-                syn::VariantData::Tuple(ref fields) => {
-                    let tuple_names: Vec<_> = (0..fields.len())
-                        .map(|i| {
-                            let c = ('a' as u8 + i as u8) as char;
-                            let mut s = String::with_capacity(1);
-                            s.push(c);
-                            syn::Ident::new(s)
-                        })
-                        .collect();
-
-                    let tuple_patterns: Vec<_> = tuple_names.iter()
-                        .map(|p| {
-                            quote! {
-                                #p ,
-                            }
-                        })
-                        .collect();
-
-                    let tuple_transforms: Vec<_> = tuple_names.iter()
-                        .map(|p| {
-                            quote! {
-                                f.transform( #p ) ,
-                            }
-                        })
-                        .collect();
-
-                    quote! {
-                        #name :: #variant_ident ( #( #tuple_patterns )* ) => {
-                            #name :: #variant_ident ( #( #tuple_transforms )* )
-                        }
-                    }
-                }
-                syn::VariantData::Unit => {
-                    quote! {
-                        // Nothing to do here.
-                    }
+            let pattern = v.wildcard_pattern();
+            match v.ident {
+                Some(ident) => {
+                    let ident = ident.to_string();
+                    quote! { #pattern => Some(#ident), }
                 }
+                None => quote! { #pattern => None, },
             }
         })
         .collect();
 
-    let queries: Vec<_> = variants.iter()
+    let child_names: Vec<_> = variants
+        .iter()
         .map(|v| {
-            let variant_ident = &v.ident;
-            match v.data {
-                syn::VariantData::Struct(ref fields) => {
-                    let field_names: Vec<_> = fields.iter()
-                        .map(|f| {
-                            let ident = &f.ident;
-                            quote! {
-                                ref #ident ,
-                            }
-                        })
-                        .collect();
-
-                    let field_queries: Vec<_> = fields.iter()
-                        .map(|f| {
-                            let ident = &f.ident;
-                            quote! {
-                                let r = q.query( #ident );
-                                each(q, r);
-                            }
-                        })
-                        .collect();
-
-                    quote! {
-                        #name :: #variant_ident { #( #field_names )* } => {
-                            #( #field_queries )*
-                        }
-                    }
-                }
-                syn::VariantData::Tuple(ref fields) => {
-                    let tuple_names: Vec<_> = (0..fields.len())
-                        .map(|i| {
-                            let c = ('a' as u8 + i as u8) as char;
-                            let mut s = String::with_capacity(1);
-                            s.push(c);
-                            syn::Ident::new(s)
-                        })
-                        .collect();
-
-                    let tuple_patterns: Vec<_> = tuple_names.iter()
-                        .map(|p| {
-                            quote! {
-                                ref #p ,
-                            }
-                        })
-                        .collect();
-
-                    let tuple_queries: Vec<_> = tuple_names.iter()
-                        .map(|p| {
-                            quote! {
-                                let r = q.query( #p );
-                                each(q, r);
-                            }
-                        })
-                        .collect();
-
-                    quote! {
-                        #name :: #variant_ident ( #( #tuple_patterns )* ) => {
-                            #( #tuple_queries )*
-                        }
-                    }
-                }
-                syn::VariantData::Unit => {
-                    quote! {
-                        // Nothing to do here.
-                    }
-                }
-            }
+            let pattern = v.wildcard_pattern();
+            let names = v.child_names();
+            quote! { #pattern => &[ #( #names ),* ], }
         })
         .collect();
 
-    let mutations: Vec<_> = variants.iter()
-        .map(|v| {
-            let variant_ident = &v.ident;
-            match v.data {
-                syn::VariantData::Struct(ref fields) => {
-                    let field_names: Vec<_> = fields.iter()
-                        .map(|f| {
-                            let ident = &f.ident;
-                            quote! {
-                                ref mut #ident ,
-                            }
-                        })
-                        .collect();
-
-                    let field_mutations: Vec<_> = fields.iter()
-                        .map(|f| {
-                            let ident = &f.ident;
-                            quote! {
-                                let r = m.mutate( #ident );
-                                each(m, r);
-                            }
-                        })
-                        .collect();
+    quote! {
+        impl #impl_generics ::scrapmetal::TermMeta for #name #ty_generics
+            #where_clause
+        {
+            #[inline]
+            fn type_name(&self) -> &'static str {
+                #type_name
+            }
 
-                    quote! {
-                        #name :: #variant_ident { #( #field_names )* } => {
-                            #( #field_mutations )*
-                        }
-                    }
+            #[inline]
+            fn variant_name(&self) -> Option<&'static str> {
+                match *self {
+                    #( #variant_names )*
                 }
-                syn::VariantData::Tuple(ref fields) => {
-                    let tuple_names: Vec<_> = (0..fields.len())
-                        .map(|i| {
-                            let c = ('a' as u8 + i as u8) as char;
-                            let mut s = String::with_capacity(1);
-                            s.push(c);
-                            syn::Ident::new(s)
-                        })
-                        .collect();
-
-                    let tuple_patterns: Vec<_> = tuple_names.iter()
-                        .map(|p| {
-                            quote! {
-                                ref mut #p ,
-                            }
-                        })
-                        .collect();
-
-                    let tuple_mutations: Vec<_> = tuple_names.iter()
-                        .map(|p| {
-                            quote! {
-                                let r = m.mutate( #p );
-                                each(m, r);
-                            }
-                        })
-                        .collect();
+            }
 
-                    quote! {
-                        #name :: #variant_ident ( #( #tuple_patterns )* ) => {
-                            #( #tuple_mutations )*
-                        }
-                    }
-                }
-                syn::VariantData::Unit => {
-                    quote! {
-                        // Nothing to do here.
-                    }
+            #[inline]
+            fn child_names(&self) -> &'static [&'static str] {
+                match *self {
+                    #( #child_names )*
                 }
             }
+        }
+    }
+}
+
+/// Implement `Term` and `Transparent` for a `#[scrap(transparent)]` wrapper,
+/// delegating traversal to its one traversed field.
+fn impl_transparent(ast: &syn::DeriveInput, variants: &[Variant], generics: &syn::Generics) -> Tokens {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let variant = match (&ast.body, variants) {
+        (&syn::Body::Struct(_), [variant]) => variant,
+        _ => panic!("#[scrap(transparent)] can only be used on structs"),
+    };
+    let inner: Vec<_> = variant
+        .bindings()
+        .into_iter()
+        .zip(variant.fields())
+        .filter(|&(_, field)| !FieldAttrs::new(field).skip)
+        .collect();
+    let (binding, field) = match inner.as_slice() {
+        [(binding, field)] if {
+            let attrs = FieldAttrs::new(field);
+            attrs.is_term() && attrs.with.is_none()
+        } => (binding, field),
+        _ => panic!(
+            "#[scrap(transparent)] structs must have exactly one field that isn't \
+             #[scrap(skip)], and it can't be #[scrap(leaf)] or #[scrap(with)]"
+        ),
+    };
+    let ty = &field.ty;
+
+    let ref_pattern = variant.pattern(quote! { ref });
+    let mut_pattern = variant.pattern(quote! { ref mut });
+    let pattern = variant.pattern(quote! {});
+    let values: Vec<_> = variant
+        .bindings()
+        .into_iter()
+        .map(|b| if b == *binding {
+            quote! { f(#b) }
+        } else {
+            quote! { #b }
         })
         .collect();
+    let expr = variant.build(&values);
 
     quote! {
         impl #impl_generics ::scrapmetal::Term for #name #ty_generics
             #where_clause
         {
             #[inline]
-            #[allow(unused_variables)]
-            #[allow(unused_mut)]
             fn map_one_transform<F>(self, f: &mut F) -> Self
             where
                 F: ::scrapmetal::GenericTransform,
             {
-                match self {
-                    #( #transforms )*
-                }
+                ::scrapmetal::Transparent::map_inner(self, |inner| {
+                    ::scrapmetal::Term::map_one_transform(inner, f)
+                })
             }
 
             #[inline]
-            #[allow(unused_variables)]
-            #[allow(unused_mut)]
-            fn map_one_query<Q, R, F>(&self, q: &mut Q, mut each: F)
+            fn map_one_query<Q, R, F>(&self, q: &mut Q, each: F)
             where
                 Q: ::scrapmetal::GenericQuery<R>,
                 F: FnMut(&mut Q, R),
             {
-                match *self {
-                    #( #queries )*
-                }
+                ::scrapmetal::Term::map_one_query(::scrapmetal::Transparent::inner(self), q, each)
             }
 
             #[inline]
-            #[allow(unused_variables)]
-            #[allow(unused_mut)]
-            fn map_one_mutation<M, R, F>(&mut self, m: &mut M, mut each: F)
+            fn map_one_mutation<M, R, F>(&mut self, m: &mut M, each: F)
+            where
+                M: ::scrapmetal::GenericMutate<R>,
+                F: FnMut(&mut M, R),
+            {
+                ::scrapmetal::Term::map_one_mutation(
+                    ::scrapmetal::Transparent::inner_mut(self),
+                    m,
+                    each,
+                )
+            }
+
+            #[inline]
+            fn map_one_transform_rev<F>(self, f: &mut F) -> Self
+            where
+                F: ::scrapmetal::GenericTransform,
+            {
+                ::scrapmetal::Transparent::map_inner(self, |inner| {
+                    ::scrapmetal::Term::map_one_transform_rev(inner, f)
+                })
+            }
+
+            #[inline]
+            fn map_one_query_rev<Q, R, F>(&self, q: &mut Q, each: F)
+            where
+                Q: ::scrapmetal::GenericQuery<R>,
+                F: FnMut(&mut Q, R),
+            {
+                ::scrapmetal::Term::map_one_query_rev(::scrapmetal::Transparent::inner(self), q, each)
+            }
+
+            #[inline]
+            fn map_one_mutation_rev<M, R, F>(&mut self, m: &mut M, each: F)
             where
                 M: ::scrapmetal::GenericMutate<R>,
                 F: FnMut(&mut M, R),
             {
+                ::scrapmetal::Term::map_one_mutation_rev(
+                    ::scrapmetal::Transparent::inner_mut(self),
+                    m,
+                    each,
+                )
+            }
+        }
+
+        impl #impl_generics ::scrapmetal::Transparent for #name #ty_generics
+            #where_clause
+        {
+            type Inner = #ty;
+
+            #[inline]
+            #[allow(unused_variables)]
+            fn inner(&self) -> &#ty {
                 match *self {
-                    #( #mutations )*
+                    #ref_pattern => #binding,
+                }
+            }
+
+            #[inline]
+            #[allow(unused_variables)]
+            fn inner_mut(&mut self) -> &mut #ty {
+                match *self {
+                    #mut_pattern => #binding,
+                }
+            }
+
+            #[inline]
+            fn map_inner<F>(self, f: F) -> Self
+            where
+                F: FnOnce(#ty) -> #ty,
+            {
+                match self {
+                    #pattern => #expr,
                 }
             }
         }
@@ -0,0 +1,223 @@
+use super::{GenericMutate, GenericQuery, GenericTransform, Term};
+use std::fmt;
+use std::ops::Deref;
+
+/// An allocator that values of type `T` can be allocated from, and that hands
+/// out shared references to them for as long as it is borrowed.
+///
+/// Implement this for whatever arena a data structure's nodes already live in,
+/// so that traversals allocate the nodes they rewrite there too.
+pub trait Arena<T> {
+    /// Move the given value into the arena and return a reference to it.
+    fn alloc(&self, value: T) -> &T;
+}
+
+/// A reference to a `T` allocated in an arena of type `A`, along with the arena
+/// itself.
+///
+/// An `ArenaRef<T, A>` has its `T` as its one child, like a `Box<T>`. Since an
+/// arena only hands out shared references, the `T` can't be rewritten where it
+/// is: transforming or mutating an `ArenaRef` clones its `T`, rewrites the
+/// clone, and allocates the result in the same arena, never on the heap. When
+/// a data structure's children are themselves `ArenaRef`s, cloning a node only
+/// copies references, so rewriting a node costs one arena allocation. Every
+/// node a transformation or mutation visits is allocated anew, whether or not
+/// anything within it changed.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// struct Leak;
+///
+/// impl<T> Arena<T> for Leak {
+///     fn alloc(&self, value: T) -> &T {
+///         Box::leak(Box::new(value))
+///     }
+/// }
+///
+/// let arena = Leak;
+/// let values = vec![ArenaRef::new(&arena, 1u32), ArenaRef::new(&arena, 2)];
+///
+/// let mut double = Everywhere::new(Transformation::new(|n: u32| n * 2));
+/// let values = double.transform(values);
+/// assert_eq!(*values[0], 2);
+/// assert_eq!(*values[1], 4);
+/// ```
+pub struct ArenaRef<'a, T, A>
+where
+    T: 'a,
+    A: 'a + ?Sized,
+{
+    value: &'a T,
+    arena: &'a A,
+}
+
+impl<'a, T, A> ArenaRef<'a, T, A>
+where
+    A: ?Sized + Arena<T>,
+{
+    /// Allocate the given value in the given arena.
+    #[inline]
+    pub fn new(arena: &'a A, value: T) -> ArenaRef<'a, T, A> {
+        ArenaRef {
+            value: arena.alloc(value),
+            arena,
+        }
+    }
+}
+
+impl<'a, T, A> ArenaRef<'a, T, A>
+where
+    A: ?Sized,
+{
+    /// Refer to a value that is already allocated in the given arena.
+    #[inline]
+    pub fn from_ref(arena: &'a A, value: &'a T) -> ArenaRef<'a, T, A> {
+        ArenaRef { value, arena }
+    }
+
+    /// Get a reference to the value, for as long as the arena is borrowed.
+    #[inline]
+    pub fn get(&self) -> &'a T {
+        self.value
+    }
+
+    /// Get the arena the value is allocated in.
+    #[inline]
+    pub fn arena(&self) -> &'a A {
+        self.arena
+    }
+}
+
+impl<'a, T, A> Clone for ArenaRef<'a, T, A>
+where
+    A: ?Sized,
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, A> Copy for ArenaRef<'a, T, A>
+where
+    A: ?Sized,
+{
+}
+
+impl<'a, T, A> Deref for ArenaRef<'a, T, A>
+where
+    A: ?Sized,
+{
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T, A> PartialEq for ArenaRef<'a, T, A>
+where
+    T: PartialEq,
+    A: ?Sized,
+{
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        *self.value == *other.value
+    }
+}
+
+impl<'a, T, A> Eq for ArenaRef<'a, T, A>
+where
+    T: Eq,
+    A: ?Sized,
+{
+}
+
+impl<'a, T, A> fmt::Debug for ArenaRef<'a, T, A>
+where
+    T: fmt::Debug,
+    A: ?Sized,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ArenaRef").field(self.value).finish()
+    }
+}
+
+impl<'a, T, A> Term for ArenaRef<'a, T, A>
+where
+    T: Clone + Term,
+    A: ?Sized + Arena<T>,
+{
+    #[inline]
+    fn map_one_transform<F>(self, f: &mut F) -> Self
+    where
+        F: GenericTransform,
+    {
+        let value = f.transform(self.value.clone());
+        ArenaRef::new(self.arena, value)
+    }
+
+    #[inline]
+    fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        let r = query.query(self.value);
+        each(query, r);
+    }
+
+    #[inline]
+    fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        let mut value = self.value.clone();
+        let r = mutation.mutate(&mut value);
+        self.value = self.arena.alloc(value);
+        each(mutation, r);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Everything, Everywhere, EverywhereMut, Mutation, Query, Transformation};
+    use std::cell::Cell;
+
+    /// Leaks every value it allocates, counting them.
+    struct Counting(Cell<usize>);
+
+    impl<T> Arena<T> for Counting {
+        fn alloc(&self, value: T) -> &T {
+            self.0.set(self.0.get() + 1);
+            Box::leak(Box::new(value))
+        }
+    }
+
+    #[test]
+    fn arena_ref() {
+        let arena = Counting(Cell::new(0));
+        let pair = (ArenaRef::new(&arena, 1u32), ArenaRef::new(&arena, vec![2u32, 3]));
+        assert_eq!(arena.0.get(), 2);
+
+        let mut sum = Everything::new(Query::new(|n: &u32| *n), |a, b| a + b);
+        assert_eq!(sum.query(&pair), 6);
+        assert_eq!(arena.0.get(), 2);
+
+        let before = pair.1.get();
+        let mut incr = Everywhere::new(Transformation::new(|n: u32| n + 1));
+        let pair = incr.transform(pair);
+        assert_eq!((*pair.0, &*pair.1), (2, &vec![3, 4]));
+        assert_eq!(arena.0.get(), 4);
+        assert_eq!(*before, vec![2, 3]);
+
+        let mut pair = pair;
+        EverywhereMut::new(Mutation::new(|n: &mut u32| *n *= 10)).mutate(&mut pair);
+        assert_eq!((*pair.0, &*pair.1), (20, &vec![30, 40]));
+        assert_eq!(arena.0.get(), 6);
+    }
+}
@@ -0,0 +1,367 @@
+use super::{collect_outermost, Cast, CastThrough, GenericTransform, Term};
+use std::fmt;
+use std::future::{self, Future};
+use std::marker::PhantomData;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::vec;
+
+/// The future of a value transformed by an `AsyncGenericTransform`.
+pub type AsyncTransformed<'f, T> = Pin<Box<dyn Future<Output = T> + 'f>>;
+
+/// A similar work around as `GenericTransform`, for transformations that need
+/// to await something, like a network request, before they can produce their
+/// result. This is roughly equivalent to `for<T> FnMut(T) -> impl Future<Output
+/// = T>`.
+///
+/// The futures it returns can't borrow from the transformation, so that a
+/// traversal can keep calling it while an earlier future is still pending.
+pub trait AsyncGenericTransform<'f> {
+    /// Call the transform function on any `T`, getting a future of the
+    /// result.
+    fn transform<T>(&mut self, t: T) -> AsyncTransformed<'f, T>
+    where
+        T: 'f + Term;
+}
+
+impl<'f, A> AsyncGenericTransform<'f> for &mut A
+where
+    A: AsyncGenericTransform<'f>,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> AsyncTransformed<'f, T>
+    where
+        T: 'f + Term,
+    {
+        (**self).transform(t)
+    }
+}
+
+/// An asynchronous transformation takes some value `U` and returns a future of
+/// a new, transformed version of it. It can be called on values of *any* type
+/// `T`, not just on values of type `U`, in which case its future resolves to
+/// the given value right away.
+#[derive(Debug)]
+pub struct AsyncTransformation<F, U, Fut>
+where
+    F: FnMut(U) -> Fut,
+    Fut: Future<Output = U>,
+{
+    f: F,
+    phantom: PhantomData<fn(U) -> Fut>,
+}
+
+impl<F, U, Fut> AsyncTransformation<F, U, Fut>
+where
+    F: FnMut(U) -> Fut,
+    Fut: Future<Output = U>,
+{
+    /// Construct a new `AsyncTransformation` from the given function.
+    #[inline]
+    pub fn new(f: F) -> AsyncTransformation<F, U, Fut> {
+        AsyncTransformation {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'f, F, U, Fut> AsyncGenericTransform<'f> for AsyncTransformation<F, U, Fut>
+where
+    F: FnMut(U) -> Fut,
+    Fut: 'f + Future<Output = U>,
+    U: 'f,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> AsyncTransformed<'f, T>
+    where
+        T: 'f + Term,
+    {
+        match Cast::<U>::cast(t) {
+            Ok(u) => Box::pin(CastBack {
+                future: Box::pin((self.f)(u)),
+                phantom: PhantomData,
+            }),
+            Err(t) => Box::pin(future::ready(t)),
+        }
+    }
+}
+
+/// Resolves to the result of a future of a `U`, as the `T` it is.
+struct CastBack<Fut, T> {
+    future: Pin<Box<Fut>>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<Fut, T> Future for CastBack<Fut, T>
+where
+    Fut: Future,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        self.future.as_mut().poll(cx).map(|u| match Cast::<T>::cast(u) {
+            Ok(t) => t,
+            Err(_) => unreachable!("the future is of a `U` only if `T` is a `U`"),
+        })
+    }
+}
+
+/// Replace every value of type `U` within a data structure with the result of
+/// an asynchronous transformation, such as one that resolves a reference over
+/// the network, without blocking.
+///
+/// Traversals are synchronous, and can't await anything partway through a
+/// value's children, so this works in two phases. First it clones each
+/// outermost value of type `U` out of the data structure, with
+/// `collect_outermost`, so `U` must be `Clone`. Then it awaits the
+/// transformation of each clone, one after the other, in top down, left to
+/// right order, and finally it puts the results back where the values came
+/// from. Values of type `U` within other values of type `U` are replaced along
+/// with them, and never transformed on their own.
+///
+/// Construct one from an asynchronous function of `U` with `new`, or from any
+/// `AsyncGenericTransform` with `from_generic`.
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::future;
+///
+/// fn resolve(name: &'static str) -> future::Ready<&'static str> {
+///     future::ready(if name == "@home" { "/home/user" } else { name })
+/// }
+///
+/// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+/// #     let mut f = std::pin::pin!(f);
+/// #     let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+/// #     loop {
+/// #         if let std::task::Poll::Ready(x) = f.as_mut().poll(&mut cx) {
+/// #             return x;
+/// #         }
+/// #     }
+/// # }
+/// let mut resolve = EverywhereAsync::new(resolve);
+/// let paths = block_on(resolve.transform(vec![("@home", 1u32), ("/tmp", 2u32)]));
+/// assert_eq!(paths, vec![("/home/user", 1), ("/tmp", 2)]);
+/// ```
+#[derive(Debug)]
+pub struct EverywhereAsync<A, U> {
+    a: A,
+    phantom: PhantomData<fn(U) -> U>,
+}
+
+impl<F, U, Fut> EverywhereAsync<AsyncTransformation<F, U, Fut>, U>
+where
+    F: FnMut(U) -> Fut,
+    Fut: Future<Output = U>,
+    U: Clone + Term,
+{
+    /// Construct a new asynchronous transformation traversal, awaiting `f` on
+    /// each value of type `U`.
+    #[inline]
+    pub fn new(f: F) -> EverywhereAsync<AsyncTransformation<F, U, Fut>, U> {
+        EverywhereAsync::from_generic(AsyncTransformation::new(f))
+    }
+}
+
+impl<A, U> EverywhereAsync<A, U>
+where
+    U: Clone + Term,
+{
+    /// Construct a new asynchronous transformation traversal, awaiting the
+    /// given generic transformation of each value of type `U`.
+    #[inline]
+    pub fn from_generic(a: A) -> EverywhereAsync<A, U> {
+        EverywhereAsync {
+            a,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Transform `t`, awaiting the transformation of each value of type `U`
+    /// within it.
+    pub fn transform<'f, T>(&mut self, t: T) -> AsyncTransform<'_, 'f, A, U, T>
+    where
+        A: AsyncGenericTransform<'f>,
+        T: Term,
+    {
+        let inputs = collect_outermost::<U, T>(&t).into_iter();
+        AsyncTransform {
+            a: &mut self.a,
+            t: Some(t),
+            outputs: Vec::with_capacity(inputs.len()),
+            inputs,
+            pending: None,
+        }
+    }
+}
+
+/// The future returned by `EverywhereAsync::transform`, which resolves to the
+/// transformed value.
+pub struct AsyncTransform<'a, 'f, A: 'a, U, T> {
+    a: &'a mut A,
+    t: Option<T>,
+    inputs: vec::IntoIter<U>,
+    outputs: Vec<U>,
+    pending: Option<AsyncTransformed<'f, U>>,
+}
+
+impl<'a, 'f, A, U, T> fmt::Debug for AsyncTransform<'a, 'f, A, U, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncTransform")
+            .field("remaining", &self.inputs.len())
+            .field("done", &self.outputs.len())
+            .finish()
+    }
+}
+
+// None of the fields are ever pinned in place: the pending future is pinned in
+// its own box.
+impl<'a, 'f, A, U, T> Unpin for AsyncTransform<'a, 'f, A, U, T> {}
+
+impl<'a, 'f, A, U, T> Future for AsyncTransform<'a, 'f, A, U, T>
+where
+    A: AsyncGenericTransform<'f>,
+    U: 'f + Term,
+    T: Term,
+{
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let this = self.get_mut();
+        loop {
+            if let Some(pending) = this.pending.as_mut() {
+                match pending.as_mut().poll(cx) {
+                    Poll::Ready(u) => this.outputs.push(u),
+                    Poll::Pending => return Poll::Pending,
+                }
+                this.pending = None;
+            }
+            match this.inputs.next() {
+                Some(u) => this.pending = Some(this.a.transform(u)),
+                None => {
+                    let t = this
+                        .t
+                        .take()
+                        .expect("AsyncTransform polled after it completed");
+                    let outputs = mem::take(&mut this.outputs).into_iter();
+                    return Poll::Ready(Replace { outputs }.transform(t));
+                }
+            }
+        }
+    }
+}
+
+/// Replaces each outermost `U`, in top down, left to right order, with the next
+/// of the given outputs.
+struct Replace<U> {
+    outputs: vec::IntoIter<U>,
+}
+
+impl<U> GenericTransform for Replace<U> {
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let outputs = &mut self.outputs;
+        match t.map_as(|_: U| {
+            let u = outputs
+                .next()
+                .expect("there is an output for each value collected");
+            (u, ())
+        }) {
+            (t, Some(())) => t,
+            (t, None) => t.map_one_transform(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cast;
+    use std::pin::pin;
+    use std::task::Waker;
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = pin!(f);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(x) = f.as_mut().poll(&mut cx) {
+                return x;
+            }
+        }
+    }
+
+    /// Resolves to its value after being polled once without resolving.
+    struct YieldOnce(u32, bool);
+
+    impl Future for YieldOnce {
+        type Output = u32;
+
+        fn poll(mut self: Pin<&mut Self>, _: &mut Context) -> Poll<u32> {
+            if self.1 {
+                Poll::Ready(self.0)
+            } else {
+                self.1 = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn everywhere_async() {
+        let mut calls = vec![];
+        {
+            let mut double = EverywhereAsync::new(|n: u32| {
+                calls.push(n);
+                future::ready(n * 2)
+            });
+            assert_eq!(block_on(double.transform((1u32, vec![2u32, 3]))), (2, vec![4, 6]));
+            assert_eq!(block_on(double.transform('a')), 'a');
+        }
+        assert_eq!(calls, vec![1, 2, 3]);
+
+        let mut pending = EverywhereAsync::new(|n: u32| YieldOnce(n + 1, false));
+        let mut f = pin!(pending.transform(vec![1u32, 2]));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(f.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(f.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(f.as_mut().poll(&mut cx), Poll::Ready(vec![2, 3]));
+
+        let mut lens = EverywhereAsync::new(|v: Vec<u32>| future::ready(vec![v.len() as u32]));
+        assert_eq!(block_on(lens.transform(vec![vec![5u32, 6]])), vec![vec![2]]);
+    }
+
+    /// Transforms every value into its default, after yielding once.
+    struct Reset;
+
+    impl AsyncGenericTransform<'static> for Reset {
+        fn transform<T>(&mut self, t: T) -> AsyncTransformed<'static, T>
+        where
+            T: 'static + Term,
+        {
+            match cast::<T, u32>(t) {
+                Ok(_) => Box::pin(CastBack {
+                    future: Box::pin(YieldOnce(0, false)),
+                    phantom: PhantomData,
+                }),
+                Err(t) => Box::pin(future::ready(t)),
+            }
+        }
+    }
+
+    #[test]
+    fn async_generic_transform() {
+        let mut inc = AsyncTransformation::new(|n: u32| future::ready(n + 1));
+        assert_eq!(block_on(inc.transform(1u32)), 2);
+        assert_eq!(block_on(inc.transform('a')), 'a');
+
+        let mut reset = EverywhereAsync::<_, u32>::from_generic(Reset);
+        assert_eq!(block_on(reset.transform((1u32, vec![2u32]))), (0, vec![0]));
+        let mut reset = EverywhereAsync::<_, Vec<u32>>::from_generic(Reset);
+        assert_eq!(block_on(reset.transform((1u32, vec![2u32]))), (1, vec![2]));
+    }
+}
@@ -0,0 +1,431 @@
+use super::{EverywhereResult, GenericQuery, GenericTransform, GenericTryTransform, Term};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A shared flag for asking traversals to stop early.
+///
+/// Clones of a token share the same flag, so a token can be handed to a
+/// traversal on one thread and cancelled from another, for example when an
+/// editor's user types again while a long analysis is still running.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Construct a new token that isn't cancelled yet.
+    #[inline]
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Ask every traversal using this token, or a clone of it, to stop.
+    #[inline]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Has this token been cancelled?
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// A traversal stopped early because its `CancelToken` was cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "traversal was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// A traversal stopped early because it ran out of fuel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OutOfFuel;
+
+impl fmt::Display for OutOfFuel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "traversal ran out of fuel")
+    }
+}
+
+impl Error for OutOfFuel {}
+
+/// Something a traversal spends a little of on each value, until it runs out.
+trait Budget {
+    type Error;
+
+    fn spend(&mut self) -> Result<(), Self::Error>;
+}
+
+impl Budget for CancelToken {
+    type Error = Cancelled;
+
+    #[inline]
+    fn spend(&mut self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Fuel, counted in values.
+impl Budget for usize {
+    type Error = OutOfFuel;
+
+    #[inline]
+    fn spend(&mut self) -> Result<(), OutOfFuel> {
+        match self.checked_sub(1) {
+            Some(fuel) => {
+                *self = fuel;
+                Ok(())
+            }
+            None => Err(OutOfFuel),
+        }
+    }
+}
+
+/// Applies a transformation while the budget lasts.
+#[derive(Debug)]
+struct Spend<F, B> {
+    f: F,
+    budget: B,
+}
+
+impl<F, B> GenericTryTransform<B::Error> for Spend<F, B>
+where
+    F: GenericTransform,
+    B: Budget,
+{
+    #[inline]
+    fn try_transform<T>(&mut self, t: T) -> Result<T, (T, B::Error)>
+    where
+        T: Term,
+    {
+        match self.budget.spend() {
+            Ok(()) => Ok(self.f.transform(t)),
+            Err(e) => Err((t, e)),
+        }
+    }
+}
+
+/// Queries and folds like `Everything` while the budget lasts, resulting in
+/// the first error once it runs out.
+struct SpendFold<'a, Q: 'a, R, F: 'a, B: 'a> {
+    q: &'a mut Q,
+    fold: &'a mut F,
+    budget: &'a mut B,
+    phantom: PhantomData<fn(R, R) -> R>,
+}
+
+impl<'a, Q, R, F, B> GenericQuery<Result<R, B::Error>> for SpendFold<'a, Q, R, F, B>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+    B: Budget,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> Result<R, B::Error>
+    where
+        T: Term,
+    {
+        self.budget.spend()?;
+        let mut r = Some(Ok(self.q.query(t)));
+        t.map_one_query(self, |me, rr| {
+            r = Some(match (r.take().unwrap(), rr) {
+                (Ok(r), Ok(rr)) => Ok((me.fold)(r, rr)),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            });
+        });
+        r.unwrap()
+    }
+}
+
+/// Recursively perform a transformation in a bottom up manner across a data
+/// structure, like `Everywhere`, stopping early if the given token is
+/// cancelled.
+///
+/// The token is checked before each value is transformed. Once it is found to
+/// be cancelled, nothing more is transformed or descended into, and the
+/// traversal returns `Cancelled` along with the data structure as transformed
+/// up to that point.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let token = CancelToken::new();
+/// let cancel = token.clone();
+/// let mut seen = 0;
+/// let tick = Transformation::new(|n: u32| {
+///     seen += 1;
+///     if seen == 2 {
+///         cancel.cancel();
+///     }
+///     n + 1
+/// });
+///
+/// let mut increment = EverywhereCancellable::new(tick, token);
+/// assert_eq!(increment.transform(vec![1u32, 2, 3]), Err((vec![2, 3, 3], Cancelled)));
+/// ```
+#[derive(Debug)]
+pub struct EverywhereCancellable<F>
+where
+    F: GenericTransform,
+{
+    everywhere: EverywhereResult<Spend<F, CancelToken>, Cancelled>,
+}
+
+impl<F> EverywhereCancellable<F>
+where
+    F: GenericTransform,
+{
+    /// Construct a new cancellable transformation traversal.
+    #[inline]
+    pub fn new(f: F, token: CancelToken) -> EverywhereCancellable<F> {
+        EverywhereCancellable {
+            everywhere: EverywhereResult::new(Spend { f, budget: token }),
+        }
+    }
+
+    /// Transform `t`, returning either the completely transformed value, or
+    /// `Cancelled` and the partially transformed value.
+    pub fn transform<T>(&mut self, t: T) -> Result<T, (T, Cancelled)>
+    where
+        T: Term,
+    {
+        self.everywhere.try_transform(t)
+    }
+}
+
+/// Recursively perform a query in a top-down, left-to-right manner across a
+/// data structure, like `Everything`, stopping early if the given token is
+/// cancelled.
+///
+/// The token is checked before each value is queried. Once it is found to be
+/// cancelled, nothing more is queried or descended into, and the traversal
+/// returns `Cancelled`.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let token = CancelToken::new();
+/// let mut sum = EverythingCancellable::new(Query::new(|n: &u32| *n), |a, b| a + b, token.clone());
+/// assert_eq!(sum.query(&vec![1u32, 2, 3]), Ok(6));
+///
+/// token.cancel();
+/// assert_eq!(sum.query(&vec![1u32, 2, 3]), Err(Cancelled));
+/// ```
+#[derive(Debug)]
+pub struct EverythingCancellable<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+{
+    q: Q,
+    fold: F,
+    token: CancelToken,
+    phantom: PhantomData<fn(R, R) -> R>,
+}
+
+impl<Q, R, F> EverythingCancellable<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+{
+    /// Construct a new cancellable query traversal.
+    #[inline]
+    pub fn new(q: Q, fold: F, token: CancelToken) -> EverythingCancellable<Q, R, F> {
+        EverythingCancellable {
+            q,
+            fold,
+            token,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Query `t`, returning either the folded result for all of it, or
+    /// `Cancelled`.
+    pub fn query<T>(&mut self, t: &T) -> Result<R, Cancelled>
+    where
+        T: Term,
+    {
+        SpendFold {
+            q: &mut self.q,
+            fold: &mut self.fold,
+            budget: &mut self.token,
+            phantom: PhantomData,
+        }.query(t)
+    }
+}
+
+/// Recursively perform a transformation in a bottom up manner across a data
+/// structure, like `Everywhere`, with a limited amount of fuel.
+///
+/// Transforming each value burns one unit of fuel, and fuel left over from one
+/// traversal carries over to the next. Once it runs out, nothing more is
+/// transformed or descended into, and the traversal returns `OutOfFuel` along
+/// with the data structure as transformed up to that point. This bounds how
+/// long traversing an untrusted or pathologically large input can take.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut increment = EverywhereFuel::new(Transformation::new(|n: u32| n + 1), 5);
+/// assert_eq!(increment.transform(vec![1u32, 2]), Ok(vec![2, 3]));
+/// assert_eq!(increment.fuel(), 2);
+/// assert_eq!(increment.transform(vec![1u32, 2, 3]), Err((vec![2, 3, 3], OutOfFuel)));
+/// ```
+#[derive(Debug)]
+pub struct EverywhereFuel<F>
+where
+    F: GenericTransform,
+{
+    everywhere: EverywhereResult<Spend<F, usize>, OutOfFuel>,
+}
+
+impl<F> EverywhereFuel<F>
+where
+    F: GenericTransform,
+{
+    /// Construct a new transformation traversal that may transform at most
+    /// `fuel` values.
+    #[inline]
+    pub fn new(f: F, fuel: usize) -> EverywhereFuel<F> {
+        EverywhereFuel {
+            everywhere: EverywhereResult::new(Spend { f, budget: fuel }),
+        }
+    }
+
+    /// Get how much fuel is left.
+    #[inline]
+    pub fn fuel(&self) -> usize {
+        self.everywhere.get_ref().budget
+    }
+
+    /// Transform `t`, returning either the completely transformed value, or
+    /// `OutOfFuel` and the partially transformed value.
+    pub fn transform<T>(&mut self, t: T) -> Result<T, (T, OutOfFuel)>
+    where
+        T: Term,
+    {
+        self.everywhere.try_transform(t)
+    }
+}
+
+/// Recursively perform a query in a top-down, left-to-right manner across a
+/// data structure, like `Everything`, with a limited amount of fuel.
+///
+/// Querying each value burns one unit of fuel, and fuel left over from one
+/// traversal carries over to the next. Once it runs out, nothing more is
+/// queried or descended into, and the traversal returns `OutOfFuel`.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut sum = EverythingFuel::new(Query::new(|n: &u32| *n), |a, b| a + b, 4);
+/// assert_eq!(sum.query(&vec![1u32, 2, 3]), Ok(6));
+/// assert_eq!(sum.query(&vec![1u32, 2, 3]), Err(OutOfFuel));
+/// assert_eq!(sum.fuel(), 0);
+/// ```
+#[derive(Debug)]
+pub struct EverythingFuel<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+{
+    q: Q,
+    fold: F,
+    fuel: usize,
+    phantom: PhantomData<fn(R, R) -> R>,
+}
+
+impl<Q, R, F> EverythingFuel<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+{
+    /// Construct a new query traversal that may query at most `fuel` values.
+    #[inline]
+    pub fn new(q: Q, fold: F, fuel: usize) -> EverythingFuel<Q, R, F> {
+        EverythingFuel {
+            q,
+            fold,
+            fuel,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get how much fuel is left.
+    #[inline]
+    pub fn fuel(&self) -> usize {
+        self.fuel
+    }
+
+    /// Query `t`, returning either the folded result for all of it, or
+    /// `OutOfFuel`.
+    pub fn query<T>(&mut self, t: &T) -> Result<R, OutOfFuel>
+    where
+        T: Term,
+    {
+        SpendFold {
+            q: &mut self.q,
+            fold: &mut self.fold,
+            budget: &mut self.fuel,
+            phantom: PhantomData,
+        }.query(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Query, Transformation};
+
+    #[test]
+    fn cancellable() {
+        let token = CancelToken::new();
+        let mut increment =
+            EverywhereCancellable::new(Transformation::new(|n: u32| n + 1), token.clone());
+        assert_eq!(increment.transform((1u32, vec![2u32])), Ok((2, vec![3])));
+
+        let mut queried = 0;
+        {
+            let cancel = token.clone();
+            let count = Query::new(|_: &u32| {
+                queried += 1;
+                if queried == 2 {
+                    cancel.cancel();
+                }
+                1
+            });
+            let mut count = EverythingCancellable::new(count, |a, b| a + b, token.clone());
+            assert_eq!(count.query(&vec![vec![1u32, 2], vec![3, 4]]), Err(Cancelled));
+        }
+        assert_eq!(queried, 2);
+
+        assert!(token.is_cancelled());
+        assert_eq!(increment.transform(vec![1u32]), Err((vec![1], Cancelled)));
+    }
+
+    #[test]
+    fn fuel() {
+        let mut increment = EverywhereFuel::new(Transformation::new(|n: u32| n + 1), 4);
+        assert_eq!(increment.transform((1u32, vec![2u32])), Ok((2, vec![3])));
+        assert_eq!(increment.fuel(), 0);
+        assert_eq!(increment.transform(1u32), Err((1, OutOfFuel)));
+
+        let mut count = EverythingFuel::new(Query::new(|_: &u32| 1), |a, b| a + b, 3);
+        assert_eq!(count.query(&vec![vec![1u32, 2], vec![3]]), Err(OutOfFuel));
+        assert_eq!(count.fuel(), 0);
+    }
+}
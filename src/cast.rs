@@ -0,0 +1,136 @@
+use super::{Cast, CastThrough, SameType};
+
+/// Is `T` the same type as `U`?
+#[inline(always)]
+pub fn same_type<T, U>() -> bool
+where
+    T: 'static,
+    U: 'static,
+{
+    <T as SameType<U>>::same_type()
+}
+
+/// Cast a value to a `U`, if it is one, or give it back if not.
+///
+/// This is the check that typed transformations use, exposed for writing
+/// generic code outside this crate. Unlike downcasting with `Any`, the check is
+/// resolved at compile time, so it costs nothing at run time. A `Leaf<U>` casts
+/// to the `U` inside it, and a `U` casts into a `Leaf<U>`. Both types must be
+/// `'static`, so that a cast can never change a value's lifetimes.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// /// Double `t` if it is a `u32`, and leave it alone otherwise.
+/// fn double<T: 'static>(t: T) -> T {
+///     match cast::<T, u32>(t) {
+///         Ok(n) => cast::<u32, T>(n * 2).unwrap_or_else(|_| unreachable!()),
+///         Err(t) => t,
+///     }
+/// }
+///
+/// assert_eq!(double(2u32), 4);
+/// assert_eq!(double('a'), 'a');
+/// ```
+#[inline(always)]
+pub fn cast<T, U>(t: T) -> Result<U, T>
+where
+    T: 'static,
+    U: 'static,
+{
+    Cast::<U>::cast(t)
+}
+
+/// Get a reference to a value as a `U`, if it is one.
+#[inline(always)]
+pub fn cast_ref<T, U>(t: &T) -> Option<&U>
+where
+    T: 'static,
+    U: 'static,
+{
+    Cast::<&U>::cast(t).ok()
+}
+
+/// Get a mutable reference to a value as a `U`, if it is one.
+#[inline(always)]
+pub fn cast_mut<T, U>(t: &mut T) -> Option<&mut U>
+where
+    T: 'static,
+    U: 'static,
+{
+    Cast::<&mut U>::cast(t).ok()
+}
+
+/// Get a reference to the `U` a value is, or is wrapped in by `Transparent`
+/// wrappers, the same way typed queries decide whether they apply to a value.
+#[inline(always)]
+pub fn cast_through_ref<T, U>(t: &T) -> Option<&U>
+where
+    T: 'static,
+    U: 'static,
+{
+    CastThrough::<U>::view_as(t)
+}
+
+/// Get a mutable reference to the `U` a value is, or is wrapped in by
+/// `Transparent` wrappers, the same way typed mutations decide whether they
+/// apply to a value.
+#[inline(always)]
+pub fn cast_through_mut<T, U>(t: &mut T) -> Option<&mut U>
+where
+    T: 'static,
+    U: 'static,
+{
+    CastThrough::<U>::view_as_mut(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Leaf, Transparent};
+
+    #[derive(Debug, PartialEq)]
+    struct Wrapper(u32);
+    impl_term!(Wrapper { 0 });
+
+    impl Transparent for Wrapper {
+        type Inner = u32;
+
+        fn inner(&self) -> &u32 {
+            &self.0
+        }
+
+        fn inner_mut(&mut self) -> &mut u32 {
+            &mut self.0
+        }
+
+        fn map_inner<F: FnOnce(u32) -> u32>(self, f: F) -> Wrapper {
+            Wrapper(f(self.0))
+        }
+    }
+
+    #[test]
+    fn casts() {
+        assert!(same_type::<u32, u32>());
+        assert!(!same_type::<u32, u64>());
+
+        assert_eq!(cast::<u32, u32>(1), Ok(1));
+        assert_eq!(cast::<u32, u64>(1), Err(1));
+        assert_eq!(cast_ref::<_, char>(&'a'), Some(&'a'));
+        assert_eq!(cast_ref::<_, u32>(&'a'), None);
+
+        let mut n = 1u32;
+        *cast_mut::<_, u32>(&mut n).unwrap() += 1;
+        assert_eq!(cast_mut::<_, char>(&mut n), None);
+        assert_eq!(n, 2);
+
+        assert_eq!(cast::<_, Leaf<u32>>(3u32), Ok(Leaf(3)));
+        assert_eq!(cast_ref::<_, u32>(&Leaf(3u32)), Some(&3));
+
+        let mut wrapper = Wrapper(3);
+        assert_eq!(cast_ref::<_, u32>(&wrapper), None);
+        assert_eq!(cast_through_ref::<_, u32>(&wrapper), Some(&3));
+        *cast_through_mut::<_, u32>(&mut wrapper).unwrap() += 1;
+        assert_eq!(wrapper, Wrapper(4));
+    }
+}
@@ -0,0 +1,1016 @@
+use super::dispatch::erased_type_id;
+use super::{CastThrough, GenericQuery, Term};
+use std::any::{self, TypeId};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+use std::iter::{self, Product, Sum};
+use std::marker::PhantomData;
+use std::ops::{Add, ControlFlow, Mul};
+
+/// Calls a function on every `U` within a data structure, top down and left to
+/// right, until it breaks.
+struct Visit<F, U, B>
+where
+    F: FnMut(&U) -> ControlFlow<B>,
+{
+    f: F,
+    broke: Option<B>,
+    phantom: PhantomData<fn(&U)>,
+}
+
+impl<F, U, B> GenericQuery<()> for Visit<F, U, B>
+where
+    F: FnMut(&U) -> ControlFlow<B>,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T)
+    where
+        T: Term,
+    {
+        if self.broke.is_some() {
+            return;
+        }
+        if let Some(u) = t.view_as() {
+            if let ControlFlow::Break(b) = (self.f)(u) {
+                self.broke = Some(b);
+                return;
+            }
+        }
+        t.map_one_query(self, |_, _| {});
+    }
+}
+
+/// Visit every `U` within `t`, returning the value it broke with, if any.
+#[inline]
+fn visit<T, U, B, F>(t: &T, f: F) -> Option<B>
+where
+    T: Term,
+    F: FnMut(&U) -> ControlFlow<B>,
+{
+    let mut visit = Visit {
+        f,
+        broke: None,
+        phantom: PhantomData,
+    };
+    visit.query(t);
+    visit.broke
+}
+
+/// The order in which to visit the values within a data structure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Order {
+    /// Top down and left to right: each value before its children, and its
+    /// children from first to last. This is the order `Everything` uses.
+    DepthFirst,
+    /// Level by level: every value on one level, from first to last, before any
+    /// value on the next level, like `BreadthFirst`.
+    ///
+    /// Each level is reached by walking down from the root again, so this takes
+    /// time proportional to the size of the data structure times its depth.
+    BreadthFirst,
+}
+
+/// Calls a function on every `U` on one level of a data structure, until it
+/// breaks, and records whether there are any values below that level.
+struct VisitLevel<'a, F: 'a, U, B>
+where
+    F: FnMut(&U) -> ControlFlow<B>,
+{
+    f: &'a mut F,
+    target: usize,
+    depth: usize,
+    deeper: bool,
+    broke: Option<B>,
+    phantom: PhantomData<fn(&U)>,
+}
+
+impl<'a, F, U, B> GenericQuery<()> for VisitLevel<'a, F, U, B>
+where
+    F: FnMut(&U) -> ControlFlow<B>,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T)
+    where
+        T: Term,
+    {
+        if self.broke.is_some() {
+            return;
+        }
+        if self.depth > self.target {
+            self.deeper = true;
+            return;
+        }
+        if self.depth == self.target {
+            if let Some(u) = t.view_as() {
+                if let ControlFlow::Break(b) = (self.f)(u) {
+                    self.broke = Some(b);
+                    return;
+                }
+            }
+        }
+        self.depth += 1;
+        t.map_one_query(self, |_, _| {});
+        self.depth -= 1;
+    }
+}
+
+/// Visit every `U` within `t` in the given order, returning the value it broke
+/// with, if any.
+fn visit_in<T, U, B, F>(order: Order, t: &T, mut f: F) -> Option<B>
+where
+    T: Term,
+    F: FnMut(&U) -> ControlFlow<B>,
+{
+    if order == Order::DepthFirst {
+        return visit(t, f);
+    }
+    let mut level = VisitLevel {
+        f: &mut f,
+        target: 0,
+        depth: 0,
+        deeper: true,
+        broke: None,
+        phantom: PhantomData,
+    };
+    while level.deeper && level.broke.is_none() {
+        level.deeper = false;
+        level.query(t);
+        level.target += 1;
+    }
+    level.broke
+}
+
+/// Call `f` on every value of type `U` within `t`, including `t` itself, in top
+/// down, left to right order.
+#[inline]
+pub fn for_each_term<U, T, F>(t: &T, mut f: F)
+where
+    T: Term,
+    F: FnMut(&U),
+{
+    visit(t, |u: &U| {
+        f(u);
+        ControlFlow::<()>::Continue(())
+    });
+}
+
+/// Call `sink` on every value of type `U` within `t` for which `pred` returns
+/// true, in top down, left to right order, as each one is found.
+///
+/// Nothing is collected, so memory use stays flat however many values match.
+/// To stream the matches elsewhere, send them down a channel from the sink:
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::sync::mpsc;
+///
+/// let (tx, rx) = mpsc::channel();
+/// let value = vec![(1u32, 'a'), (20u32, 'b'), (30u32, 'c')];
+/// for_each_where(&value, |n: &u32| *n > 10, |n| tx.send(*n).unwrap());
+/// drop(tx);
+/// assert_eq!(rx.iter().collect::<Vec<_>>(), vec![20, 30]);
+/// ```
+#[inline]
+pub fn for_each_where<U, T, P, S>(t: &T, mut pred: P, mut sink: S)
+where
+    T: Term,
+    P: FnMut(&U) -> bool,
+    S: FnMut(&U),
+{
+    visit(t, |u: &U| {
+        if pred(u) {
+            sink(u);
+        }
+        ControlFlow::<()>::Continue(())
+    });
+}
+
+/// Call `f` on every value of type `U` within `t`, including `t` itself, in top
+/// down, left to right order, until it breaks.
+///
+/// This is the internal iteration counterpart of an iterator over the values,
+/// stopping the traversal as soon as the consumer is done with it. There is no
+/// lazy `Iterator` of references to the values, since `Term` only lends a
+/// value's children to the traversal for the duration of a call, and can't be
+/// suspended partway through one.
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::ops::ControlFlow;
+///
+/// let value = vec![1u32, 2, 3, 4];
+/// let mut sum = 0;
+/// let flow = try_for_each_term(&value, |n: &u32| {
+///     sum += n;
+///     if sum > 2 { ControlFlow::Break(*n) } else { ControlFlow::Continue(()) }
+/// });
+/// assert_eq!(flow, ControlFlow::Break(2));
+/// ```
+#[inline]
+pub fn try_for_each_term<U, T, B, F>(t: &T, f: F) -> ControlFlow<B>
+where
+    T: Term,
+    F: FnMut(&U) -> ControlFlow<B>,
+{
+    match visit(t, f) {
+        Some(b) => ControlFlow::Break(b),
+        None => ControlFlow::Continue(()),
+    }
+}
+
+/// Call `f` on every value of type `U` within `t`, including `t` itself, in the
+/// given order, until it breaks.
+///
+/// This is `try_for_each_term`, but can visit the values breadth first too.
+#[inline]
+pub fn try_for_each_term_in<U, T, B, F>(order: Order, t: &T, f: F) -> ControlFlow<B>
+where
+    T: Term,
+    F: FnMut(&U) -> ControlFlow<B>,
+{
+    match visit_in(order, t, f) {
+        Some(b) => ControlFlow::Break(b),
+        None => ControlFlow::Continue(()),
+    }
+}
+
+/// Fold every value of type `U` within `t`, including `t` itself, into an
+/// accumulator, in top down, left to right order.
+///
+/// Like `Iterator::fold`, this starts with `init` and replaces the accumulator
+/// with the result of calling `f` with it and each value in turn.
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::collections::BTreeMap;
+///
+/// let value = (vec!['a', 'b'], ('a', 3u32));
+/// let counts = fold_terms(&value, BTreeMap::new(), |mut counts, c: &char| {
+///     *counts.entry(*c).or_insert(0) += 1;
+///     counts
+/// });
+/// assert_eq!(counts.into_iter().collect::<Vec<_>>(), vec![('a', 2), ('b', 1)]);
+/// ```
+pub fn fold_terms<U, T, A, F>(t: &T, init: A, mut f: F) -> A
+where
+    T: Term,
+    F: FnMut(A, &U) -> A,
+{
+    let mut acc = Some(init);
+    visit(t, |u: &U| {
+        acc = Some(f(acc.take().unwrap(), u));
+        ControlFlow::<()>::Continue(())
+    });
+    acc.unwrap()
+}
+
+/// Collect a clone of every value of type `U` within `t`, including `t` itself,
+/// in top down, left to right order.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (vec![1u32, 2], ('a', 3u32));
+/// assert_eq!(collect_all::<u32, _>(&value), vec![1, 2, 3]);
+/// ```
+#[inline]
+pub fn collect_all<U, T>(t: &T) -> Vec<U>
+where
+    T: Term,
+    U: Clone,
+{
+    collect_all_where(t, |_: &U| true)
+}
+
+/// Collect a clone of every value of type `U` within `t` for which `pred`
+/// returns true, in top down, left to right order.
+pub fn collect_all_where<U, T, P>(t: &T, mut pred: P) -> Vec<U>
+where
+    T: Term,
+    U: Clone,
+    P: FnMut(&U) -> bool,
+{
+    let mut all = vec![];
+    visit(t, |u: &U| {
+        if pred(u) {
+            all.push(u.clone());
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    all
+}
+
+/// Collect a clone of each distinct value of type `U` within `t`, including `t`
+/// itself, into a `HashSet`.
+///
+/// Values are only cloned the first time they are seen.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (vec!["debug", "trace"], ("debug", 3u32));
+/// let flags = collect_hash_set::<&str, _>(&value);
+/// assert_eq!(flags.len(), 2);
+/// assert!(flags.contains("debug") && flags.contains("trace"));
+/// ```
+pub fn collect_hash_set<U, T>(t: &T) -> HashSet<U>
+where
+    T: Term,
+    U: Clone + Eq + Hash,
+{
+    let mut set = HashSet::new();
+    visit(t, |u: &U| {
+        if !set.contains(u) {
+            set.insert(u.clone());
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    set
+}
+
+/// Collect a clone of each distinct value of type `U` within `t`, including `t`
+/// itself, into a `BTreeSet`.
+///
+/// Values are only cloned the first time they are seen.
+pub fn collect_btree_set<U, T>(t: &T) -> BTreeSet<U>
+where
+    T: Term,
+    U: Clone + Ord,
+{
+    let mut set = BTreeSet::new();
+    visit(t, |u: &U| {
+        if !set.contains(u) {
+            set.insert(u.clone());
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    set
+}
+
+/// Collect a clone of every outermost value of type `U` within `t`, in top
+/// down, left to right order, without descending into the values collected.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = vec![Box::new(Box::new(1u32)), Box::new(Box::new(2u32))];
+/// let boxes = collect_outermost_where(&value, |b: &Box<Box<u32>>| **b != Box::new(2));
+/// assert_eq!(boxes, vec![Box::new(Box::new(1))]);
+/// assert_eq!(collect_outermost::<Vec<Box<Box<u32>>>, _>(&value), vec![value]);
+/// ```
+#[inline]
+pub fn collect_outermost<U, T>(t: &T) -> Vec<U>
+where
+    T: Term,
+    U: Clone,
+{
+    collect_outermost_where(t, |_: &U| true)
+}
+
+/// Collect a clone of every outermost value of type `U` within `t` for which
+/// `pred` returns true, in top down, left to right order, without descending
+/// into the values collected.
+///
+/// Values for which `pred` returns false are still descended into, so this
+/// collects the matches that aren't nested inside another match.
+pub fn collect_outermost_where<U, T, P>(t: &T, pred: P) -> Vec<U>
+where
+    T: Term,
+    U: Clone,
+    P: FnMut(&U) -> bool,
+{
+    let mut outermost = Outermost {
+        pred,
+        all: vec![],
+    };
+    outermost.query(t);
+    outermost.all
+}
+
+/// Collects every `U` matching a predicate, but not those within another one.
+struct Outermost<P, U>
+where
+    P: FnMut(&U) -> bool,
+{
+    pred: P,
+    all: Vec<U>,
+}
+
+impl<P, U> GenericQuery<()> for Outermost<P, U>
+where
+    P: FnMut(&U) -> bool,
+    U: Clone,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T)
+    where
+        T: Term,
+    {
+        if let Some(u) = t.view_as() {
+            if (self.pred)(u) {
+                self.all.push(u.clone());
+                return;
+            }
+        }
+        t.map_one_query(self, |_, _| {});
+    }
+}
+
+/// Count the values of type `U` within `t`, including `t` itself.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (vec![1u32, 2], ('a', 3u32));
+/// assert_eq!(count_all::<u32, _>(&value), 3);
+/// ```
+#[inline]
+pub fn count_all<U, T>(t: &T) -> usize
+where
+    T: Term,
+{
+    count_where(t, |_: &U| true)
+}
+
+/// Count the values of type `U` within `t` for which `pred` returns true.
+///
+/// Unlike taking the length of `collect_all_where`, this doesn't allocate.
+pub fn count_where<U, T, P>(t: &T, mut pred: P) -> usize
+where
+    T: Term,
+    P: FnMut(&U) -> bool,
+{
+    let mut count = 0;
+    visit(t, |u: &U| {
+        if pred(u) {
+            count += 1;
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    count
+}
+
+/// Does `pred` return true for any value of type `U` within `t`?
+///
+/// This stops traversing at the first value for which it does.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (vec!["a", "b"], 'c');
+/// assert!(any(&value, |s: &&str| *s == "b"));
+/// assert!(!any(&value, |c: &char| c.is_uppercase()));
+/// ```
+#[inline]
+pub fn any<U, T, P>(t: &T, mut pred: P) -> bool
+where
+    T: Term,
+    P: FnMut(&U) -> bool,
+{
+    let found = visit(t, |u: &U| {
+        if pred(u) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    found.is_some()
+}
+
+/// Does `pred` return true for every value of type `U` within `t`?
+///
+/// This stops traversing at the first value for which it doesn't. It is
+/// trivially true when there are no values of type `U`.
+#[inline]
+pub fn all<U, T, P>(t: &T, mut pred: P) -> bool
+where
+    T: Term,
+    P: FnMut(&U) -> bool,
+{
+    !any(t, |u: &U| !pred(u))
+}
+
+/// Find a clone of the first value of type `U` within `t`, in top down, left to
+/// right order, for which `pred` returns true.
+///
+/// This stops traversing as soon as it finds one, so values are visited in the
+/// same order as `Everything` queries them: each value before its children,
+/// and its children from first to last.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (vec![1u32, 20], ('a', 30u32));
+/// assert_eq!(find_first(&value, |n: &u32| *n > 10), Some(20));
+/// ```
+#[inline]
+pub fn find_first<U, T, P>(t: &T, mut pred: P) -> Option<U>
+where
+    T: Term,
+    U: Clone,
+    P: FnMut(&U) -> bool,
+{
+    visit(t, |u: &U| {
+        if pred(u) {
+            ControlFlow::Break(u.clone())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })
+}
+
+/// Sum `f` of every value of type `U` within `t`, including `t` itself.
+///
+/// This is zero if there are no values of type `U`.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let costs = vec![("parse", 3u32), ("check", 4u32)];
+/// assert_eq!(sum_of(&costs, |cost: &u32| u64::from(*cost)), 7u64);
+/// assert_eq!(product_of(&costs, |cost: &u32| *cost), 12);
+/// assert_eq!(mean_of(&costs, |cost: &u32| f64::from(*cost)), Some(3.5));
+/// ```
+#[inline]
+pub fn sum_of<U, T, N, F>(t: &T, mut f: F) -> N
+where
+    T: Term,
+    N: Sum + Add<Output = N>,
+    F: FnMut(&U) -> N,
+{
+    fold_terms(t, iter::empty::<N>().sum(), |sum, u: &U| sum + f(u))
+}
+
+/// Multiply `f` of every value of type `U` within `t`, including `t` itself.
+///
+/// This is one if there are no values of type `U`.
+#[inline]
+pub fn product_of<U, T, N, F>(t: &T, mut f: F) -> N
+where
+    T: Term,
+    N: Product + Mul<Output = N>,
+    F: FnMut(&U) -> N,
+{
+    fold_terms(t, iter::empty::<N>().product(), |product, u: &U| product * f(u))
+}
+
+/// Average `f` of every value of type `U` within `t`, including `t` itself.
+///
+/// This is `None` if there are no values of type `U`.
+pub fn mean_of<U, T, F>(t: &T, mut f: F) -> Option<f64>
+where
+    T: Term,
+    F: FnMut(&U) -> f64,
+{
+    let (count, sum) = fold_terms(t, (0usize, 0.0), |(count, sum), u: &U| {
+        (count + 1, sum + f(u))
+    });
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f64)
+    }
+}
+
+/// Find a clone of the value of type `U` within `t` for which `key` returns the
+/// greatest key, in a single traversal.
+///
+/// Like `Iterator::max_by_key`, this returns the last such value, in top down,
+/// left to right order, if several have the greatest key.
+#[inline]
+pub fn max_by_key<U, T, K, F>(t: &T, key: F) -> Option<U>
+where
+    T: Term,
+    U: Clone,
+    K: Ord,
+    F: FnMut(&U) -> K,
+{
+    extremum(t, key, |new, best| new >= best)
+}
+
+/// Find a clone of the value of type `U` within `t` for which `key` returns the
+/// least key, in a single traversal.
+///
+/// Like `Iterator::min_by_key`, this returns the first such value, in top
+/// down, left to right order, if several have the least key.
+#[inline]
+pub fn min_by_key<U, T, K, F>(t: &T, key: F) -> Option<U>
+where
+    T: Term,
+    U: Clone,
+    K: Ord,
+    F: FnMut(&U) -> K,
+{
+    extremum(t, key, |new, best| new < best)
+}
+
+/// Find a clone of the value of type `U` within `t` whose key is `better` than
+/// that of every value before it.
+fn extremum<U, T, K, F, B>(t: &T, mut key: F, mut better: B) -> Option<U>
+where
+    T: Term,
+    U: Clone,
+    F: FnMut(&U) -> K,
+    B: FnMut(&K, &K) -> bool,
+{
+    let mut best: Option<(K, U)> = None;
+    visit(t, |u: &U| {
+        let k = key(u);
+        if best.as_ref().is_none_or(|(best, _)| better(&k, best)) {
+            best = Some((k, u.clone()));
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    best.map(|(_, u)| u)
+}
+
+/// Count how many values of each type a traversal of `t` visits, including `t`
+/// itself, keyed by `TypeId`, along with the type's name for display.
+///
+/// This is useful for seeing what a traversal actually touches. A type's
+/// lifetimes are ignored, as they are when deciding whether a typed
+/// transformation or query applies to a value, so `&'a str`s are counted as
+/// `&'static str`s.
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::any::TypeId;
+///
+/// let census = census(&vec![(1u32, 'a'), (2u32, 'b')]);
+/// assert_eq!(census[&TypeId::of::<u32>()], ("u32", 2));
+/// assert_eq!(census[&TypeId::of::<char>()], ("char", 2));
+/// assert_eq!(census.len(), 4);
+/// ```
+pub fn census<T>(t: &T) -> HashMap<TypeId, (&'static str, usize)>
+where
+    T: Term,
+{
+    let mut census = Census(HashMap::new());
+    census.query(t);
+    census.0
+}
+
+/// Counts the values of each type it visits.
+struct Census(HashMap<TypeId, (&'static str, usize)>);
+
+impl GenericQuery<()> for Census {
+    #[inline]
+    fn query<T>(&mut self, t: &T)
+    where
+        T: Term,
+    {
+        self.0
+            .entry(erased_type_id::<T>())
+            .or_insert((any::type_name::<T>(), 0))
+            .1 += 1;
+        t.map_one_query(self, |_, _| {});
+    }
+}
+
+/// Sample `k` values of type `U` within `t` uniformly at random, in a single
+/// pass, with reservoir sampling.
+///
+/// Bring your own source of randomness: `random_below(n)` must return a
+/// uniformly random number below `n`. If there are `k` or fewer values of type
+/// `U`, this returns clones of all of them, in top down, left to right order.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// // A small linear congruential generator, for the sake of the example.
+/// let mut state = 42u64;
+/// let mut random_below = |n: usize| {
+///     state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+///     (state >> 33) as usize % n
+/// };
+///
+/// let value: Vec<u32> = (0..1000).collect();
+/// let sample = sample_terms::<u32, _, _>(&value, 10, &mut random_below);
+/// assert_eq!(sample.len(), 10);
+/// assert!(sample.iter().all(|n| *n < 1000));
+/// ```
+pub fn sample_terms<U, T, R>(t: &T, k: usize, mut random_below: R) -> Vec<U>
+where
+    T: Term,
+    U: Clone,
+    R: FnMut(usize) -> usize,
+{
+    let mut reservoir = Vec::with_capacity(k);
+    let mut seen = 0;
+    visit(t, |u: &U| {
+        seen += 1;
+        if reservoir.len() < k {
+            reservoir.push(u.clone());
+        } else {
+            let j = random_below(seen);
+            if j < k {
+                reservoir[j] = u.clone();
+            }
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    reservoir
+}
+
+/// Find a clone of the `n`th value of type `U` within `t`, counting from zero,
+/// in top down, left to right order, for which `pred` returns true.
+///
+/// This stops traversing as soon as it finds it, so stepping through the
+/// matches one at a time doesn't need them all collected first.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let notes = vec!["TODO: a", "done", "TODO: b", "TODO: c"];
+/// let todo = |note: &&str| note.starts_with("TODO");
+/// assert_eq!(find_nth(&notes, 1, todo), Some("TODO: b"));
+/// assert_eq!(find_nth(&notes, 3, todo), None);
+/// ```
+pub fn find_nth<U, T, P>(t: &T, n: usize, mut pred: P) -> Option<U>
+where
+    T: Term,
+    U: Clone,
+    P: FnMut(&U) -> bool,
+{
+    let mut seen = 0;
+    find_first(t, |u: &U| {
+        if !pred(u) {
+            return false;
+        }
+        seen += 1;
+        seen > n
+    })
+}
+
+/// Find a clone of the first value of type `U` within `t`, in the given order,
+/// for which `pred` returns true.
+///
+/// With `Order::BreadthFirst`, this finds the match closest to the root.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (vec![1u32], 2u32);
+/// assert_eq!(find_first_in(Order::DepthFirst, &value, |_: &u32| true), Some(1));
+/// assert_eq!(find_first_in(Order::BreadthFirst, &value, |_: &u32| true), Some(2));
+/// ```
+#[inline]
+pub fn find_first_in<U, T, P>(order: Order, t: &T, mut pred: P) -> Option<U>
+where
+    T: Term,
+    U: Clone,
+    P: FnMut(&U) -> bool,
+{
+    visit_in(order, t, |u: &U| {
+        if pred(u) {
+            ControlFlow::Break(u.clone())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })
+}
+
+/// Collect a clone of every value of type `U` within `t` for which `pred`
+/// returns true, in the given order.
+pub fn collect_all_in<U, T, P>(order: Order, t: &T, mut pred: P) -> Vec<U>
+where
+    T: Term,
+    U: Clone,
+    P: FnMut(&U) -> bool,
+{
+    let mut all = vec![];
+    visit_in(order, t, |u: &U| {
+        if pred(u) {
+            all.push(u.clone());
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Expr {
+        Var(&'static str),
+        Let(&'static str, Box<Expr>, Box<Expr>),
+    }
+    impl_term!(enum Expr {
+        Var(name),
+        Let(name, value, body),
+    });
+
+    #[test]
+    fn collect_all() {
+        let var = Expr::Var;
+        let expr = Expr::Let("x", Box::new(var("y")), Box::new(var("x")));
+
+        let names = super::collect_all::<&str, _>(&expr);
+        assert_eq!(names, vec!["x", "y", "x"]);
+
+        let vars = collect_all_where(&expr, |e: &Expr| matches!(*e, Expr::Var(_)));
+        assert_eq!(vars, vec![var("y"), var("x")]);
+    }
+
+    #[test]
+    fn count() {
+        let expr = Expr::Let("x", Box::new(Expr::Var("y")), Box::new(Expr::Var("x")));
+        assert_eq!(count_all::<Expr, _>(&expr), 3);
+        assert_eq!(count_where(&expr, |name: &&str| *name == "x"), 2);
+        assert_eq!(count_all::<u32, _>(&expr), 0);
+    }
+
+    #[test]
+    fn any_and_all() {
+        let value = vec![1u32, 2, 3, 4];
+
+        let mut visited = 0;
+        assert!(any(&value, |n: &u32| {
+            visited += 1;
+            *n == 2
+        }));
+        assert_eq!(visited, 2);
+
+        let mut visited = 0;
+        assert!(!all(&value, |n: &u32| {
+            visited += 1;
+            *n < 3
+        }));
+        assert_eq!(visited, 3);
+
+        assert!(!any(&value, |n: &u32| *n > 4));
+        assert!(all(&value, |n: &u32| *n > 0));
+        assert!(all(&value, |_: &char| false));
+    }
+
+    #[test]
+    fn find_first() {
+        let expr = Expr::Let("x", Box::new(Expr::Var("y")), Box::new(Expr::Var("z")));
+        assert_eq!(
+            super::find_first(&expr, |e: &Expr| matches!(*e, Expr::Var(_))),
+            Some(Expr::Var("y"))
+        );
+        assert_eq!(super::find_first(&expr, |name: &&str| *name > "x"), Some("y"));
+        assert_eq!(super::find_first(&expr, |name: &&str| name.is_empty()), None);
+    }
+
+    #[test]
+    fn max_and_min_by_key() {
+        let value = vec![(3u32, 'a'), (1u32, 'b'), (3u32, 'c'), (1u32, 'd')];
+        let first = |pair: &(u32, char)| pair.0;
+        assert_eq!(max_by_key(&value, first), Some((3, 'c')));
+        assert_eq!(min_by_key(&value, first), Some((1, 'b')));
+        assert_eq!(max_by_key(&value, |n: &u32| *n), Some(3));
+        assert_eq!(min_by_key(&value, |_: &bool| 0), None::<bool>);
+    }
+
+    #[test]
+    fn for_each_term() {
+        let expr = Expr::Let("x", Box::new(Expr::Var("y")), Box::new(Expr::Var("z")));
+
+        let mut names = vec![];
+        super::for_each_term(&expr, |name: &&str| names.push(*name));
+        assert_eq!(names, vec!["x", "y", "z"]);
+
+        let mut names = vec![];
+        let flow = try_for_each_term(&expr, |name: &&str| {
+            names.push(*name);
+            if names.len() == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(flow, ControlFlow::Break(()));
+        assert_eq!(names, vec!["x", "y"]);
+    }
+
+    #[test]
+    fn fold_terms() {
+        let expr = Expr::Let("x", Box::new(Expr::Var("y")), Box::new(Expr::Var("x")));
+        let bound = super::fold_terms(&expr, vec![], |mut bound, e: &Expr| {
+            if let Expr::Let(name, _, _) = *e {
+                bound.push(name);
+            }
+            bound
+        });
+        assert_eq!(bound, vec!["x"]);
+        assert_eq!(super::fold_terms(&expr, 0, |n, _: &u32| n + 1), 0);
+    }
+
+    #[test]
+    fn census() {
+        let expr = Expr::Let("x", Box::new(Expr::Var("y")), Box::new(Expr::Var("x")));
+        let census = super::census(&expr);
+        assert_eq!(census[&TypeId::of::<Expr>()], (any::type_name::<Expr>(), 3));
+        assert_eq!(census[&TypeId::of::<Box<Expr>>()].1, 2);
+        assert_eq!(census[&TypeId::of::<&str>()], ("&str", 3));
+        assert_eq!(census.values().map(|&(_, n)| n).sum::<usize>(), 8);
+    }
+
+    #[test]
+    fn collect_outermost() {
+        let expr = Expr::Let(
+            "x",
+            Box::new(Expr::Let("y", Box::new(Expr::Var("z")), Box::new(Expr::Var("y")))),
+            Box::new(Expr::Var("x")),
+        );
+        let is_let = |e: &Expr| matches!(*e, Expr::Let(..));
+
+        assert_eq!(collect_outermost_where(&expr, is_let), vec![expr.clone()]);
+        let Expr::Let(_, value, body) = expr.clone() else {
+            unreachable!()
+        };
+        let lets = collect_outermost_where(&(*value.clone(), *body.clone()), is_let);
+        assert_eq!(lets, vec![*value]);
+        assert_eq!(super::collect_outermost::<Expr, _>(&*body), vec![*body]);
+    }
+
+    #[test]
+    fn breadth_first() {
+        let expr = Expr::Let(
+            "x",
+            Box::new(Expr::Let("y", Box::new(Expr::Var("z")), Box::new(Expr::Var("y")))),
+            Box::new(Expr::Var("w")),
+        );
+
+        let names = collect_all_in(Order::DepthFirst, &expr, |_: &&str| true);
+        assert_eq!(names, vec!["x", "y", "z", "y", "w"]);
+        let names = collect_all_in(Order::BreadthFirst, &expr, |_: &&str| true);
+        assert_eq!(names, vec!["x", "y", "w", "z", "y"]);
+
+        let is_var = |e: &Expr| matches!(*e, Expr::Var(_));
+        assert_eq!(find_first_in(Order::DepthFirst, &expr, is_var), Some(Expr::Var("z")));
+        assert_eq!(find_first_in(Order::BreadthFirst, &expr, is_var), Some(Expr::Var("w")));
+
+        let mut visited = 0;
+        let flow = try_for_each_term_in(Order::BreadthFirst, &expr, |_: &&str| {
+            visited += 1;
+            if visited == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(flow, ControlFlow::Break(()));
+        assert_eq!(visited, 2);
+    }
+
+    #[test]
+    fn collect_sets() {
+        let expr = Expr::Let("x", Box::new(Expr::Var("y")), Box::new(Expr::Var("x")));
+        let names = collect_btree_set::<&str, _>(&expr);
+        assert_eq!(names.into_iter().collect::<Vec<_>>(), vec!["x", "y"]);
+        let names = collect_hash_set::<&str, _>(&expr);
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn for_each_where() {
+        let expr = Expr::Let("x", Box::new(Expr::Var("y")), Box::new(Expr::Var("x")));
+        let mut xs = 0;
+        super::for_each_where(&expr, |name: &&str| *name == "x", |_| xs += 1);
+        assert_eq!(xs, 2);
+    }
+
+    #[test]
+    fn numeric_reductions() {
+        let value = vec![1u32, 2, 3, 4];
+        assert_eq!(sum_of(&value, |n: &u32| *n), 10);
+        assert_eq!(product_of(&value, |n: &u32| *n), 24);
+        assert_eq!(mean_of(&value, |n: &u32| f64::from(*n)), Some(2.5));
+
+        assert_eq!(sum_of(&value, |_: &char| 1), 0);
+        assert_eq!(product_of(&value, |_: &char| 2), 1);
+        assert_eq!(mean_of(&value, |_: &char| 1.0), None);
+    }
+
+    #[test]
+    fn find_nth() {
+        let expr = Expr::Let("x", Box::new(Expr::Var("y")), Box::new(Expr::Var("z")));
+        assert_eq!(super::find_nth(&expr, 0, |_: &&str| true), Some("x"));
+        assert_eq!(super::find_nth(&expr, 2, |_: &&str| true), Some("z"));
+        assert_eq!(super::find_nth(&expr, 3, |_: &&str| true), None);
+        assert_eq!(super::find_nth(&expr, 1, |name: &&str| *name != "y"), Some("z"));
+    }
+
+    #[test]
+    fn sample_terms() {
+        let value: Vec<u32> = (0..5).collect();
+        assert_eq!(
+            super::sample_terms::<u32, _, _>(&value, 10, |_| unreachable!()),
+            vec![0, 1, 2, 3, 4]
+        );
+
+        // Always replacing the first slot keeps the last value there.
+        let mut bounds = vec![];
+        let sample = super::sample_terms::<u32, _, _>(&value, 2, |n| {
+            bounds.push(n);
+            0
+        });
+        assert_eq!(sample, vec![4, 1]);
+        assert_eq!(bounds, vec![3, 4, 5]);
+    }
+}
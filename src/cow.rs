@@ -0,0 +1,389 @@
+use super::{any, CastThrough, GenericMutate, GenericQuery, GenericTransform, Term};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Is this an `Rc` or `Arc` that shares its value, and that the given partial
+/// transformation wouldn't change anything within?
+trait SharedAndUnchanged {
+    fn shared_and_unchanged<U, F>(&self, f: &mut F) -> bool
+    where
+        F: FnMut(&U) -> Option<U>;
+}
+
+impl<T> SharedAndUnchanged for T {
+    #[inline(always)]
+    default fn shared_and_unchanged<U, F>(&self, _: &mut F) -> bool
+    where
+        F: FnMut(&U) -> Option<U>,
+    {
+        false
+    }
+}
+
+impl<T> SharedAndUnchanged for Rc<T>
+where
+    T: Clone + Term,
+{
+    #[inline]
+    fn shared_and_unchanged<U, F>(&self, f: &mut F) -> bool
+    where
+        F: FnMut(&U) -> Option<U>,
+    {
+        (Rc::strong_count(self) > 1 || Rc::weak_count(self) > 0)
+            && !any(self, |u: &U| f(u).is_some())
+    }
+}
+
+impl<T> SharedAndUnchanged for Arc<T>
+where
+    T: Clone + Term,
+{
+    #[inline]
+    fn shared_and_unchanged<U, F>(&self, f: &mut F) -> bool
+    where
+        F: FnMut(&U) -> Option<U>,
+    {
+        (Arc::strong_count(self) > 1 || Arc::weak_count(self) > 0)
+            && !any(self, |u: &U| f(u).is_some())
+    }
+}
+
+/// Recursively perform a partial transformation in place, in a bottom up
+/// manner, copying values shared by `Rc`s or `Arc`s only where something within
+/// them actually changes.
+///
+/// Mutating through a shared `Rc` or `Arc` has to clone the value it shares
+/// first, with `make_mut`. Before descending into one, this checks whether `f`
+/// would return a replacement for any value within it, and leaves it, and its
+/// sharing, alone if not. Values that aren't shared are mutated in place as
+/// usual. This makes rewriting a few leaves of a persistent data structure copy
+/// only the path to them.
+///
+/// The check calls `f` on values it then discards, so `f` should be a pure
+/// function of its argument. Checking costs a traversal of each shared value
+/// that is reached, and nested shared values are checked again on the way
+/// down.
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::sync::Arc;
+///
+/// let left = Arc::new(vec![1u32, 2]);
+/// let right = Arc::new(vec![3u32, 4]);
+/// let mut value = vec![left.clone(), right.clone()];
+///
+/// let mut bump = EverywhereCow::new(|n: &u32| if *n == 4 { Some(40) } else { None });
+/// bump.mutate(&mut value);
+///
+/// assert_eq!(*value[1], vec![3, 40]);
+/// assert!(Arc::ptr_eq(&value[0], &left));
+/// assert_eq!(*right, vec![3, 4]);
+/// ```
+#[derive(Debug)]
+pub struct EverywhereCow<F, U>
+where
+    F: FnMut(&U) -> Option<U>,
+{
+    f: F,
+    phantom: PhantomData<fn(&U) -> U>,
+}
+
+impl<F, U> EverywhereCow<F, U>
+where
+    F: FnMut(&U) -> Option<U>,
+{
+    /// Construct a new copy on write transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> EverywhereCow<F, U> {
+        EverywhereCow {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U> GenericMutate<()> for EverywhereCow<F, U>
+where
+    F: FnMut(&U) -> Option<U>,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        if (*t).shared_and_unchanged(&mut self.f) {
+            return;
+        }
+        t.map_one_mutation(self, |_, ()| {});
+        if let Some(u) = t.view_as_mut() {
+            if let Some(replacement) = (self.f)(u) {
+                *u = replacement;
+            }
+        }
+    }
+}
+
+/// How queries see the value in a `std::borrow::Cow<B>`, whether it is borrowed
+/// or owned: as a `&B`, without copying anything.
+///
+/// A `CowView<B>` only exists for the duration of a query, and derefs to the
+/// `B` for no longer than it is borrowed itself. Its children are those of the
+/// `B`: the `B` itself when it is a `Term`, the elements of a slice of `Term`s,
+/// and nothing otherwise, as for a `str`.
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::borrow::Cow;
+///
+/// let cows: Vec<Cow<str>> = vec![Cow::Borrowed("hi"), Cow::Owned("hey".to_string())];
+/// let mut len = Everything::new(Query::or_else(|| 0, |s: &CowView<str>| s.len()), |a, b| a + b);
+/// assert_eq!(len.query(&cows), 5);
+/// ```
+pub struct CowView<B>
+where
+    B: ?Sized,
+{
+    // Not a reference, so that typed queries can tell views apart by type
+    // alone, and so that queries can't keep a view past the call they are
+    // lent it in.
+    value: *const B,
+}
+
+unsafe impl<B> Send for CowView<B>
+where
+    B: ?Sized + Sync,
+{
+}
+
+unsafe impl<B> Sync for CowView<B>
+where
+    B: ?Sized + Sync,
+{
+}
+
+impl<B> CowView<B>
+where
+    B: ?Sized,
+{
+    /// Lend a view of the given value to `f`.
+    #[inline]
+    pub(crate) fn with<F, R>(value: &B, f: F) -> R
+    where
+        F: FnOnce(&CowView<B>) -> R,
+    {
+        f(&CowView { value })
+    }
+}
+
+impl<B> Deref for CowView<B>
+where
+    B: ?Sized,
+{
+    type Target = B;
+
+    #[inline]
+    fn deref(&self) -> &B {
+        // Safe because a view is only ever lent out while its value is
+        // borrowed, and never for longer than itself.
+        unsafe { &*self.value }
+    }
+}
+
+impl<B> fmt::Debug for CowView<B>
+where
+    B: ?Sized + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CowView").field(&&**self).finish()
+    }
+}
+
+/// The children a `CowView<B>` lends to queries.
+trait ViewChildren {
+    fn query_children<Q, R, F>(&self, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R);
+
+    fn query_children_rev<Q, R, F>(&self, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R);
+}
+
+impl<B> ViewChildren for B
+where
+    B: ?Sized,
+{
+    #[inline(always)]
+    default fn query_children<Q, R, F>(&self, _: &mut Q, _: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+    }
+
+    #[inline(always)]
+    default fn query_children_rev<Q, R, F>(&self, _: &mut Q, _: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+    }
+}
+
+impl<T> ViewChildren for T
+where
+    T: Term,
+{
+    #[inline]
+    fn query_children<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        let r = query.query(self);
+        each(query, r);
+    }
+
+    #[inline]
+    fn query_children_rev<Q, R, F>(&self, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        self.query_children(query, each);
+    }
+}
+
+impl<T> ViewChildren for [T]
+where
+    T: Term,
+{
+    #[inline]
+    fn query_children<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        for t in self {
+            let r = query.query(t);
+            each(query, r);
+        }
+    }
+
+    #[inline]
+    fn query_children_rev<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        for t in self.iter().rev() {
+            let r = query.query(t);
+            each(query, r);
+        }
+    }
+}
+
+/// Only queries ever see a `CowView`, so transforming or mutating one does
+/// nothing.
+impl<B> Term for CowView<B>
+where
+    B: ?Sized,
+{
+    #[inline(always)]
+    fn map_one_transform<F>(self, _: &mut F) -> Self
+    where
+        F: GenericTransform,
+    {
+        self
+    }
+
+    #[inline]
+    fn map_one_query<Q, R, F>(&self, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        (**self).query_children(query, each);
+    }
+
+    #[inline(always)]
+    fn map_one_mutation<M, R, F>(&mut self, _: &mut M, _: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+    }
+
+    #[inline]
+    fn map_one_query_rev<Q, R, F>(&self, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        (**self).query_children_rev(query, each);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Everywhere, EverywhereMut, GenericTransform, Mutation, Transformation};
+
+    #[test]
+    fn everywhere_cow() {
+        let leaf = Rc::new(1u32);
+        let shared = Rc::new(vec![leaf.clone(), Rc::new(2u32)]);
+        let mut value = (shared.clone(), Rc::new(vec![Rc::new(3u32)]), shared.clone());
+
+        let mut calls = 0;
+        {
+            let mut bump = EverywhereCow::new(|n: &u32| {
+                calls += 1;
+                if *n == 2 {
+                    Some(20)
+                } else {
+                    None
+                }
+            });
+            bump.mutate(&mut value);
+        }
+
+        // The unshared vector and its element were mutated in place.
+        assert_eq!(*value.1[0], 3);
+        // Each shared copy was copied, but the unchanged leaf within is still
+        // shared by all of them.
+        assert_eq!((*value.0[1], *value.2[1]), (20, 20));
+        assert!(!Rc::ptr_eq(&value.0, &shared) && !Rc::ptr_eq(&value.2, &shared));
+        assert!(Rc::ptr_eq(&value.0[0], &leaf) && Rc::ptr_eq(&value.2[0], &leaf));
+        assert_eq!(*shared[1], 2);
+        assert_eq!(calls, 11);
+    }
+
+    #[test]
+    fn shared_terms() {
+        // Transforming unwraps the only reference, and clones shared values.
+        let only = Arc::new(vec![1u32]);
+        let storage = only.as_ptr();
+        let only = Everywhere::new(Transformation::new(|n: u32| n + 1)).transform(only);
+        assert_eq!((only[0], only.as_ptr()), (2, storage));
+
+        let shared = Rc::new(1u32);
+        let copy = Everywhere::new(Transformation::new(|c: char| c)).transform(shared.clone());
+        assert!(!Rc::ptr_eq(&copy, &shared));
+
+        // Mutating copies shared values on write, even if nothing changes.
+        let mut value = (shared.clone(), shared.clone());
+        EverywhereMut::new(Mutation::new(|_: &mut u32| {})).mutate(&mut value);
+        assert!(!Rc::ptr_eq(&value.0, &shared) && !Rc::ptr_eq(&value.1, &shared));
+
+        // Copying on write only where something changes keeps them shared.
+        let mut value = (shared.clone(), shared.clone());
+        EverywhereCow::new(|_: &u32| None).mutate(&mut value);
+        assert!(Rc::ptr_eq(&value.0, &shared) && Rc::ptr_eq(&value.1, &shared));
+    }
+}
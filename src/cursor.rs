@@ -0,0 +1,352 @@
+use super::{CastThrough, GenericMutate, GenericQuery, Path, PathSegment, Term};
+use std::marker::PhantomData;
+
+/// A cursor pointing at one value within a data structure, for surgical edits.
+///
+/// A cursor is a path from the root, so moving it around is free, and reading
+/// or editing the value it points at only visits the values along the path to
+/// it, rather than the whole data structure. After an edit, the values above
+/// it can be fixed up too, such as to recompute a cached size or hash, with
+/// `edit_and_fix`.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut doc = vec![vec!["a", "b"], vec!["c"]];
+/// let mut cursor = CursorMut::new(&mut doc);
+///
+/// cursor.child(0).child(1);
+/// assert_eq!(cursor.edit(|s: &mut &str| *s = "B"), Some(()));
+///
+/// cursor.up();
+/// cursor.up();
+/// assert!(cursor.seek(Query::new(|s: &&str| *s == "c")));
+/// assert_eq!(cursor.path().to_string(), "[1][0]");
+/// assert_eq!(cursor.inspect(|s: &&str| s.len()), Some(1));
+///
+/// assert_eq!(doc, vec![vec!["a", "B"], vec!["c"]]);
+/// ```
+#[derive(Debug)]
+pub struct CursorMut<'a, T>
+where
+    T: 'a + Term,
+{
+    root: &'a mut T,
+    path: Path,
+}
+
+impl<'a, T> CursorMut<'a, T>
+where
+    T: Term,
+{
+    /// Construct a new cursor pointing at the root.
+    #[inline]
+    pub fn new(root: &'a mut T) -> CursorMut<'a, T> {
+        CursorMut::at(root, Path::new())
+    }
+
+    /// Construct a new cursor pointing at the value at the given path.
+    #[inline]
+    pub fn at(root: &'a mut T, path: Path) -> CursorMut<'a, T> {
+        CursorMut { root, path }
+    }
+
+    /// Get the path to the value this cursor points at.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move down to the child at position `index` among its siblings.
+    ///
+    /// This doesn't check that there is such a child; reading or editing a
+    /// value that doesn't exist does nothing.
+    #[inline]
+    pub fn child(&mut self, index: usize) -> &mut CursorMut<'a, T> {
+        self.path.push(PathSegment::Index(index), index);
+        self
+    }
+
+    /// Move up to the parent, returning false if this is already at the root.
+    #[inline]
+    pub fn up(&mut self) -> bool {
+        self.path.pop().is_some()
+    }
+
+    /// Move to the first value, top down, within the value this cursor points
+    /// at, including itself, for which the given query evaluates to true.
+    ///
+    /// Returns false, and stays put, if there is no such value.
+    pub fn seek<Q>(&mut self, q: Q) -> bool
+    where
+        Q: GenericQuery<bool>,
+    {
+        let mut seek = Seek { q: Some(q), found: None };
+        self.visit(&mut seek, &mut NoFixup);
+        match seek.found {
+            Some(found) => {
+                for (segment, index) in found.segments().iter().zip(found.indices()) {
+                    self.path.push(*segment, *index);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Call `f` on the value this cursor points at, if it exists and is a `U`.
+    pub fn inspect<U, R, F>(&mut self, f: F) -> Option<R>
+    where
+        F: FnOnce(&U) -> R,
+    {
+        let mut inspect = Inspect {
+            f: Some(f),
+            r: None,
+            phantom: PhantomData,
+        };
+        self.visit(&mut inspect, &mut NoFixup);
+        inspect.r
+    }
+
+    /// Edit the value this cursor points at with `f`, if it exists and is a
+    /// `U`.
+    #[inline]
+    pub fn edit<U, R, F>(&mut self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut U) -> R,
+    {
+        self.edit_and_fix(f, NoFixup)
+    }
+
+    /// Edit the value this cursor points at with `f`, if it exists and is a
+    /// `U`, and then mutate each of its ancestors with `fixup`, from its parent
+    /// up to the root.
+    pub fn edit_and_fix<U, R, F, M>(&mut self, f: F, mut fixup: M) -> Option<R>
+    where
+        F: FnOnce(&mut U) -> R,
+        M: GenericMutate<()>,
+    {
+        let mut edit = Edit {
+            f: Some(f),
+            r: None,
+            phantom: PhantomData,
+        };
+        self.visit(&mut edit, &mut fixup);
+        edit.r
+    }
+
+    /// Mutate the value this cursor points at with `target`, and, if it
+    /// exists, each of its ancestors with `fixup`.
+    fn visit<M, A>(&mut self, target: &mut M, fixup: &mut A)
+    where
+        M: GenericMutate<()>,
+        A: GenericMutate<()>,
+    {
+        AtPath {
+            indices: self.path.indices(),
+            depth: 0,
+            target,
+            fixup,
+            reached: false,
+        }.mutate(self.root)
+    }
+}
+
+/// Mutates the value at the end of a path, and then each of its ancestors.
+struct AtPath<'a, M: 'a, A: 'a> {
+    indices: &'a [usize],
+    depth: usize,
+    target: &'a mut M,
+    fixup: &'a mut A,
+    reached: bool,
+}
+
+impl<'a, M, A> GenericMutate<()> for AtPath<'a, M, A>
+where
+    M: GenericMutate<()>,
+    A: GenericMutate<()>,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        match self.indices.get(self.depth).cloned() {
+            Some(target) => {
+                self.depth += 1;
+                t.map_one_mutation(
+                    &mut AtChild {
+                        at: self,
+                        target,
+                        index: 0,
+                    },
+                    |_, ()| {},
+                );
+                self.depth -= 1;
+                if self.reached {
+                    self.fixup.mutate(t);
+                }
+            }
+            None => {
+                self.reached = true;
+                self.target.mutate(t);
+            }
+        }
+    }
+}
+
+/// Continues along a path into only the child on it.
+struct AtChild<'a, 'b: 'a, M: 'b, A: 'b> {
+    at: &'a mut AtPath<'b, M, A>,
+    target: usize,
+    index: usize,
+}
+
+impl<'a, 'b, M, A> GenericMutate<()> for AtChild<'a, 'b, M, A>
+where
+    M: GenericMutate<()>,
+    A: GenericMutate<()>,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        let index = self.index;
+        self.index += 1;
+        if index == self.target {
+            self.at.mutate(t);
+        }
+    }
+}
+
+/// Leaves values alone.
+struct NoFixup;
+
+impl GenericMutate<()> for NoFixup {
+    #[inline]
+    fn mutate<T>(&mut self, _: &mut T)
+    where
+        T: Term,
+    {
+    }
+}
+
+/// Finds the path to the first value matching a query.
+struct Seek<Q> {
+    q: Option<Q>,
+    found: Option<Path>,
+}
+
+impl<Q> GenericMutate<()> for Seek<Q>
+where
+    Q: GenericQuery<bool>,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        if let Some(q) = self.q.take() {
+            self.found = Path::find(&*t, q);
+        }
+    }
+}
+
+/// Calls a function on a value, if it is a `U`.
+struct Inspect<F, U, R> {
+    f: Option<F>,
+    r: Option<R>,
+    phantom: PhantomData<fn(&U)>,
+}
+
+impl<F, U, R> GenericMutate<()> for Inspect<F, U, R>
+where
+    F: FnOnce(&U) -> R,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        if let (Some(u), Some(f)) = ((*t).view_as(), self.f.take()) {
+            self.r = Some(f(u));
+        }
+    }
+}
+
+/// Calls a function on a mutable value, if it is a `U`.
+struct Edit<F, U, R> {
+    f: Option<F>,
+    r: Option<R>,
+    phantom: PhantomData<fn(&mut U)>,
+}
+
+impl<F, U, R> GenericMutate<()> for Edit<F, U, R>
+where
+    F: FnOnce(&mut U) -> R,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        if let (Some(u), Some(f)) = (t.view_as_mut(), self.f.take()) {
+            self.r = Some(f(u));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Mutation, Query};
+
+    #[derive(Debug, PartialEq)]
+    struct Node {
+        size: u32,
+        children: Vec<Leaf>,
+    }
+    impl_term!(Node { size, children });
+
+    #[derive(Debug, PartialEq)]
+    struct Leaf(Vec<u32>);
+    impl_term!(Leaf { 0 });
+
+    #[test]
+    fn cursor_mut() {
+        let mut tree = vec![Node {
+            size: 3,
+            children: vec![Leaf(vec![1, 2]), Leaf(vec![3])],
+        }];
+
+        let mut fixed = 0;
+        {
+            let mut cursor = CursorMut::new(&mut tree);
+            assert!(cursor.seek(Query::new(|l: &Leaf| l.0 == [3])));
+            assert_eq!(cursor.path().to_string(), "[0][1][1]");
+
+            // Growing a leaf recomputes the size of the node above it.
+            let grown = cursor.edit_and_fix(
+                |l: &mut Leaf| l.0.push(4),
+                Mutation::new(|n: &mut Node| {
+                    fixed += 1;
+                    n.size = n.children.iter().map(|l| l.0.len() as u32).sum();
+                }),
+            );
+            assert_eq!(grown, Some(()));
+
+            // Values of the wrong type, or that don't exist, are left alone.
+            assert_eq!(cursor.edit(|_: &mut Node| ()), None);
+            cursor.child(9);
+            assert_eq!(cursor.inspect(|_: &Leaf| ()), None);
+            assert!(!cursor.seek(Query::new(|_: &u32| true)));
+            assert!(cursor.up() && cursor.up() && cursor.up() && cursor.up());
+            assert!(!cursor.up());
+            assert_eq!(cursor.inspect(|t: &Vec<Node>| t.len()), Some(1));
+        }
+        assert_eq!(fixed, 1);
+        assert_eq!(tree[0].size, 4);
+        assert_eq!(tree[0].children[1], Leaf(vec![3, 4]));
+    }
+}
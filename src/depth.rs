@@ -0,0 +1,250 @@
+use super::{CastThrough, GenericPathedTransform, GenericQuery, GenericTransform, Path, Term};
+use std::marker::PhantomData;
+
+/// Recursively perform a transformation or query across a data structure, but
+/// only down to a maximum depth.
+///
+/// The value a traversal starts at is at depth zero, and its children are at
+/// depth one, and so on. Values deeper than the maximum depth are neither
+/// visited nor descended into, which saves walking the whole structure when
+/// only its top few levels matter.
+///
+/// Constructed with `new`, this is a bottom up transformation traversal like
+/// `Everywhere`. Constructed with `with_fold`, this is a top down query
+/// traversal like `Everything`, joining the results of its queries with the
+/// given fold function.
+#[derive(Debug)]
+pub struct MaxDepth<F, J = ()> {
+    f: F,
+    fold: J,
+    max: usize,
+    depth: usize,
+}
+
+impl<F> MaxDepth<F>
+where
+    F: GenericTransform,
+{
+    /// Construct a new depth limited transformation traversal.
+    #[inline]
+    pub fn new(max: usize, f: F) -> MaxDepth<F> {
+        MaxDepth {
+            f,
+            fold: (),
+            max,
+            depth: 0,
+        }
+    }
+}
+
+impl<Q, J> MaxDepth<Q, J> {
+    /// Construct a new depth limited query traversal.
+    #[inline]
+    pub fn with_fold<R>(max: usize, q: Q, fold: J) -> MaxDepth<Q, J>
+    where
+        Q: GenericQuery<R>,
+        J: FnMut(R, R) -> R,
+    {
+        MaxDepth {
+            f: q,
+            fold,
+            max,
+            depth: 0,
+        }
+    }
+}
+
+impl<F, J> GenericTransform for MaxDepth<F, J>
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let t = if self.depth < self.max {
+            self.depth += 1;
+            let t = t.map_one_transform(self);
+            self.depth -= 1;
+            t
+        } else {
+            t
+        };
+        self.f.transform(t)
+    }
+}
+
+impl<Q, J, R> GenericQuery<R> for MaxDepth<Q, J>
+where
+    Q: GenericQuery<R>,
+    J: FnMut(R, R) -> R,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> R
+    where
+        T: Term,
+    {
+        let mut r = Some(self.f.query(t));
+        if self.depth < self.max {
+            self.depth += 1;
+            t.map_one_query(self, |me, rr| {
+                r = Some((me.fold)(r.take().unwrap(), rr));
+            });
+            self.depth -= 1;
+        }
+        r.unwrap()
+    }
+}
+
+/// A depth aware transformation takes some value `U` and how deep it is in the
+/// data structure being traversed, and returns a new, transformed version of
+/// it. It can be called on values of *any* type `T`, not just on values of type
+/// `U`, in which case it is simply the identity function.
+///
+/// The value a traversal starts at is at depth zero. Traverse with
+/// `PathedEverywhere` to tell the transformation each value's depth.
+#[derive(Debug)]
+pub struct TransformationWithDepth<F, U>
+where
+    F: FnMut(U, usize) -> U,
+{
+    f: F,
+    phantom: PhantomData<fn(U) -> U>,
+}
+
+impl<F, U> TransformationWithDepth<F, U>
+where
+    F: FnMut(U, usize) -> U,
+{
+    /// Construct a new `TransformationWithDepth` from the given function.
+    #[inline]
+    pub fn new(f: F) -> TransformationWithDepth<F, U> {
+        TransformationWithDepth {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U> GenericPathedTransform for TransformationWithDepth<F, U>
+where
+    F: FnMut(U, usize) -> U,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, path: &Path) -> T {
+        let f = &mut self.f;
+        t.map_as(|u| (f(u, path.len()), ())).0
+    }
+}
+
+/// Fold every value of type `U` within `t`, including `t` itself, into an
+/// accumulator along with how deep it is, in top down, left to right order.
+///
+/// This is `fold_terms`, but with the depth of each value passed to `f` too.
+/// The value `t` is at depth zero.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (1u32, vec![2u32, 3]);
+/// let deepest = fold_terms_with_depth(&value, 0, |deepest, _: &u32, depth| {
+///     deepest.max(depth)
+/// });
+/// assert_eq!(deepest, 2);
+/// ```
+pub fn fold_terms_with_depth<U, T, A, F>(t: &T, init: A, f: F) -> A
+where
+    T: Term,
+    F: FnMut(A, &U, usize) -> A,
+{
+    let mut fold = DepthFold {
+        f,
+        acc: Some(init),
+        depth: 0,
+        phantom: PhantomData,
+    };
+    fold.query(t);
+    fold.acc.unwrap()
+}
+
+/// Folds every `U` within a data structure into an accumulator, keeping track
+/// of how deep each one is.
+struct DepthFold<F, U, A>
+where
+    F: FnMut(A, &U, usize) -> A,
+{
+    f: F,
+    acc: Option<A>,
+    depth: usize,
+    phantom: PhantomData<fn(&U)>,
+}
+
+impl<F, U, A> GenericQuery<()> for DepthFold<F, U, A>
+where
+    F: FnMut(A, &U, usize) -> A,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T)
+    where
+        T: Term,
+    {
+        if let Some(u) = t.view_as() {
+            self.acc = Some((self.f)(self.acc.take().unwrap(), u, self.depth));
+        }
+        self.depth += 1;
+        t.map_one_query(self, |_, _| {});
+        self.depth -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{PathedEverywhere, Query, Transformation};
+
+    #[test]
+    fn max_depth() {
+        let value = (1u32, (2u32, vec![3u32]));
+
+        let mut count = MaxDepth::with_fold(1, Query::new(|_: &u32| 1), |a, b| a + b);
+        assert_eq!(count.query(&value), 1);
+        let mut count = MaxDepth::with_fold(2, Query::new(|_: &u32| 1), |a, b| a + b);
+        assert_eq!(count.query(&value), 2);
+
+        let mut incr = MaxDepth::new(2, Transformation::new(|n: u32| n + 1));
+        assert_eq!(incr.transform(value), (2, (3, vec![3])));
+    }
+
+    #[test]
+    fn transformation_with_depth() {
+        let mut depths = PathedEverywhere::new(TransformationWithDepth::new(|_: u32, depth| {
+            depth as u32
+        }));
+        let value = (0u32, vec![(0u32, 0u32)]);
+        assert_eq!(depths.transform(value), (1, vec![(3, 3)]));
+    }
+
+    #[test]
+    fn fold_terms_with_depth() {
+        enum Expr {
+            Num(u32),
+            Neg(Box<Expr>),
+        }
+        impl_term!(enum Expr {
+            Num(n),
+            Neg(e),
+        });
+
+        let expr = Expr::Neg(Box::new(Expr::Neg(Box::new(Expr::Num(1)))));
+        let too_deep = super::fold_terms_with_depth(&expr, vec![], |mut too_deep, e: &Expr, depth| {
+            if depth > 2 {
+                if let Expr::Num(n) = *e {
+                    too_deep.push(n);
+                }
+            }
+            too_deep
+        });
+        assert_eq!(too_deep, vec![1]);
+    }
+}
@@ -0,0 +1,693 @@
+use super::term_impls::replace_in_place;
+use super::{GenericMutate, GenericQuery, GenericTransform, Term, Transformation};
+use std::any::{self, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+
+/// Get the `TypeId` of a type that might not be `'static`, ignoring its
+/// lifetimes, like casting does.
+trait ErasedTypeId {
+    fn erased_type_id(&self) -> TypeId
+    where
+        Self: 'static;
+}
+
+impl<T> ErasedTypeId for PhantomData<T>
+where
+    T: ?Sized,
+{
+    #[inline]
+    fn erased_type_id(&self) -> TypeId
+    where
+        Self: 'static,
+    {
+        TypeId::of::<T>()
+    }
+}
+
+#[inline]
+pub(crate) fn erased_type_id<T>() -> TypeId
+where
+    T: ?Sized,
+{
+    let phantom: &dyn ErasedTypeId = &PhantomData::<T>;
+    // Safe because lifetimes are erased by this point anyways, and the method
+    // only looks at the type, never at any borrowed data.
+    let phantom: &(dyn ErasedTypeId + 'static) = unsafe { mem::transmute(phantom) };
+    phantom.erased_type_id()
+}
+
+/// A typed transformation with its type erased, which reads its `U` from the
+/// given pointer and writes the result back.
+type ErasedTransformation<'f> = Box<dyn FnMut(*mut ()) + 'f>;
+
+/// A set of typed transformations, each on a different type, dispatched with a
+/// table keyed by type.
+///
+/// Chaining typed transformations with `GenericRewrite::or_else` tries each of
+/// them in turn on every value, which adds up for rewrite systems with dozens
+/// of rules. A transformation set looks up the one for a value's type in a
+/// single step instead, and is the identity function on values of types
+/// without one.
+///
+/// Values are matched on their exact type, so unlike a `Transformation`, a set
+/// doesn't see through `Transparent` wrappers.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut rules = TransformationSet::new()
+///     .with(|b: bool| !b)
+///     .with(|n: u32| n + 1)
+///     .with(|c: char| c.to_ascii_uppercase());
+/// let value = Everywhere::new(&mut rules).transform((true, 1u32, vec!['a']));
+/// assert_eq!(value, (false, 2, vec!['A']));
+/// ```
+pub struct TransformationSet<'f> {
+    transformations: HashMap<TypeId, ErasedTransformation<'f>>,
+}
+
+impl<'f> TransformationSet<'f> {
+    /// Construct a new, empty transformation set.
+    #[inline]
+    pub fn new() -> TransformationSet<'f> {
+        TransformationSet {
+            transformations: HashMap::new(),
+        }
+    }
+
+    /// Add a transformation of values of type `U`, replacing any the set
+    /// already has for them.
+    pub fn with<U, F>(mut self, mut f: F) -> TransformationSet<'f>
+    where
+        U: 'static,
+        F: 'f + FnMut(U) -> U,
+    {
+        let erased = move |u: *mut ()| {
+            // Safe because the set only calls this with a pointer to a valid
+            // `U`.
+            let u = unsafe { &mut *(u as *mut U) };
+            replace_in_place(u, &mut f);
+        };
+        self.transformations.insert(TypeId::of::<U>(), Box::new(erased));
+        self
+    }
+
+    /// How many types this set has a transformation for.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.transformations.len()
+    }
+
+    /// Does this set not have any transformations?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.transformations.is_empty()
+    }
+}
+
+impl<'f> Default for TransformationSet<'f> {
+    #[inline]
+    fn default() -> TransformationSet<'f> {
+        TransformationSet::new()
+    }
+}
+
+impl<'f> fmt::Debug for TransformationSet<'f> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TransformationSet")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<'f> GenericTransform for TransformationSet<'f> {
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        if self.transformations.is_empty() {
+            return t;
+        }
+        match self.transformations.get_mut(&erased_type_id::<T>()) {
+            Some(f) => {
+                let mut t = MaybeUninit::new(t);
+                f(t.as_mut_ptr() as *mut ());
+                // Safe because the transformation wrote its result back.
+                unsafe { t.assume_init() }
+            }
+            None => t,
+        }
+    }
+}
+
+/// A typed query with its type erased, which reads its `U` from the given
+/// pointer.
+type ErasedQuery<'f, R> = Box<dyn FnMut(*const ()) -> R + 'f>;
+
+/// A set of typed queries, each on a different type, along with a default for
+/// values of every other type, dispatched with a table keyed by type.
+///
+/// This is the query counterpart of `TransformationSet`: rather than trying
+/// each case of a query extended with `GenericQuery::ext` in turn, it looks up
+/// the one for a value's type in a single step. Values are matched on their
+/// exact type, so a set doesn't see through `Transparent` wrappers.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut kind = QuerySet::new(|| "other")
+///     .with(|_: &u32| "number")
+///     .with(|_: &char| "char")
+///     .with(|v: &Vec<u32>| if v.is_empty() { "empty" } else { "numbers" });
+/// assert_eq!(kind.query(&1u32), "number");
+/// assert_eq!(kind.query(&Vec::<u32>::new()), "empty");
+/// assert_eq!(kind.query(&true), "other");
+///
+/// let cost = QuerySet::new(|| 1u32).with(|v: &Vec<u32>| v.len() as u32 * 2);
+/// let total = Everything::new(cost, |a, b| a + b).query(&(1u32, vec![1u32, 2]));
+/// assert_eq!(total, 1 + 1 + 4 + 2);
+/// ```
+pub struct QuerySet<'f, R> {
+    queries: HashMap<TypeId, ErasedQuery<'f, R>>,
+    default: Box<dyn FnMut() -> R + 'f>,
+}
+
+impl<'f, R> QuerySet<'f, R> {
+    /// Construct a new query set, without any typed queries, answering with
+    /// `default()` for every value.
+    #[inline]
+    pub fn new<D>(default: D) -> QuerySet<'f, R>
+    where
+        D: 'f + FnMut() -> R,
+    {
+        QuerySet {
+            queries: HashMap::new(),
+            default: Box::new(default),
+        }
+    }
+
+    /// Add a query of values of type `U`, replacing any the set already has
+    /// for them.
+    pub fn with<U, F>(mut self, mut f: F) -> QuerySet<'f, R>
+    where
+        U: 'static,
+        F: 'f + FnMut(&U) -> R,
+    {
+        let erased = move |u: *const ()| {
+            // Safe because the set only calls this with a pointer to a valid
+            // `U`.
+            f(unsafe { &*(u as *const U) })
+        };
+        self.queries.insert(TypeId::of::<U>(), Box::new(erased));
+        self
+    }
+
+    /// How many types this set has a query for.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Does this set not have any typed queries?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+impl<'f, R> fmt::Debug for QuerySet<'f, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("QuerySet")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<'f, R> GenericQuery<R> for QuerySet<'f, R> {
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> R
+    where
+        T: Term,
+    {
+        if self.queries.is_empty() {
+            return (self.default)();
+        }
+        match self.queries.get_mut(&erased_type_id::<T>()) {
+            Some(f) => f(t as *const T as *const ()),
+            None => (self.default)(),
+        }
+    }
+}
+
+/// A cast of a type-erased pointer to one type to a pointer to the trait
+/// object it implements.
+type ErasedUpcast<D> = Box<dyn Fn(*mut ()) -> *mut D>;
+
+/// A mutation of every value whose type implements some trait, through a
+/// trait object.
+///
+/// Typed mutations apply to a single concrete type, but a rewrite like
+/// resetting every span in a syntax tree needs to apply to every type with a
+/// span. Register each type implementing the trait, with how to cast it to the
+/// trait object `D`, and the mutation applies `f` to every value of those
+/// types, via one lookup in a table keyed by type. Values of types that aren't
+/// registered are left alone. Name the trait object type when constructing
+/// one, so that `f`'s argument type can be inferred.
+///
+/// Values are matched on their exact type, so this doesn't see through
+/// `Transparent` wrappers either.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// trait HasSpan {
+///     fn span_mut(&mut self) -> &mut (u32, u32);
+/// }
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Ident((u32, u32), &'static str);
+/// impl_term!(Ident { 1 });
+/// impl HasSpan for Ident {
+///     fn span_mut(&mut self) -> &mut (u32, u32) {
+///         &mut self.0
+///     }
+/// }
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Call((u32, u32), Ident, Vec<Ident>);
+/// impl_term!(Call { 1, 2 });
+/// impl HasSpan for Call {
+///     fn span_mut(&mut self) -> &mut (u32, u32) {
+///         &mut self.0
+///     }
+/// }
+///
+/// let mut call = Call((0, 6), Ident((0, 1), "f"), vec![Ident((2, 3), "x")]);
+///
+/// let reset = TraitMutation::<dyn HasSpan>::new(|s| *s.span_mut() = (0, 0))
+///     .register(|i: &mut Ident| i as &mut dyn HasSpan)
+///     .register(|c: &mut Call| c as &mut dyn HasSpan);
+/// EverywhereMut::new(reset).mutate(&mut call);
+///
+/// assert_eq!(call, Call((0, 0), Ident((0, 0), "f"), vec![Ident((0, 0), "x")]));
+/// ```
+pub struct TraitMutation<'f, D>
+where
+    D: ?Sized + 'static,
+{
+    f: Box<dyn FnMut(&mut D) + 'f>,
+    upcasts: HashMap<TypeId, ErasedUpcast<D>>,
+}
+
+impl<'f, D> TraitMutation<'f, D>
+where
+    D: ?Sized + 'static,
+{
+    /// Construct a new trait mutation applying `f`, without any types
+    /// registered yet.
+    #[inline]
+    pub fn new<F>(f: F) -> TraitMutation<'f, D>
+    where
+        F: 'f + FnMut(&mut D),
+    {
+        TraitMutation {
+            f: Box::new(f),
+            upcasts: HashMap::new(),
+        }
+    }
+
+    /// Register `U` as a type implementing the trait, with a cast of it to the
+    /// trait object.
+    pub fn register<U>(mut self, upcast: fn(&mut U) -> &mut D) -> TraitMutation<'f, D>
+    where
+        U: 'static,
+    {
+        let erased = move |u: *mut ()| {
+            // Safe because the mutation only calls this with a pointer to a
+            // valid `U`.
+            upcast(unsafe { &mut *(u as *mut U) }) as *mut D
+        };
+        self.upcasts.insert(TypeId::of::<U>(), Box::new(erased));
+        self
+    }
+
+    /// How many types are registered.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.upcasts.len()
+    }
+
+    /// Are no types registered?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.upcasts.is_empty()
+    }
+}
+
+impl<'f, D> fmt::Debug for TraitMutation<'f, D>
+where
+    D: ?Sized + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TraitMutation")
+            .field("trait_object", &any::type_name::<D>())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<'f, D> GenericMutate<()> for TraitMutation<'f, D>
+where
+    D: ?Sized + 'static,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        if self.upcasts.is_empty() {
+            return;
+        }
+        if let Some(upcast) = self.upcasts.get(&erased_type_id::<T>()) {
+            // Safe because the cast only reborrows `t`, as registered.
+            let d = unsafe { &mut *upcast(t as *mut T as *mut ()) };
+            (self.f)(d);
+        }
+    }
+}
+
+impl<'f, D> GenericTransform for TraitMutation<'f, D>
+where
+    D: ?Sized + 'static,
+{
+    #[inline]
+    fn transform<T>(&mut self, mut t: T) -> T
+    where
+        T: Term,
+    {
+        self.mutate(&mut t);
+        t
+    }
+}
+
+/// A mutable reference to a value of some type that isn't known statically,
+/// for passing values to a `GenericTransformDyn`.
+///
+/// Like `&mut dyn Any`, but for values of any type implementing `Term`.
+pub struct AnyTerm<'a> {
+    type_id: TypeId,
+    type_name: &'static str,
+    ptr: *mut (),
+    phantom: PhantomData<&'a mut ()>,
+}
+
+impl<'a> AnyTerm<'a> {
+    /// Erase the type of the given mutable reference.
+    #[inline]
+    pub fn new<T>(t: &'a mut T) -> AnyTerm<'a>
+    where
+        T: 'static + Term,
+    {
+        AnyTerm {
+            type_id: TypeId::of::<T>(),
+            type_name: any::type_name::<T>(),
+            ptr: t as *mut T as *mut (),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Erase the type of a mutable reference to a value whose type might not
+    /// be `'static`, ignoring its lifetimes like typed transformations do.
+    #[inline]
+    pub(crate) fn erased<T>(t: &'a mut T) -> AnyTerm<'a>
+    where
+        T: Term,
+    {
+        AnyTerm {
+            type_id: erased_type_id::<T>(),
+            type_name: any::type_name::<T>(),
+            ptr: t as *mut T as *mut (),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get the name of the type of the value, from `std::any::type_name`.
+    #[inline]
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Is the value a `U`?
+    #[inline]
+    pub fn is<U>(&self) -> bool
+    where
+        U: 'static,
+    {
+        self.type_id == TypeId::of::<U>()
+    }
+
+    /// Get the value as a `U`, if it is one.
+    #[inline]
+    pub fn downcast_mut<U>(&mut self) -> Option<&mut U>
+    where
+        U: 'static,
+    {
+        if self.is::<U>() {
+            // Safe because the value is a `U`.
+            Some(unsafe { &mut *(self.ptr as *mut U) })
+        } else {
+            None
+        }
+    }
+
+    /// Replace the value with `f` of it, if it is a `U`, and return whether it
+    /// was.
+    #[inline]
+    pub fn transform<U, F>(&mut self, f: F) -> bool
+    where
+        U: 'static,
+        F: FnOnce(U) -> U,
+    {
+        match self.downcast_mut() {
+            Some(u) => {
+                replace_in_place(u, f);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Borrow this for a shorter lifetime.
+    #[inline]
+    pub(crate) fn reborrow(&mut self) -> AnyTerm<'_> {
+        AnyTerm {
+            type_id: self.type_id,
+            type_name: self.type_name,
+            ptr: self.ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a> fmt::Debug for AnyTerm<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AnyTerm")
+            .field("type_name", &self.type_name)
+            .finish()
+    }
+}
+
+/// A dyn compatible counterpart of `GenericTransform`, for transformations
+/// behind a `Box<dyn GenericTransformDyn>`, such as ones supplied by plugins.
+///
+/// Rather than being generic over the type of value it transforms, this takes
+/// the value with its type erased, and transforms it in place. A boxed
+/// `GenericTransformDyn` is a `GenericTransform`, so it can be used with any
+/// traversal, and `Transformation`s and `TransformationSet`s of `'static`
+/// types are `GenericTransformDyn`s.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// struct Plugin;
+///
+/// impl GenericTransformDyn for Plugin {
+///     fn transform_dyn(&mut self, mut t: AnyTerm) {
+///         if let Some(n) = t.downcast_mut::<u32>() {
+///             *n += 1;
+///         }
+///     }
+/// }
+///
+/// let plugins: Vec<Box<dyn GenericTransformDyn>> = vec![
+///     Box::new(Plugin),
+///     Box::new(Transformation::new(|c: char| c.to_ascii_uppercase())),
+/// ];
+/// let mut plugins: Box<dyn GenericTransformDyn> = Box::new(plugins);
+/// let value = Everywhere::new(&mut plugins).transform((1u32, vec!['a']));
+/// assert_eq!(value, (2, vec!['A']));
+/// ```
+pub trait GenericTransformDyn {
+    /// Transform the given value in place.
+    fn transform_dyn(&mut self, t: AnyTerm);
+}
+
+impl<D> GenericTransform for Box<D>
+where
+    D: ?Sized + GenericTransformDyn,
+{
+    #[inline]
+    fn transform<T>(&mut self, mut t: T) -> T
+    where
+        T: Term,
+    {
+        (**self).transform_dyn(AnyTerm::erased(&mut t));
+        t
+    }
+}
+
+/// Each transformation is applied in turn.
+impl<D> GenericTransformDyn for Vec<Box<D>>
+where
+    D: ?Sized + GenericTransformDyn,
+{
+    #[inline]
+    fn transform_dyn(&mut self, mut t: AnyTerm) {
+        for d in self {
+            d.transform_dyn(t.reborrow());
+        }
+    }
+}
+
+impl<F, U> GenericTransformDyn for Transformation<F, U>
+where
+    F: FnMut(U) -> U,
+    U: 'static + Term,
+{
+    #[inline]
+    fn transform_dyn(&mut self, mut t: AnyTerm) {
+        t.transform(|u: U| GenericTransform::transform(self, u));
+    }
+}
+
+impl<'f> GenericTransformDyn for TransformationSet<'f> {
+    #[inline]
+    fn transform_dyn(&mut self, t: AnyTerm) {
+        if let Some(f) = self.transformations.get_mut(&t.type_id) {
+            f(t.ptr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Everywhere;
+
+    #[test]
+    fn transformation_set() {
+        let mut calls = 0;
+        {
+            let mut rules = TransformationSet::new()
+                .with(|n: u32| n + 1)
+                .with(|s: &'static str| if s == "x" { "y" } else { s })
+                .with(|v: Vec<u32>| {
+                    calls += 1;
+                    v.into_iter().rev().collect()
+                })
+                .with(|n: u32| n * 10);
+            assert_eq!(rules.len(), 3);
+            assert_eq!(rules.transform(1u32), 10);
+            assert_eq!(rules.transform(vec![1u32, 2]), vec![2, 1]);
+            assert_eq!(rules.transform(("x", 'c')), ("x", 'c'));
+            assert_eq!(rules.transform("x"), "y");
+        }
+        assert_eq!(calls, 1);
+
+        assert!(TransformationSet::default().is_empty());
+    }
+
+    #[test]
+    fn generic_transform_dyn() {
+        let mut n = 1u32;
+        {
+            let mut t = AnyTerm::new(&mut n);
+            assert!(t.type_name().ends_with("u32"));
+            assert!(!t.is::<u64>());
+            assert_eq!(t.downcast_mut::<char>(), None);
+            assert!(t.transform(|n: u32| n + 1));
+            assert!(!t.transform(|c: char| c));
+        }
+        assert_eq!(n, 2);
+
+        let plugins: Vec<Box<dyn GenericTransformDyn>> = vec![
+            Box::new(TransformationSet::new().with(|n: u32| n * 10)),
+            Box::new(Transformation::new(|n: u32| n + 1)),
+            Box::new(Transformation::new(|v: Vec<u32>| v.into_iter().rev().collect())),
+        ];
+        let mut plugins: Box<dyn GenericTransformDyn> = Box::new(plugins);
+        let value = Everywhere::new(&mut plugins).transform((vec![1u32, 2], 'c'));
+        assert_eq!(value, (vec![21, 11], 'c'));
+    }
+
+    trait Count {
+        fn count(&mut self) -> &mut u32;
+    }
+
+    impl Count for u32 {
+        fn count(&mut self) -> &mut u32 {
+            self
+        }
+    }
+
+    impl Count for (u32, char) {
+        fn count(&mut self) -> &mut u32 {
+            &mut self.0
+        }
+    }
+
+    #[test]
+    fn trait_mutation() {
+        let mut seen = vec![];
+        {
+            let mut bump = TraitMutation::<dyn Count>::new(|c| {
+                *c.count() += 1;
+                seen.push(*c.count());
+            })
+            .register(|n: &mut u32| n as &mut dyn Count)
+            .register(|p: &mut (u32, char)| p as &mut dyn Count);
+            assert_eq!(bump.len(), 2);
+            assert_eq!(Everywhere::new(&mut bump).transform(vec![(1u32, 'a')]), vec![(3, 'a')]);
+            let mut value = (1u64, 10u32);
+            bump.mutate(&mut value);
+            assert_eq!(value, (1, 10));
+        }
+        assert_eq!(seen, vec![2, 3]);
+        assert!(TraitMutation::<dyn Count>::new(|_| ()).is_empty());
+    }
+
+    #[test]
+    fn query_set() {
+        let mut defaults = 0;
+        {
+            let mut kind = QuerySet::new(|| {
+                defaults += 1;
+                "other".to_string()
+            })
+            .with(|n: &u32| format!("u32 {}", n))
+            .with(|s: &&'static str| format!("str {}", s))
+            .with(|n: &u32| format!("number {}", n));
+            assert_eq!(kind.len(), 2);
+            assert_eq!(kind.query(&1u32), "number 1");
+            assert_eq!(kind.query(&"x"), "str x");
+            assert_eq!(kind.query(&1u64), "other");
+            assert_eq!(kind.query(&vec![1u32]), "other");
+        }
+        assert_eq!(defaults, 2);
+        assert!(QuerySet::new(|| ()).is_empty());
+    }
+}
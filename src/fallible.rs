@@ -0,0 +1,532 @@
+use super::{
+    CastThrough, GenericPathedTransform, GenericQuery, GenericTransform, Path, PathedEverywhere,
+    Term,
+};
+use std::marker::PhantomData;
+
+/// A transformation that can fail. This is roughly equivalent to `for<T>
+/// FnMut(T) -> Result<T, (T, E)>`.
+///
+/// Like `GenericRewrite`, failing gives back the value it was called on, along
+/// with the error, so that a traversal can carry on without it.
+pub trait GenericTryTransform<E> {
+    /// Call the transform function on any `T`.
+    fn try_transform<T>(&mut self, t: T) -> Result<T, (T, E)>
+    where
+        T: Term;
+}
+
+impl<F, E> GenericTryTransform<E> for &mut F
+where
+    F: GenericTryTransform<E>,
+{
+    #[inline]
+    fn try_transform<T>(&mut self, t: T) -> Result<T, (T, E)>
+    where
+        T: Term,
+    {
+        (**self).try_transform(t)
+    }
+}
+
+/// A fallible transformation takes some value `U` and returns either a new,
+/// transformed version of it, or the original value and an error. It can be
+/// called on values of *any* type `T`, not just on values of type `U`, in
+/// which case it always succeeds and is simply the identity function.
+///
+/// This essentially lifts a `FnMut(U) -> Result<U, (U, E)>` into a `for<T>
+/// FnMut(T) -> Result<T, (T, E)>`.
+#[derive(Debug)]
+pub struct TryTransformation<F, U, E>
+where
+    F: FnMut(U) -> Result<U, (U, E)>,
+{
+    f: F,
+    phantom: PhantomData<fn(U) -> (U, E)>,
+}
+
+impl<F, U, E> TryTransformation<F, U, E>
+where
+    F: FnMut(U) -> Result<U, (U, E)>,
+{
+    /// Construct a new `TryTransformation` from the given function.
+    #[inline]
+    pub fn new(f: F) -> TryTransformation<F, U, E> {
+        TryTransformation {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U, E> GenericTryTransform<E> for TryTransformation<F, U, E>
+where
+    F: FnMut(U) -> Result<U, (U, E)>,
+{
+    #[inline]
+    fn try_transform<T>(&mut self, t: T) -> Result<T, (T, E)>
+    where
+        T: Term,
+    {
+        let f = &mut self.f;
+        match t.map_as(|u| match f(u) {
+            Ok(u) => (u, None),
+            Err((u, e)) => (u, Some(e)),
+        }) {
+            (t, Some(Some(e))) => Err((t, e)),
+            (t, _) => Ok(t),
+        }
+    }
+}
+
+/// Recursively perform a fallible transformation in a bottom up manner across
+/// a data structure, stopping at the first error.
+///
+/// Once the transformation fails, it isn't applied to any more values, and the
+/// traversal returns the error along with the data structure as rewritten up
+/// to that point. Call it with `try_transform` to get that error; as a plain
+/// `GenericTransform`, it stops at the first error but ignores it, and the
+/// next value it transforms starts afresh.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut double = EverywhereResult::new(TryTransformation::new(|n: u8| {
+///     n.checked_mul(2).ok_or((n, "overflow"))
+/// }));
+/// assert_eq!(double.try_transform(vec![1u8, 2]), Ok(vec![2, 4]));
+/// assert_eq!(double.try_transform(vec![1u8, 200, 3]), Err((vec![2, 200, 3], "overflow")));
+/// ```
+#[derive(Debug)]
+pub struct EverywhereResult<F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    f: F,
+    error: Option<E>,
+    depth: usize,
+}
+
+impl<F, E> EverywhereResult<F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    /// Construct a new fail fast transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> EverywhereResult<F, E> {
+        EverywhereResult {
+            f,
+            error: None,
+            depth: 0,
+        }
+    }
+
+    /// Get the underlying fallible transformation.
+    #[inline]
+    pub(crate) fn get_ref(&self) -> &F {
+        &self.f
+    }
+}
+
+impl<F, E> GenericTransform for EverywhereResult<F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        if self.depth == 0 {
+            self.error = None;
+        }
+        if self.error.is_some() {
+            return t;
+        }
+        self.depth += 1;
+        let t = t.map_one_transform(self);
+        self.depth -= 1;
+        if self.error.is_some() {
+            return t;
+        }
+        match self.f.try_transform(t) {
+            Ok(t) => t,
+            Err((t, e)) => {
+                self.error = Some(e);
+                t
+            }
+        }
+    }
+}
+
+impl<F, E> GenericTryTransform<E> for EverywhereResult<F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    #[inline]
+    fn try_transform<T>(&mut self, t: T) -> Result<T, (T, E)>
+    where
+        T: Term,
+    {
+        self.error = None;
+        let t = self.transform(t);
+        match self.error.take() {
+            Some(e) => Err((t, e)),
+            None => Ok(t),
+        }
+    }
+}
+
+/// Recursively perform a fallible transformation in a bottom up manner across
+/// a data structure, all or nothing.
+///
+/// If the transformation fails anywhere, the traversal returns the error along
+/// with the data structure exactly as it was, rather than partially rewritten.
+/// This clones the data structure up front to be able to do so.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut double = EverywhereTransaction::new(TryTransformation::new(|n: u8| {
+///     n.checked_mul(2).ok_or((n, "overflow"))
+/// }));
+/// assert_eq!(double.transform(vec![1u8, 2]), Ok(vec![2, 4]));
+/// assert_eq!(double.transform(vec![1u8, 200, 3]), Err((vec![1, 200, 3], "overflow")));
+/// ```
+#[derive(Debug)]
+pub struct EverywhereTransaction<F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    everywhere: EverywhereResult<F, E>,
+}
+
+impl<F, E> EverywhereTransaction<F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    /// Construct a new all or nothing transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> EverywhereTransaction<F, E> {
+        EverywhereTransaction {
+            everywhere: EverywhereResult::new(f),
+        }
+    }
+
+    /// Transform `t`, returning either the completely transformed value, or
+    /// the first error and the original value.
+    pub fn transform<T>(&mut self, t: T) -> Result<T, (T, E)>
+    where
+        T: Term + Clone,
+    {
+        let original = t.clone();
+        self.everywhere
+            .try_transform(t)
+            .map_err(|(_, e)| (original, e))
+    }
+}
+
+/// Recursively perform a fallible transformation in a bottom up manner across
+/// a complete data structure, carrying on after errors, and collect every error
+/// along with the path to the value it happened at.
+///
+/// Values the transformation fails on are left as they were, and the traversal
+/// goes on to the rest of the data structure, so that every problem is reported
+/// in one run.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut double = EverywhereErrors::new(TryTransformation::new(|n: u8| {
+///     n.checked_mul(2).ok_or((n, "overflow"))
+/// }));
+/// let (value, errors) = double.transform(vec![1u8, 200, 3, 250]);
+/// assert_eq!(value, vec![2, 200, 6, 250]);
+/// let errors: Vec<_> = errors.iter().map(|(path, e)| (path.to_string(), *e)).collect();
+/// assert_eq!(errors, vec![("[1]".to_string(), "overflow"), ("[3]".to_string(), "overflow")]);
+/// ```
+#[derive(Debug)]
+pub struct EverywhereErrors<F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    f: F,
+    phantom: PhantomData<fn() -> E>,
+}
+
+impl<F, E> EverywhereErrors<F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    /// Construct a new error collecting transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> EverywhereErrors<F, E> {
+        EverywhereErrors {
+            f,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Transform `t`, returning the transformed value and the errors, if any,
+    /// in the order they happened.
+    pub fn transform<T>(&mut self, t: T) -> (T, Vec<(Path, E)>)
+    where
+        T: Term,
+    {
+        let mut collect = CollectErrors {
+            f: &mut self.f,
+            errors: vec![],
+            phantom: PhantomData,
+        };
+        let t = PathedEverywhere::new(&mut collect).transform(t);
+        (t, collect.errors)
+    }
+}
+
+/// Applies a fallible transformation, collecting its errors.
+struct CollectErrors<'a, F: 'a, E> {
+    f: &'a mut F,
+    errors: Vec<(Path, E)>,
+    phantom: PhantomData<fn() -> E>,
+}
+
+impl<'a, F, E> GenericPathedTransform for CollectErrors<'a, F, E>
+where
+    F: GenericTryTransform<E>,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, path: &Path) -> T
+    where
+        T: Term,
+    {
+        match self.f.try_transform(t) {
+            Ok(t) => t,
+            Err((t, e)) => {
+                self.errors.push((path.clone(), e));
+                t
+            }
+        }
+    }
+}
+
+/// A fallible query creates either some value `R` or an error `E` from
+/// references to a `U`. It can be called on values of any type `T`, not just on
+/// values of type `U`, in which case it always succeeds with `R::default()`.
+///
+/// This essentially lifts a `FnMut(&U) -> Result<R, E>` into a `for<T>
+/// FnMut(&T) -> Result<R, E>`.
+#[derive(Debug)]
+pub struct TryQuery<Q, U, R, E>
+where
+    Q: FnMut(&U) -> Result<R, E>,
+    R: Default,
+{
+    query: Q,
+    phantom: PhantomData<fn(U) -> (R, E)>,
+}
+
+impl<Q, U, R, E> TryQuery<Q, U, R, E>
+where
+    Q: FnMut(&U) -> Result<R, E>,
+    R: Default,
+{
+    /// Construct a new `TryQuery` from the given function.
+    #[inline]
+    pub fn new(query: Q) -> TryQuery<Q, U, R, E> {
+        TryQuery {
+            query,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<Q, U, R, E> GenericQuery<Result<R, E>> for TryQuery<Q, U, R, E>
+where
+    Q: FnMut(&U) -> Result<R, E>,
+    R: Default,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> Result<R, E>
+    where
+        T: Term,
+    {
+        match t.view_as() {
+            Some(u) => (self.query)(u),
+            None => Ok(R::default()),
+        }
+    }
+}
+
+/// Recursively perform a fallible query in a top-down, left-to-right manner
+/// across a data structure, like `Everything`, stopping at the first error.
+///
+/// Once the query fails, nothing more is queried or descended into, and the
+/// traversal returns the error.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let parse = TryQuery::new(|s: &&str| s.parse::<u32>());
+/// let mut sum = EverythingResult::new(parse, |a, b| a + b);
+/// assert_eq!(sum.query(&vec!["1", "2"]), Ok(3));
+/// assert!(sum.query(&vec!["1", "two", "3"]).is_err());
+/// ```
+#[derive(Debug)]
+pub struct EverythingResult<Q, R, E, F>
+where
+    Q: GenericQuery<Result<R, E>>,
+    F: FnMut(R, R) -> R,
+{
+    q: Q,
+    fold: F,
+    phantom: PhantomData<fn(R) -> (R, E)>,
+}
+
+impl<Q, R, E, F> EverythingResult<Q, R, E, F>
+where
+    Q: GenericQuery<Result<R, E>>,
+    F: FnMut(R, R) -> R,
+{
+    /// Construct a new fail fast query traversal.
+    #[inline]
+    pub fn new(q: Q, fold: F) -> EverythingResult<Q, R, E, F> {
+        EverythingResult {
+            q,
+            fold,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<Q, R, E, F> GenericQuery<Result<R, E>> for EverythingResult<Q, R, E, F>
+where
+    Q: GenericQuery<Result<R, E>>,
+    F: FnMut(R, R) -> R,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> Result<R, E>
+    where
+        T: Term,
+    {
+        let mut fail_fast = FailFast {
+            q: &mut self.q,
+            fold: &mut self.fold,
+            failed: false,
+            phantom: PhantomData,
+        };
+        fail_fast
+            .query(t)
+            .map_err(|e| e.expect("the first error is never skipped"))
+    }
+}
+
+/// Queries and folds like `Everything` until the first error, resulting in
+/// `Err(None)` for each value skipped after it.
+struct FailFast<'a, Q: 'a, R, E, F: 'a> {
+    q: &'a mut Q,
+    fold: &'a mut F,
+    failed: bool,
+    phantom: PhantomData<fn(R) -> (R, E)>,
+}
+
+impl<'a, Q, R, E, F> GenericQuery<Result<R, Option<E>>> for FailFast<'a, Q, R, E, F>
+where
+    Q: GenericQuery<Result<R, E>>,
+    F: FnMut(R, R) -> R,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> Result<R, Option<E>>
+    where
+        T: Term,
+    {
+        if self.failed {
+            return Err(None);
+        }
+        let mut r = match self.q.query(t) {
+            Ok(r) => Some(Ok(r)),
+            Err(e) => {
+                self.failed = true;
+                return Err(Some(e));
+            }
+        };
+        t.map_one_query(self, |me, rr| {
+            r = Some(match (r.take().unwrap(), rr) {
+                (Ok(r), Ok(rr)) => Ok((me.fold)(r, rr)),
+                (Err(Some(e)), _) | (_, Err(Some(e))) => Err(Some(e)),
+                (Err(None), _) | (_, Err(None)) => Err(None),
+            });
+        });
+        r.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everywhere_result() {
+        let mut resolve = EverywhereResult::new(TryTransformation::new(|name: &'static str| {
+            match name {
+                "x" => Ok("1"),
+                "y" => Ok("2"),
+                _ => Err((name, format!("unresolved name `{}`", name))),
+            }
+        }));
+
+        assert_eq!(resolve.try_transform(("x", vec!["y"])), Ok(("1", vec!["2"])));
+        assert_eq!(
+            resolve.try_transform(vec!["x", "z", "y", "w"]),
+            Err((vec!["1", "z", "y", "w"], "unresolved name `z`".to_string()))
+        );
+        assert_eq!(resolve.try_transform(5u32), Ok(5));
+
+        // A failed plain transformation doesn't stop the next one.
+        assert_eq!(resolve.transform(vec!["z", "x"]), vec!["z", "x"]);
+        assert_eq!(resolve.transform(vec!["x", "y"]), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn everywhere_transaction() {
+        let mut sub = EverywhereTransaction::new(TryTransformation::new(|n: u32| {
+            n.checked_sub(1).ok_or((n, ()))
+        }));
+        assert_eq!(sub.transform((1u32, vec![2u32])), Ok((0, vec![1])));
+        assert_eq!(sub.transform((1u32, vec![0u32, 3])), Err(((1, vec![0, 3]), ())));
+    }
+
+    #[test]
+    fn everywhere_errors() {
+        let mut parse = EverywhereErrors::new(TryTransformation::new(|s: &'static str| {
+            s.parse::<u32>().map(|_| s).map_err(|e| (s, e.to_string()))
+        }));
+
+        let (value, errors) = parse.transform(vec![("1", "x"), ("2", "")]);
+        assert_eq!(value, vec![("1", "x"), ("2", "")]);
+        let errors: Vec<_> = errors.into_iter().map(|(path, _)| path.to_string()).collect();
+        assert_eq!(errors, vec!["[0][1]", "[1][1]"]);
+
+        let (_, errors) = parse.transform(("3", "4"));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn everything_result() {
+        let mut queried = vec![];
+        {
+            let check = TryQuery::new(|n: &u32| {
+                queried.push(*n);
+                if *n == 0 {
+                    Err("zero".to_string())
+                } else {
+                    Ok(1u32)
+                }
+            });
+            let mut count = EverythingResult::new(check, |a, b| a + b);
+            assert_eq!(count.query(&(1u32, vec![2u32])), Ok(2));
+            assert_eq!(count.query(&vec![vec![3u32, 0], vec![4]]), Err("zero".to_string()));
+            assert_eq!(count.query(&5u32), Ok(1));
+        }
+        assert_eq!(queried, vec![1, 2, 3, 0, 5]);
+    }
+}
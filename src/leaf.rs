@@ -0,0 +1,138 @@
+use super::{Cast, GenericMutate, GenericQuery, GenericTransform, Term};
+
+/// A value that is traversed as an atom: none of its children are ever visited.
+///
+/// Typed transformations, queries, and mutations on `U` see through a
+/// `Leaf<U>`, so they still apply to the value as a whole, just never to
+/// anything inside it. This is what `#[scrap(leaf)]` fields are traversed as,
+/// and since a `Leaf<T>` never looks inside its `T`, the `T` need not be a
+/// `Term` at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Leaf<T>(pub T);
+
+impl<T> Leaf<T> {
+    /// Wrap the given value.
+    #[inline]
+    pub fn new(value: T) -> Leaf<T> {
+        Leaf(value)
+    }
+
+    /// Unwrap the value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+
+    /// View a reference to a value as a reference to a `Leaf`.
+    #[inline]
+    pub fn from_ref(value: &T) -> &Leaf<T> {
+        // Safe because `Leaf` is `repr(transparent)` over `T`.
+        unsafe { &*(value as *const T as *const Leaf<T>) }
+    }
+
+    /// View a mutable reference to a value as a mutable reference to a `Leaf`.
+    #[inline]
+    pub fn from_mut(value: &mut T) -> &mut Leaf<T> {
+        // Safe because `Leaf` is `repr(transparent)` over `T`.
+        unsafe { &mut *(value as *mut T as *mut Leaf<T>) }
+    }
+}
+
+impl<T> Term for Leaf<T> {
+    #[inline(always)]
+    fn map_one_transform<F>(self, _: &mut F) -> Self
+    where
+        F: GenericTransform,
+    {
+        self
+    }
+
+    #[inline(always)]
+    fn map_one_query<Q, R, F>(&self, _: &mut Q, _: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+    }
+
+    #[inline(always)]
+    fn map_one_mutation<M, R, F>(&mut self, _: &mut M, _: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+    }
+}
+
+/// A specialization that lets a `Leaf<T>` be cast to the `T` inside it.
+impl<T> Cast<T> for Leaf<T> {
+    #[inline(always)]
+    fn cast(self) -> Result<T, Self> {
+        Ok(self.0)
+    }
+}
+
+/// A specialization that lets a `T` be cast back into a `Leaf<T>`.
+impl<T> Cast<Leaf<T>> for T {
+    #[inline(always)]
+    fn cast(self) -> Result<Leaf<T>, Self> {
+        Ok(Leaf(self))
+    }
+}
+
+impl<'a, T> Cast<&'a T> for &'a Leaf<T> {
+    #[inline(always)]
+    fn cast(self) -> Result<&'a T, Self> {
+        Ok(&self.0)
+    }
+}
+
+impl<'a, T> Cast<&'a mut T> for &'a mut Leaf<T> {
+    #[inline(always)]
+    fn cast(self) -> Result<&'a mut T, Self> {
+        Ok(&mut self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Everything, Everywhere, Query, Transformation};
+
+    #[test]
+    fn leaf() {
+        let mut reverse = Everywhere::new(Transformation::new(|mut v: Vec<u32>| {
+            v.reverse();
+            v
+        }));
+        let mut incr = Everywhere::new(Transformation::new(|n: u32| n + 1));
+        let mut count = Everything::new(Query::new(|_: &u32| 1), |a, b| a + b);
+
+        let v = (Leaf(vec![1u32, 2]), vec![3u32, 4]);
+        assert_eq!(count.query(&v), 2);
+        let v = incr.transform(v);
+        assert_eq!(v, (Leaf(vec![1, 2]), vec![4, 5]));
+        assert_eq!(reverse.transform(v), (Leaf(vec![2, 1]), vec![5, 4]));
+    }
+
+    #[test]
+    fn std_atoms() {
+        use std::net::{IpAddr, Ipv4Addr};
+        use std::num::NonZeroU32;
+        use std::path::PathBuf;
+        use std::time::Duration;
+
+        let value = (
+            vec![Duration::from_secs(1), Duration::from_secs(2)],
+            PathBuf::from("a"),
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            NonZeroU32::new(1).unwrap(),
+        );
+        let mut count = Everything::new(Query::new(|_: &Duration| 1), |a, b| a + b);
+        assert_eq!(count.query(&value), 2);
+
+        let mut double = Everywhere::new(Transformation::new(|d: Duration| d * 2));
+        assert_eq!(double.transform(value).0, vec![Duration::from_secs(2), Duration::from_secs(4)]);
+    }
+}
@@ -4,18 +4,127 @@
 //! combinators from the Haskell paper "Scrap Your Boilerplate: A Practical
 //! Design Pattern for Generic Programming" by Lämmel and Peyton Jones to Rust.
 //!
+//! An `Rc` or `Arc` has the value it points to as its one child. Transforming
+//! one by value unwraps it if it is the only reference to its value, and clones
+//! its value otherwise, so the result never shares anything with other
+//! references, even where nothing changed. Mutating one is copy on write, with
+//! `make_mut`, which also clones shared values, whether or not the mutation
+//! changes them. To keep unchanged values shared, use `EverywhereCow`, which
+//! only copies shared values that something within actually changes.
+//!
+//! `Cell`, `RefCell`, `Mutex`, and `RwLock` have their value as their one child.
+//! Transforming or mutating one needs no borrowing or locking, since it is
+//! owned or mutably borrowed already. Queries see a copy of a `Cell`'s value,
+//! so only `Cell`s of `Copy` values are terms. Querying a `RefCell` borrows it,
+//! and panics if it is already mutably borrowed, and querying a `Mutex` or
+//! `RwLock` locks it, so querying one that the same thread holds deadlocks.
+//! Poisoned locks are traversed just like any other.
+//!
+//! A `Cow`'s one child is its value as the owned type, such as a `String` for a
+//! `Cow<str>`, whether or not it is borrowed. Transforming or mutating a
+//! borrowed value traverses an owned copy of it, which then replaces the
+//! borrowed value only if it ended up different. Queries never copy a `Cow`'s
+//! value, and see it as a `CowView<B>` that derefs to the `&B`, such as a
+//! `&str`, whether it is borrowed or owned.
+//!
+//! Sets and heaps traverse their elements, but since elements can't be changed
+//! where they are without breaking the collection's invariants, both
+//! transforming and mutating one rebuilds it, merging elements that became
+//! equal. Maps traverse only their values, not their keys, so mutating a map
+//! changes its values in place, and transforming one never makes keys collide.
+//! To rewrite keys, rebuild the map with a typed transformation on the map type
+//! itself.
+//!
+//! Enable the `derive` cargo feature to get `#[derive(Term)]` re-exported from
+//! this crate, rather than depending on `scrapmetal-derive` directly, and the
+//! `async` cargo feature to get asynchronous transformations, with
+//! `AsyncGenericTransform` and `EverywhereAsync`. Enable the `rayon` cargo
+//! feature to get `ParEverything`, which queries large collections in parallel,
+//! and the `tracing` cargo feature to get `TracingObserver`, which reports what
+//! an `Instrumented` traversal does to the `tracing` framework.
+//!
 #![feature(specialization)]
+#![allow(incomplete_features)]
 #![deny(missing_docs)]
 #![deny(missing_debug_implementations)]
 
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "derive")]
+extern crate scrapmetal_derive;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+#[macro_use]
+mod macros;
+
+mod arena;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod cancel;
+mod cast;
+mod collect;
+mod cow;
+mod cursor;
+mod depth;
+mod dispatch;
+mod fallible;
+mod leaf;
+mod memo;
+mod meta;
 mod mutation;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod pass;
+mod path;
 mod query;
+mod reflect;
+mod remote;
+mod rewrite;
+mod rules;
+mod schedule;
+mod scope;
+mod step;
 mod term_impls;
+mod trace;
 mod transform;
+mod transparent;
+mod validate;
 
+pub use arena::*;
+#[cfg(feature = "async")]
+pub use asynchronous::*;
+pub use cancel::*;
+pub use cast::*;
+pub use collect::*;
+pub use cow::*;
+pub use cursor::*;
+pub use depth::*;
+pub use dispatch::*;
+pub use fallible::*;
+pub use leaf::*;
+pub use memo::*;
+pub use meta::*;
 pub use mutation::*;
+#[cfg(feature = "rayon")]
+pub use parallel::*;
+pub use pass::*;
+pub use path::*;
 pub use query::*;
+pub use reflect::*;
+pub use remote::*;
+pub use rewrite::*;
+pub use rules::*;
+pub use schedule::*;
+pub use scope::*;
+pub use step::*;
+pub use trace::*;
 pub use transform::*;
+pub use transparent::*;
+pub use validate::*;
+
+#[cfg(feature = "derive")]
+pub use scrapmetal_derive::{Term, TermMeta};
 
 /// Dynamically cast a value to a `T`.
 trait Cast<T>: Sized {
@@ -38,6 +147,123 @@ impl<T> Cast<T> for T {
     }
 }
 
+/// Statically check whether `Self=T`, without needing a value to cast.
+trait SameType<T> {
+    fn same_type() -> bool;
+}
+
+impl<T, U> SameType<T> for U {
+    #[inline(always)]
+    default fn same_type() -> bool {
+        false
+    }
+}
+
+impl<T> SameType<T> for T {
+    #[inline(always)]
+    fn same_type() -> bool {
+        true
+    }
+}
+
+/// Cast a value to a `U`, seeing through any `Transparent` wrappers around the
+/// `U`. This is what typed transformations, queries, and mutations use to
+/// decide whether they apply to a value.
+trait CastThrough<U>: Sized {
+    /// If this value is a `U`, or transparently wraps one, apply `f` to that
+    /// `U` and return its extra result.
+    fn map_as<X, F>(self, f: F) -> (Self, Option<X>)
+    where
+        F: FnOnce(U) -> (U, X);
+
+    /// Get a reference to the `U` this value is or transparently wraps.
+    fn view_as(&self) -> Option<&U>;
+
+    /// Get a mutable reference to the `U` this value is or transparently wraps.
+    fn view_as_mut(&mut self) -> Option<&mut U>;
+}
+
+impl<T, U> CastThrough<U> for T {
+    #[inline(always)]
+    default fn map_as<X, F>(self, f: F) -> (Self, Option<X>)
+    where
+        F: FnOnce(U) -> (U, X),
+    {
+        match Cast::<U>::cast(self) {
+            Ok(u) => {
+                let (u, x) = f(u);
+                match Cast::<T>::cast(u) {
+                    Ok(t) => (t, Some(x)),
+                    Err(_) => unreachable!(
+                        "If T=U, then U=T, and if T=Leaf<U>, then U casts back \
+                         into a Leaf<U>. Cast isn't pub, so there aren't any \
+                         future specializations that could wreck this for us."
+                    ),
+                }
+            }
+            Err(t) => (t, None),
+        }
+    }
+
+    #[inline(always)]
+    default fn view_as(&self) -> Option<&U> {
+        Cast::<&U>::cast(self).ok()
+    }
+
+    #[inline(always)]
+    default fn view_as_mut(&mut self) -> Option<&mut U> {
+        Cast::<&mut U>::cast(self).ok()
+    }
+}
+
+/// A specialization that looks inside `Transparent` wrappers when they aren't
+/// themselves a `U`.
+impl<T, U> CastThrough<U> for T
+where
+    T: Transparent,
+{
+    #[inline]
+    fn map_as<X, F>(self, f: F) -> (Self, Option<X>)
+    where
+        F: FnOnce(U) -> (U, X),
+    {
+        match Cast::<U>::cast(self) {
+            Ok(u) => {
+                let (u, x) = f(u);
+                match Cast::<T>::cast(u) {
+                    Ok(t) => (t, Some(x)),
+                    Err(_) => unreachable!("If T=U, then U=T"),
+                }
+            }
+            Err(t) => {
+                let mut x = None;
+                let t = t.map_inner(|inner| {
+                    let (inner, inner_x) = inner.map_as(f);
+                    x = inner_x;
+                    inner
+                });
+                (t, x)
+            }
+        }
+    }
+
+    #[inline]
+    fn view_as(&self) -> Option<&U> {
+        match Cast::<&U>::cast(self) {
+            Ok(u) => Some(u),
+            Err(t) => t.inner().view_as(),
+        }
+    }
+
+    #[inline]
+    fn view_as_mut(&mut self) -> Option<&mut U> {
+        match Cast::<&mut U>::cast(self) {
+            Ok(u) => Some(u),
+            Err(t) => t.inner_mut().view_as_mut(),
+        }
+    }
+}
+
 /// A `Term` is a value that can be mapped or queried.
 pub trait Term: Sized {
     /// Perform one-layer traversal and transformation of this value's direct
@@ -61,6 +287,46 @@ pub trait Term: Sized {
     where
         M: GenericMutate<R>,
         F: FnMut(&mut M, R);
+
+    /// Like `map_one_transform`, but visiting the direct children from last to
+    /// first.
+    ///
+    /// This defaults to visiting them in the usual first to last order, which
+    /// is right for terms with at most one child, and for unordered
+    /// collections.
+    #[inline]
+    fn map_one_transform_rev<F>(self, f: &mut F) -> Self
+    where
+        F: GenericTransform,
+    {
+        self.map_one_transform(f)
+    }
+
+    /// Like `map_one_query`, but visiting the direct children from last to
+    /// first.
+    ///
+    /// This defaults to visiting them in the usual first to last order.
+    #[inline]
+    fn map_one_query_rev<Q, R, F>(&self, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        self.map_one_query(query, each)
+    }
+
+    /// Like `map_one_mutation`, but visiting the direct children from last to
+    /// first.
+    ///
+    /// This defaults to visiting them in the usual first to last order.
+    #[inline]
+    fn map_one_mutation_rev<M, R, F>(&mut self, mutation: &mut M, each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        self.map_one_mutation(mutation, each)
+    }
 }
 
 #[cfg(test)]
@@ -71,5 +337,7 @@ mod tests {
     fn casting() {
         assert_eq!(Cast::<bool>::cast(1), Err(1));
         assert_eq!(Cast::<bool>::cast(true), Ok(true));
+        assert!(!<u32 as SameType<bool>>::same_type());
+        assert!(<bool as SameType<bool>>::same_type());
     }
 }
@@ -0,0 +1,468 @@
+/// Implement `Term` for a simple struct or enum without a proc-macro.
+///
+/// List the fields to traverse for structs, using indices for tuple
+/// structs. Any field that isn't listed is never traversed, and doesn't need to
+/// be a `Term`:
+///
+/// ```
+/// #[macro_use]
+/// extern crate scrapmetal;
+///
+/// struct Point {
+///     x: f64,
+///     y: f64,
+///     label: String,
+/// }
+/// impl_term!(Point { x, y });
+///
+/// struct Meters(f64);
+/// impl_term!(Meters { 0 });
+/// # fn main() {}
+/// ```
+///
+/// Enums must bind every field of every variant, giving a name to each field
+/// of tuple variants:
+///
+/// ```
+/// #[macro_use]
+/// extern crate scrapmetal;
+///
+/// enum Shape {
+///     Circle(f64),
+///     Rect { w: f64, h: f64 },
+///     Empty,
+/// }
+/// impl_term!(enum Shape {
+///     Circle(r),
+///     Rect { w, h },
+///     Empty,
+/// });
+/// # fn main() {}
+/// ```
+///
+/// Generic types get a `T: Term` bound on each of their type parameters:
+///
+/// ```
+/// #[macro_use]
+/// extern crate scrapmetal;
+///
+/// struct Wrapper<T>(T);
+/// impl_term!(Wrapper<T> { 0 });
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! impl_term {
+    // Internal rules that traverse a list of fields or bindings from last to
+    // first, by recursing on the rest of the list before the first element.
+    (@rev_transform $this:ident $f:ident [] ) => {};
+    (@rev_transform $this:ident $f:ident [ $head:tt $( $rest:tt )* ] ) => {
+        $crate::impl_term!(@rev_transform $this $f [ $( $rest )* ]);
+        $this.$head = $f.transform($this.$head);
+    };
+    (@rev_query $this:ident $q:ident $each:ident [] ) => {};
+    (@rev_query $this:ident $q:ident $each:ident [ $head:tt $( $rest:tt )* ] ) => {
+        $crate::impl_term!(@rev_query $this $q $each [ $( $rest )* ]);
+        let r = $q.query(&$this.$head);
+        $each($q, r);
+    };
+    (@rev_mutate $this:ident $m:ident $each:ident [] ) => {};
+    (@rev_mutate $this:ident $m:ident $each:ident [ $head:tt $( $rest:tt )* ] ) => {
+        $crate::impl_term!(@rev_mutate $this $m $each [ $( $rest )* ]);
+        let r = $m.mutate(&mut $this.$head);
+        $each($m, r);
+    };
+    (@rev_transform_bindings $f:ident [] ) => {};
+    (@rev_transform_bindings $f:ident [ $head:ident $( $rest:ident )* ] ) => {
+        $crate::impl_term!(@rev_transform_bindings $f [ $( $rest )* ]);
+        let $head = $f.transform($head);
+    };
+    (@rev_query_bindings $q:ident $each:ident [] ) => {};
+    (@rev_query_bindings $q:ident $each:ident [ $head:ident $( $rest:ident )* ] ) => {
+        $crate::impl_term!(@rev_query_bindings $q $each [ $( $rest )* ]);
+        let r = $q.query($head);
+        $each($q, r);
+    };
+    (@rev_mutate_bindings $m:ident $each:ident [] ) => {};
+    (@rev_mutate_bindings $m:ident $each:ident [ $head:ident $( $rest:ident )* ] ) => {
+        $crate::impl_term!(@rev_mutate_bindings $m $each [ $( $rest )* ]);
+        let r = $m.mutate($head);
+        $each($m, r);
+    };
+
+    ( $name:ident $( < $( $param:ident ),* > )* { $( $field:tt ),* $(,)* } ) => {
+        impl $( < $( $param ),* > )* $crate::Term for $name $( < $( $param ),* > )*
+        where
+            $( $( $param: $crate::Term, )* )*
+        {
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_transform<F>(self, f: &mut F) -> Self
+            where
+                F: $crate::GenericTransform,
+            {
+                let mut this = self;
+                $( this.$field = f.transform(this.$field); )*
+                this
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_query<Q, R, F>(&self, q: &mut Q, mut each: F)
+            where
+                Q: $crate::GenericQuery<R>,
+                F: FnMut(&mut Q, R),
+            {
+                $(
+                    let r = q.query(&self.$field);
+                    each(q, r);
+                )*
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_mutation<M, R, F>(&mut self, m: &mut M, mut each: F)
+            where
+                M: $crate::GenericMutate<R>,
+                F: FnMut(&mut M, R),
+            {
+                $(
+                    let r = m.mutate(&mut self.$field);
+                    each(m, r);
+                )*
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_transform_rev<F>(self, f: &mut F) -> Self
+            where
+                F: $crate::GenericTransform,
+            {
+                let mut this = self;
+                $crate::impl_term!(@rev_transform this f [ $( $field )* ]);
+                this
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_query_rev<Q, R, F>(&self, q: &mut Q, mut each: F)
+            where
+                Q: $crate::GenericQuery<R>,
+                F: FnMut(&mut Q, R),
+            {
+                let this = self;
+                $crate::impl_term!(@rev_query this q each [ $( $field )* ]);
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_mutation_rev<M, R, F>(&mut self, m: &mut M, mut each: F)
+            where
+                M: $crate::GenericMutate<R>,
+                F: FnMut(&mut M, R),
+            {
+                let this = self;
+                $crate::impl_term!(@rev_mutate this m each [ $( $field )* ]);
+            }
+        }
+    };
+
+    (
+        enum $name:ident $( < $( $param:ident ),* > )* {
+            $(
+                $variant:ident
+                $( ( $( $tuple:ident ),* ) )*
+                $( { $( $named:ident ),* } )*
+            ),* $(,)*
+        }
+    ) => {
+        impl $( < $( $param ),* > )* $crate::Term for $name $( < $( $param ),* > )*
+        where
+            $( $( $param: $crate::Term, )* )*
+        {
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_transform<F>(self, f: &mut F) -> Self
+            where
+                F: $crate::GenericTransform,
+            {
+                match self {
+                    $(
+                        $name::$variant
+                        $( ( $( $tuple ),* ) )*
+                        $( { $( $named ),* } )* => {
+                            $name::$variant
+                            $( ( $( f.transform($tuple) ),* ) )*
+                            $( { $( $named: f.transform($named) ),* } )*
+                        }
+                    )*
+                }
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_query<Q, R, F>(&self, q: &mut Q, mut each: F)
+            where
+                Q: $crate::GenericQuery<R>,
+                F: FnMut(&mut Q, R),
+            {
+                match *self {
+                    $(
+                        $name::$variant
+                        $( ( $( ref $tuple ),* ) )*
+                        $( { $( ref $named ),* } )* => {
+                            $( $(
+                                let r = q.query($tuple);
+                                each(q, r);
+                            )* )*
+                            $( $(
+                                let r = q.query($named);
+                                each(q, r);
+                            )* )*
+                        }
+                    )*
+                }
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_mutation<M, R, F>(&mut self, m: &mut M, mut each: F)
+            where
+                M: $crate::GenericMutate<R>,
+                F: FnMut(&mut M, R),
+            {
+                match *self {
+                    $(
+                        $name::$variant
+                        $( ( $( ref mut $tuple ),* ) )*
+                        $( { $( ref mut $named ),* } )* => {
+                            $( $(
+                                let r = m.mutate($tuple);
+                                each(m, r);
+                            )* )*
+                            $( $(
+                                let r = m.mutate($named);
+                                each(m, r);
+                            )* )*
+                        }
+                    )*
+                }
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_transform_rev<F>(self, f: &mut F) -> Self
+            where
+                F: $crate::GenericTransform,
+            {
+                match self {
+                    $(
+                        $name::$variant
+                        $( ( $( $tuple ),* ) )*
+                        $( { $( $named ),* } )* => {
+                            $crate::impl_term!(@rev_transform_bindings f [
+                                $( $( $tuple )* )* $( $( $named )* )*
+                            ]);
+                            $name::$variant
+                            $( ( $( $tuple ),* ) )*
+                            $( { $( $named ),* } )*
+                        }
+                    )*
+                }
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_query_rev<Q, R, F>(&self, q: &mut Q, mut each: F)
+            where
+                Q: $crate::GenericQuery<R>,
+                F: FnMut(&mut Q, R),
+            {
+                match *self {
+                    $(
+                        $name::$variant
+                        $( ( $( ref $tuple ),* ) )*
+                        $( { $( ref $named ),* } )* => {
+                            $crate::impl_term!(@rev_query_bindings q each [
+                                $( $( $tuple )* )* $( $( $named )* )*
+                            ]);
+                        }
+                    )*
+                }
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            #[allow(unused_variables)]
+            fn map_one_mutation_rev<M, R, F>(&mut self, m: &mut M, mut each: F)
+            where
+                M: $crate::GenericMutate<R>,
+                F: FnMut(&mut M, R),
+            {
+                match *self {
+                    $(
+                        $name::$variant
+                        $( ( $( ref mut $tuple ),* ) )*
+                        $( { $( ref mut $named ),* } )* => {
+                            $crate::impl_term!(@rev_mutate_bindings m each [
+                                $( $( $tuple )* )* $( $( $named )* )*
+                            ]);
+                        }
+                    )*
+                }
+            }
+        }
+    };
+}
+
+/// Implement `Term` for types that traversals should treat as atoms, without
+/// any children.
+///
+/// Typed transformations, queries, and mutations still apply to atoms as a
+/// whole; they just never look inside them, so their fields don't need to be
+/// terms. The types must be `'static`, so that typed transformations and
+/// queries can tell them apart by type alone.
+///
+/// ```
+/// #[macro_use]
+/// extern crate scrapmetal;
+///
+/// use scrapmetal::*;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Symbol(u32);
+/// #[derive(Debug, PartialEq)]
+/// struct Span(usize, usize);
+/// leaf!(Symbol, Span);
+///
+/// # fn main() {
+/// let mut rename = Everywhere::new(Transformation::new(|s: Symbol| Symbol(s.0 + 1)));
+/// assert_eq!(rename.transform((Symbol(1), Span(0, 2))), (Symbol(2), Span(0, 2)));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! leaf {
+    ( $( $name:ty ),+ $(,)* ) => {
+        $( impl $crate::Term for $name where $name: 'static {
+            #[inline(always)]
+            fn map_one_transform<F>(self, _: &mut F) -> Self
+            where
+                F: $crate::GenericTransform,
+            {
+                self
+            }
+
+            #[inline(always)]
+            fn map_one_query<Q, R, F>(&self, _: &mut Q, _: F)
+            where
+                Q: $crate::GenericQuery<R>,
+                F: FnMut(&mut Q, R),
+            {
+            }
+
+            #[inline(always)]
+            fn map_one_mutation<M, R, F>(&mut self, _: &mut M, _: F)
+            where
+                M: $crate::GenericMutate<R>,
+                F: FnMut(&mut M, R),
+            {
+            }
+        } )+
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: u32,
+        y: u32,
+        label: &'static str,
+    }
+    impl_term!(Point { x, y });
+
+    #[derive(Debug, PartialEq)]
+    struct Wrapper<T>(T, u32);
+    impl_term!(Wrapper<T> { 0 });
+
+    #[derive(Debug, PartialEq)]
+    enum Shape {
+        Circle(u32),
+        Rect { w: u32, h: Box<Shape> },
+        Empty,
+    }
+    impl_term!(enum Shape {
+        Circle(r),
+        Rect { w, h },
+        Empty,
+    });
+
+    #[test]
+    fn impl_term_macro() {
+        let mut incr = Everywhere::new(Transformation::new(|n: u32| n + 1));
+        let mut count = Everything::new(Query::new(|_: &u32| 1), |a, b| a + b);
+
+        let point = Point {
+            x: 1,
+            y: 2,
+            label: "p",
+        };
+        assert_eq!(count.query(&point), 2);
+        let point = Wrapper(point, 10);
+        assert_eq!(
+            incr.transform(point),
+            Wrapper(
+                Point {
+                    x: 2,
+                    y: 3,
+                    label: "p",
+                },
+                10
+            )
+        );
+
+        let mut shape = Shape::Rect {
+            w: 1,
+            h: Box::new(Shape::Circle(2)),
+        };
+        let mut zero = MutateEverything::new(Mutation::new(|n: &mut u32| *n = 0));
+        zero.mutate(&mut shape);
+        assert_eq!(count.query(&shape), 2);
+        assert_eq!(
+            incr.transform(shape),
+            Shape::Rect {
+                w: 1,
+                h: Box::new(Shape::Circle(1)),
+            }
+        );
+        assert_eq!(incr.transform(Shape::Empty), Shape::Empty);
+
+        let mut rev = Everything::new_rev(Query::new(|n: &u32| vec![*n]), |mut a, b| {
+            a.extend(b);
+            a
+        });
+        let point = Point {
+            x: 1,
+            y: 2,
+            label: "p",
+        };
+        assert_eq!(rev.query(&point), vec![2, 1]);
+        let shape = Shape::Rect {
+            w: 1,
+            h: Box::new(Shape::Circle(2)),
+        };
+        assert_eq!(rev.query(&shape), vec![2, 1]);
+    }
+}
@@ -0,0 +1,164 @@
+use super::{GenericQuery, Term};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Get the address of the value an `Rc` or `Arc` shares, if this is one.
+trait SharedAddress {
+    fn shared_address(&self) -> Option<usize>;
+}
+
+impl<T> SharedAddress for T {
+    #[inline(always)]
+    default fn shared_address(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<T> SharedAddress for Rc<T> {
+    #[inline(always)]
+    fn shared_address(&self) -> Option<usize> {
+        Some(Rc::as_ptr(self) as usize)
+    }
+}
+
+impl<T> SharedAddress for Arc<T> {
+    #[inline(always)]
+    fn shared_address(&self) -> Option<usize> {
+        Some(Arc::as_ptr(self) as usize)
+    }
+}
+
+/// Recursively perform a query in a top-down, left-to-right manner across a
+/// data structure, like `Everything`, but only once for each value shared by
+/// `Rc`s or `Arc`s.
+///
+/// The result for each `Rc` or `Arc` is cached by the address of the value it
+/// shares, and other references to the same value reuse that result rather
+/// than traversing it again. This makes querying data structures with many
+/// shared subtrees, such as DAGs, take time proportional to the number of
+/// distinct values, rather than to the number of paths to them. The cache is
+/// emptied at the start of each query.
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::rc::Rc;
+///
+/// let shared = Rc::new(vec![1u32, 2, 3]);
+/// let value = vec![shared.clone(), shared.clone(), shared];
+///
+/// let mut queried = 0;
+/// let mut sum = MemoEverything::new(
+///     Query::new(|n: &u32| {
+///         queried += 1;
+///         *n
+///     }),
+///     |a, b| a + b,
+/// );
+/// assert_eq!(sum.query(&value), 18);
+/// drop(sum);
+/// assert_eq!(queried, 3);
+/// ```
+#[derive(Debug)]
+pub struct MemoEverything<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+{
+    q: Q,
+    fold: F,
+    cache: HashMap<usize, R>,
+    depth: usize,
+    phantom: PhantomData<fn(R, R) -> R>,
+}
+
+impl<Q, R, F> MemoEverything<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+    R: Clone,
+{
+    /// Construct a new `MemoEverything` query traversal.
+    #[inline]
+    pub fn new(q: Q, fold: F) -> MemoEverything<Q, R, F> {
+        MemoEverything {
+            q,
+            fold,
+            cache: HashMap::new(),
+            depth: 0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<Q, R, F> GenericQuery<R> for MemoEverything<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+    R: Clone,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> R
+    where
+        T: Term,
+    {
+        if self.depth == 0 {
+            self.cache.clear();
+        }
+        let address = t.shared_address();
+        if let Some(r) = address.and_then(|a| self.cache.get(&a)) {
+            return r.clone();
+        }
+
+        let mut r = Some(self.q.query(t));
+        self.depth += 1;
+        t.map_one_query(self, |me, rr| {
+            r = Some((me.fold)(r.take().unwrap(), rr));
+        });
+        self.depth -= 1;
+        let r = r.unwrap();
+
+        if let Some(address) = address {
+            self.cache.insert(address, r.clone());
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Everything, Query};
+
+    #[derive(Clone)]
+    enum Dag {
+        Leaf(u32),
+        Node(Arc<Dag>, Arc<Dag>),
+    }
+    impl_term!(enum Dag {
+        Leaf(n),
+        Node(left, right),
+    });
+
+    #[test]
+    fn memo_everything() {
+        // A DAG with 2^20 paths from the root to its one leaf.
+        let mut dag = Arc::new(Dag::Leaf(1));
+        for _ in 0..20 {
+            dag = Arc::new(Dag::Node(dag.clone(), dag));
+        }
+
+        let leaves = || Query::new(|d: &Dag| match *d {
+            Dag::Leaf(n) => n,
+            Dag::Node(..) => 0,
+        });
+        let mut memo = MemoEverything::new(leaves(), |a, b| a + b);
+        assert_eq!(memo.query(&dag), 1 << 20);
+        assert_eq!(memo.query(&dag), 1 << 20);
+
+        let small = Arc::new(Dag::Node(Arc::new(Dag::Leaf(2)), Arc::new(Dag::Leaf(3))));
+        let mut plain = Everything::new(leaves(), |a, b| a + b);
+        assert_eq!(memo.query(&small), plain.query(&small));
+    }
+}
@@ -0,0 +1,23 @@
+use super::Term;
+
+/// Names for a `Term`'s type, its variant, and each of its direct children.
+///
+/// This is implemented by `#[derive(TermMeta)]`, and lets diagnostics and
+/// tooling built on top of traversals name the locations they touch.
+pub trait TermMeta: Term {
+    /// The name of this value's type, for example `"Expr"`.
+    fn type_name(&self) -> &'static str;
+
+    /// The name of the enum variant that this value is, or `None` if its type
+    /// is not an enum.
+    fn variant_name(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// The names of this value's direct children, in the same order that the
+    /// `Term::map_one_*` methods visit them. Tuple fields are named by their
+    /// index.
+    fn child_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+}
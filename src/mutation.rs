@@ -1,5 +1,6 @@
-use super::{Cast, Term};
+use super::{CastThrough, Term};
 use std::marker::PhantomData;
+use std::mem;
 
 /// A similar work around as `GenericTransform`, but mutating in place and
 /// optionally returning some query type, rather than taking `self` and
@@ -13,6 +14,19 @@ pub trait GenericMutate<R> {
         T: Term;
 }
 
+impl<M, R> GenericMutate<R> for &mut M
+where
+    M: GenericMutate<R>,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T) -> R
+    where
+        T: Term,
+    {
+        (**self).mutate(t)
+    }
+}
+
 /// A mutation creates some value `R` from mutable references to a `U`. It can
 /// be called on values of any type `T`, not just on values of type `U`, so it
 /// requires a default `R` value for when it is called on values which are not a
@@ -74,9 +88,9 @@ where
     where
         T: Term,
     {
-        match Cast::<&mut U>::cast(t) {
-            Ok(u) => (self.mutation)(u),
-            Err(_) => (self.make_default)(),
+        match t.view_as_mut() {
+            Some(u) => (self.mutation)(u),
+            None => (self.make_default)(),
         }
     }
 }
@@ -93,6 +107,7 @@ where
 {
     m: M,
     fold: F,
+    rev: bool,
     phantom: PhantomData<fn(R, R) -> R>,
 }
 
@@ -107,11 +122,27 @@ where
         MutateEverything {
             m,
             fold,
+            rev: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Construct a new `MutateEverything` query traversal that goes
+    /// right-to-left, visiting each value's children from last to first.
+    #[inline]
+    pub fn with_query_rev(m: M, fold: F) -> MutateEverything<M, R, F> {
+        MutateEverything {
+            m,
+            fold,
+            rev: true,
             phantom: PhantomData,
         }
     }
 }
 
+#[inline(always)]
+fn fold_unit(_: (), _: ()) {}
+
 impl<M> MutateEverything<M, (), fn((), ())>
 where
     M: GenericMutate<()>,
@@ -119,13 +150,14 @@ where
     /// Construct a new `MutateEverything` query traversal.
     #[inline]
     pub fn new(m: M) -> MutateEverything<M, (), fn((), ())> {
-        #[inline(always)]
-        fn fold(_: (), _: ()) {}
-        MutateEverything {
-            m,
-            fold,
-            phantom: PhantomData,
-        }
+        MutateEverything::with_query(m, fold_unit)
+    }
+
+    /// Construct a new `MutateEverything` query traversal that goes
+    /// right-to-left, visiting each value's children from last to first.
+    #[inline]
+    pub fn new_rev(m: M) -> MutateEverything<M, (), fn((), ())> {
+        MutateEverything::with_query_rev(m, fold_unit)
     }
 }
 
@@ -140,13 +172,190 @@ where
         T: Term,
     {
         let mut r = Some(self.m.mutate(t));
-        t.map_one_mutation(self, |me, rr| {
+        let each = |me: &mut Self, rr| {
             r = Some((me.fold)(r.take().unwrap(), rr));
-        });
+        };
+        if self.rev {
+            t.map_one_mutation_rev(self, each);
+        } else {
+            t.map_one_mutation(self, each);
+        }
         r.unwrap()
     }
 }
 
+/// Recursively mutate a complete data structure in place, in a bottom up
+/// manner, mutating each value's children before the value itself.
+///
+/// This is the in-place counterpart of `Everywhere`: rather than moving each
+/// value out and rebuilding the data structure around the results, it hands the
+/// mutation a `&mut U` to each value where it lies, via
+/// `Term::map_one_mutation`, so a few changed leaves in a big `Vec` or
+/// `HashMap` don't cost reconstructing the whole thing.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut names = vec![vec!["a", "b"], vec!["c"]];
+/// let storage = names[0].as_ptr();
+///
+/// let mut upper = EverywhereMut::new(Mutation::new(|s: &mut &'static str| {
+///     if *s == "b" {
+///         *s = "B";
+///     }
+/// }));
+/// upper.mutate(&mut names);
+/// assert_eq!(names, vec![vec!["a", "B"], vec!["c"]]);
+/// assert_eq!(names[0].as_ptr(), storage);
+/// ```
+#[derive(Debug)]
+pub struct EverywhereMut<M>
+where
+    M: GenericMutate<()>,
+{
+    m: M,
+    rev: bool,
+}
+
+impl<M> EverywhereMut<M>
+where
+    M: GenericMutate<()>,
+{
+    /// Construct a new in-place mutation traversal.
+    #[inline]
+    pub fn new(m: M) -> EverywhereMut<M> {
+        EverywhereMut { m, rev: false }
+    }
+
+    /// Construct a new in-place mutation traversal that visits each value's
+    /// children from last to first, using `Term::map_one_mutation_rev`.
+    #[inline]
+    pub fn new_rev(m: M) -> EverywhereMut<M> {
+        EverywhereMut { m, rev: true }
+    }
+}
+
+impl<M> GenericMutate<()> for EverywhereMut<M>
+where
+    M: GenericMutate<()>,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        if self.rev {
+            t.map_one_mutation_rev(self, |_, ()| {});
+        } else {
+            t.map_one_mutation(self, |_, ()| {});
+        }
+        self.m.mutate(t)
+    }
+}
+
+/// Run a by-value rewrite on a value behind a `&mut` reference, by temporarily
+/// swapping `placeholder()` into its place.
+///
+/// If `f` panics, the placeholder is left behind, so the reference always
+/// points at a valid value.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut name = Box::new("x");
+/// replace_with(&mut name, || Box::new(""), |b| Box::new(if *b == "x" { "y" } else { *b }));
+/// assert_eq!(*name, "y");
+/// ```
+#[inline]
+pub fn replace_with<U, P, F>(u: &mut U, placeholder: P, f: F)
+where
+    P: FnOnce() -> U,
+    F: FnOnce(U) -> U,
+{
+    let taken = mem::replace(u, placeholder());
+    *u = f(taken);
+}
+
+/// A take mutation bridges a by-value `FnMut(U) -> U` to in-place traversals:
+/// on each `&mut U`, it takes the value out, leaving a placeholder, rewrites
+/// it, and puts the result back. It can be called on values of *any* type `T`,
+/// not just on values of type `U`, in which case it leaves them alone.
+///
+/// This lifts a `FnMut(U) -> U` into a `for<T> FnMut(&mut T)`, for use with
+/// `EverywhereMut` and `MutateEverything`.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut value = vec![vec![3u32, 1, 2], vec![5, 4]];
+/// let sort = TakeMutation::new(|mut v: Vec<u32>| {
+///     v.sort();
+///     v
+/// });
+/// EverywhereMut::new(sort).mutate(&mut value);
+/// assert_eq!(value, vec![vec![1, 2, 3], vec![4, 5]]);
+/// ```
+#[derive(Debug)]
+pub struct TakeMutation<F, U, P>
+where
+    F: FnMut(U) -> U,
+    P: FnMut() -> U,
+{
+    f: F,
+    placeholder: P,
+    phantom: PhantomData<fn(U) -> U>,
+}
+
+impl<F, U> TakeMutation<F, U, fn() -> U>
+where
+    F: FnMut(U) -> U,
+    U: Default,
+{
+    /// Construct a new `TakeMutation`, leaving `U::default()` in each value's
+    /// place while it is rewritten.
+    #[inline]
+    pub fn new(f: F) -> TakeMutation<F, U, fn() -> U> {
+        TakeMutation {
+            f,
+            placeholder: Default::default,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U, P> TakeMutation<F, U, P>
+where
+    F: FnMut(U) -> U,
+    P: FnMut() -> U,
+{
+    /// Construct a new `TakeMutation`, leaving `placeholder()` in each value's
+    /// place while it is rewritten.
+    #[inline]
+    pub fn with_placeholder(placeholder: P, f: F) -> TakeMutation<F, U, P> {
+        TakeMutation {
+            f,
+            placeholder,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U, P> GenericMutate<()> for TakeMutation<F, U, P>
+where
+    F: FnMut(U) -> U,
+    P: FnMut() -> U,
+{
+    #[inline]
+    fn mutate<T>(&mut self, t: &mut T)
+    where
+        T: Term,
+    {
+        if let Some(u) = t.view_as_mut() {
+            replace_with(u, &mut self.placeholder, &mut self.f);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,4 +373,67 @@ mod tests {
         let mut v = vec![1, 2, 3];
         assert_eq!(set_char_to_a.mutate(&mut v), 0);
     }
+
+    #[test]
+    fn everywhere_mut() {
+        // Each vector's length is counted after its elements are doubled.
+        let mut seen = vec![];
+        {
+            let double = Mutation::new(|n: &mut u32| *n *= 2);
+            let record = Mutation::new(|v: &mut Vec<u32>| seen.push(v.clone()));
+            let mut both = EverywhereMut::new(Both(double, record));
+            let mut value = (1u32, vec![2u32, 3]);
+            both.mutate(&mut value);
+            assert_eq!(value, (2, vec![4, 6]));
+        }
+        assert_eq!(seen, vec![vec![4, 6]]);
+
+        let mut order = vec![];
+        {
+            let mut record = EverywhereMut::new_rev(Mutation::new(|n: &mut u32| order.push(*n)));
+            record.mutate(&mut vec![1u32, 2, 3]);
+        }
+        assert_eq!(order, vec![3, 2, 1]);
+    }
+
+    /// Applies two mutations in turn.
+    struct Both<A, B>(A, B);
+
+    impl<A, B> GenericMutate<()> for Both<A, B>
+    where
+        A: GenericMutate<()>,
+        B: GenericMutate<()>,
+    {
+        fn mutate<T>(&mut self, t: &mut T)
+        where
+            T: Term,
+        {
+            self.0.mutate(t);
+            self.1.mutate(t);
+        }
+    }
+
+    #[test]
+    fn take_mutation() {
+        let mut value = (vec![1u32, 2], 3u32);
+        let mut double = TakeMutation::new(|v: Vec<u32>| v.into_iter().map(|n| n * 2).collect());
+        EverywhereMut::new(&mut double).mutate(&mut value);
+        assert_eq!(value, (vec![2, 4], 3));
+
+        let mut placeholders = 0;
+        {
+            let mut wrap = TakeMutation::with_placeholder(
+                || {
+                    placeholders += 1;
+                    Box::new('?')
+                },
+                |b: Box<char>| Box::new(b.to_ascii_uppercase()),
+            );
+            let mut value = vec![Box::new('a'), Box::new('b')];
+            wrap.mutate(&mut value);
+            EverywhereMut::new(&mut wrap).mutate(&mut value);
+            assert_eq!(value, vec![Box::new('A'), Box::new('B')]);
+        }
+        assert_eq!(placeholders, 2);
+    }
 }
@@ -0,0 +1,168 @@
+use super::{GenericQuery, Term};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+/// Recursively perform a query across a data structure, like `Everything`, but
+/// splitting the elements of large collections across rayon's thread pool. The
+/// `F: Fn(R, R) -> R` joining results must be associative, so that results can
+/// be joined in whichever grouping the threads finish in. They are always
+/// joined in left-to-right order, though, so the result is the same as
+/// `Everything`'s.
+///
+/// The elements of `Vec`s and `VecDeque`s of `Sync` values are queried in
+/// parallel, each with a clone of the query. The children of other values are
+/// queried one after another, since a traversal can't tell how long it may
+/// hold on to a reference to an arbitrary child, such as the value in a
+/// `RefCell`. Collections of `Sync` values within them are split across the
+/// thread pool again, however deeply they are nested.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let values: Vec<_> = (0..1000u32).map(|n| (n, vec![n])).collect();
+/// let mut sum = ParEverything::new(Query::new(|n: &u32| u64::from(*n)), |a, b| a + b);
+/// assert_eq!(sum.query(&values), 999 * 1000);
+/// ```
+#[derive(Debug)]
+pub struct ParEverything<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: Fn(R, R) -> R,
+{
+    q: Q,
+    fold: F,
+    phantom: PhantomData<fn(R, R) -> R>,
+}
+
+impl<Q, R, F> ParEverything<Q, R, F>
+where
+    Q: GenericQuery<R>,
+    F: Fn(R, R) -> R,
+{
+    /// Construct a new `ParEverything` query traversal.
+    #[inline]
+    pub fn new(q: Q, fold: F) -> ParEverything<Q, R, F> {
+        ParEverything {
+            q,
+            fold,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<Q, R, F> Clone for ParEverything<Q, R, F>
+where
+    Q: Clone + GenericQuery<R>,
+    F: Clone + Fn(R, R) -> R,
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        ParEverything::new(self.q.clone(), self.fold.clone())
+    }
+}
+
+impl<Q, R, F> GenericQuery<R> for ParEverything<Q, R, F>
+where
+    Q: GenericQuery<R> + Clone + Send,
+    R: Send,
+    F: Fn(R, R) -> R + Clone + Send + Sync,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> R
+    where
+        T: Term,
+    {
+        let r = self.q.query(t);
+        match ParChildren::par_query_children(t, self) {
+            Some(rr) => (self.fold)(r, rr),
+            None => {
+                let mut r = Some(r);
+                t.map_one_query(self, |me, rr| {
+                    r = Some((me.fold)(r.take().unwrap(), rr));
+                });
+                r.unwrap()
+            }
+        }
+    }
+}
+
+/// Queries the children of values whose children can be shared across threads
+/// in parallel.
+trait ParChildren {
+    /// Query each child with a clone of `e`, in parallel, and join their
+    /// results, or return `None` if this value's children can't be queried in
+    /// parallel, or it doesn't have any.
+    fn par_query_children<Q, R, F>(&self, e: &ParEverything<Q, R, F>) -> Option<R>
+    where
+        Q: GenericQuery<R> + Clone + Send,
+        R: Send,
+        F: Fn(R, R) -> R + Clone + Send + Sync;
+}
+
+impl<T> ParChildren for T {
+    #[inline]
+    default fn par_query_children<Q, R, F>(&self, _: &ParEverything<Q, R, F>) -> Option<R>
+    where
+        Q: GenericQuery<R> + Clone + Send,
+        R: Send,
+        F: Fn(R, R) -> R + Clone + Send + Sync,
+    {
+        None
+    }
+}
+
+macro_rules! impl_par_children {
+    ($collection:ident) => {
+        impl<T> ParChildren for $collection<T>
+        where
+            T: Term + Sync,
+        {
+            #[inline]
+            fn par_query_children<Q, R, F>(&self, e: &ParEverything<Q, R, F>) -> Option<R>
+            where
+                Q: GenericQuery<R> + Clone + Send,
+                R: Send,
+                F: Fn(R, R) -> R + Clone + Send + Sync,
+            {
+                self.par_iter()
+                    .map_with(e.clone(), |e, t| e.query(t))
+                    .reduce_with(&e.fold)
+            }
+        }
+    };
+}
+
+impl_par_children!(Vec);
+impl_par_children!(VecDeque);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Everything, Query};
+    use std::cell::RefCell;
+
+    #[test]
+    fn par_everything() {
+        let values: Vec<_> = (0..100u32).map(|n| vec![(n, 'a')]).collect();
+        let count = |c| ParEverything::new(Query::new(move |x: &char| usize::from(*x == c)), |a, b| a + b);
+        assert_eq!(count('a').query(&values), 100);
+        assert_eq!(count('b').query(&values), 0);
+        assert_eq!(count('a').query(&Vec::<char>::new()), 0);
+
+        // Results are joined in the same order as `Everything` joins them.
+        let values: VecDeque<_> = (0..100u32).map(|n| (n, vec![n * 2])).collect();
+        let collect = || Query::or_else(Vec::new, |n: &u32| vec![*n]);
+        let append = |mut a: Vec<u32>, b| {
+            a.extend(b);
+            a
+        };
+        let expected = Everything::new(collect(), append).query(&values);
+        assert_eq!(ParEverything::new(collect(), append).query(&values), expected);
+
+        // The children of values that aren't `Sync` are queried in turn.
+        let cells = vec![RefCell::new(vec![1u32, 2]), RefCell::new(vec![3])];
+        let mut sum = ParEverything::new(Query::or_else(|| 0, |n: &u32| *n), |a, b| a + b);
+        assert_eq!(sum.query(&cells), 6);
+    }
+}
@@ -0,0 +1,147 @@
+use super::dispatch::erased_type_id;
+use super::{Direction, GenericTransform, Term, TransformationSet};
+use std::any::TypeId;
+use std::collections::HashSet;
+
+/// A whole transformation pass over a data structure, declared fluently with a
+/// `PassBuilder`.
+///
+/// A pass applies a typed transformation to each value of the types it has
+/// one for, traversing either bottom up, like `Everywhere`, or top down, like
+/// `EverywhereTopDown`. Values of pruned types are left alone entirely: the
+/// pass neither transforms them nor descends into them.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut pass = Pass::bottom_up()
+///     .on::<u32>(|n| n + 1)
+///     .on::<char>(|c| c.to_ascii_uppercase())
+///     .prune::<Vec<char>>()
+///     .build();
+///
+/// let value = pass.transform((1u32, vec!['a'], ('b', vec![2u32])));
+/// assert_eq!(value, (2, vec!['a'], ('B', vec![3])));
+/// ```
+#[derive(Debug)]
+pub struct Pass<'f> {
+    direction: Direction,
+    transformations: TransformationSet<'f>,
+    pruned: HashSet<TypeId>,
+}
+
+impl<'f> Pass<'f> {
+    /// Start declaring a pass that transforms each value after its children.
+    #[inline]
+    pub fn bottom_up() -> PassBuilder<'f> {
+        PassBuilder::new(Direction::BottomUp)
+    }
+
+    /// Start declaring a pass that transforms each value before its children.
+    #[inline]
+    pub fn top_down() -> PassBuilder<'f> {
+        PassBuilder::new(Direction::TopDown)
+    }
+}
+
+impl<'f> GenericTransform for Pass<'f> {
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        if !self.pruned.is_empty() && self.pruned.contains(&erased_type_id::<T>()) {
+            return t;
+        }
+        match self.direction {
+            Direction::BottomUp => {
+                let t = t.map_one_transform(self);
+                self.transformations.transform(t)
+            }
+            Direction::TopDown => {
+                let t = self.transformations.transform(t);
+                t.map_one_transform(self)
+            }
+        }
+    }
+}
+
+/// Declares a `Pass` one typed transformation or pruned type at a time. See
+/// `Pass::bottom_up` and `Pass::top_down`.
+#[derive(Debug)]
+pub struct PassBuilder<'f> {
+    pass: Pass<'f>,
+}
+
+impl<'f> PassBuilder<'f> {
+    /// Construct a new builder of a pass traversing in the given direction.
+    #[inline]
+    pub fn new(direction: Direction) -> PassBuilder<'f> {
+        PassBuilder {
+            pass: Pass {
+                direction,
+                transformations: TransformationSet::new(),
+                pruned: HashSet::new(),
+            },
+        }
+    }
+
+    /// Transform values of type `U` with `f`, replacing any transformation the
+    /// pass already has for them.
+    #[inline]
+    pub fn on<U>(mut self, f: impl 'f + FnMut(U) -> U) -> PassBuilder<'f>
+    where
+        U: 'static,
+    {
+        self.pass.transformations = self.pass.transformations.with(f);
+        self
+    }
+
+    /// Leave values of type `U`, and everything within them, alone.
+    #[inline]
+    pub fn prune<U>(mut self) -> PassBuilder<'f>
+    where
+        U: 'static,
+    {
+        self.pass.pruned.insert(TypeId::of::<U>());
+        self
+    }
+
+    /// Finish declaring the pass.
+    #[inline]
+    pub fn build(self) -> Pass<'f> {
+        self.pass
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pass() {
+        let value = vec![vec![1u32, 2], vec![3]];
+
+        let mut order = vec![];
+        {
+            let mut pass = Pass::top_down()
+                .on::<Vec<u32>>(|v| {
+                    order.push(v.len());
+                    v.into_iter().map(|n| n * 10).collect()
+                })
+                .on::<u32>(|n| n + 1)
+                .build();
+            assert_eq!(pass.transform(value.clone()), vec![vec![11, 21], vec![31]]);
+        }
+        assert_eq!(order, vec![2, 1]);
+
+        let mut pass = Pass::bottom_up()
+            .on::<Vec<u32>>(|v| v.into_iter().map(|n| n * 10).collect())
+            .on::<u32>(|n| n + 1)
+            .build();
+        assert_eq!(pass.transform(value.clone()), vec![vec![20, 30], vec![40]]);
+
+        let mut pruned = Pass::bottom_up().on::<u32>(|n| n + 1).prune::<Vec<u32>>().build();
+        assert_eq!(pruned.transform((0u32, value.clone())), (1, value));
+    }
+}
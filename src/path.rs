@@ -0,0 +1,787 @@
+use super::{CastThrough, GenericQuery, GenericTransform, Term, TermMeta};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
+
+/// One step from a value to one of its direct children.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    /// The child with the given name, for values that implement `TermMeta`.
+    Field(&'static str),
+    /// The child at the given position among its siblings, counting from zero,
+    /// for values that don't implement `TermMeta`, such as a `Vec`'s elements.
+    Index(usize),
+}
+
+/// The location of a value within the data structure being traversed, as the
+/// sequence of steps from the root down to it.
+///
+/// Paths are displayed like `.routes[0].handler`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+    indices: Vec<usize>,
+}
+
+impl Path {
+    /// Construct a new, empty path to the root.
+    #[inline]
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    /// The steps from the root down to this location.
+    #[inline]
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// The position of the value at each step among its siblings, counting
+    /// from zero, whether or not that step is named.
+    #[inline]
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    /// The position of the value at this location among its siblings, or
+    /// `None` for the root.
+    #[inline]
+    pub fn child_index(&self) -> Option<usize> {
+        self.indices.last().cloned()
+    }
+
+    /// How many steps this location is below the root.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Is this the path to the root?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Add a step to the end of this path, to the child at position `index`
+    /// among its siblings.
+    #[inline]
+    pub fn push(&mut self, segment: PathSegment, index: usize) {
+        self.segments.push(segment);
+        self.indices.push(index);
+    }
+
+    /// Remove the last step from this path.
+    #[inline]
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.indices.pop();
+        self.segments.pop()
+    }
+
+    /// Find the path to the first value, top down, within `t` for which the
+    /// given query evaluates to true.
+    pub fn find<T, Q>(t: &T, q: Q) -> Option<Path>
+    where
+        T: Term,
+        Q: GenericQuery<bool>,
+    {
+        walk_with_paths(t, Finder { q, found: None }).found
+    }
+
+    /// Does this path match the given pattern?
+    ///
+    /// Patterns are written the same way paths are displayed, with `*` in
+    /// place of a field name or index matching any of them, so
+    /// `.routes[*].handler` matches `.routes[0].handler` and
+    /// `.routes[1].handler`. Malformed patterns never match.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let mut segments = self.segments.iter();
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            let segment = match segments.next() {
+                Some(segment) => segment,
+                None => return false,
+            };
+            let is_field = rest.starts_with('.');
+            let (expected, after) = if let Some(field) = rest.strip_prefix('.') {
+                let end = field.find(['.', '[']).unwrap_or(field.len());
+                (&field[..end], &field[end..])
+            } else if let Some(index) = rest.strip_prefix('[') {
+                match index.find(']') {
+                    Some(end) => (&index[..end], &index[end + 1..]),
+                    None => return false,
+                }
+            } else {
+                return false;
+            };
+            let matched = match *segment {
+                PathSegment::Field(name) => is_field && (expected == "*" || expected == name),
+                PathSegment::Index(i) => {
+                    !is_field && (expected == "*" || expected == i.to_string())
+                }
+            };
+            if !matched {
+                return false;
+            }
+            rest = after;
+        }
+        segments.next().is_none()
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for segment in &self.segments {
+            match *segment {
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Index(i) => write!(f, "[{}]", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the names of a value's children, if its type implements `TermMeta`.
+trait ChildNames {
+    fn maybe_child_names(&self) -> Option<&'static [&'static str]>;
+}
+
+impl<T> ChildNames for T {
+    #[inline(always)]
+    default fn maybe_child_names(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+}
+
+impl<T> ChildNames for T
+where
+    T: TermMeta,
+{
+    #[inline(always)]
+    fn maybe_child_names(&self) -> Option<&'static [&'static str]> {
+        Some(self.child_names())
+    }
+}
+
+/// The step to the `index`th child of a value with the given child names.
+#[inline]
+fn segment(names: Option<&'static [&'static str]>, index: usize) -> PathSegment {
+    match names.and_then(|names| names.get(index)) {
+        Some(name) => PathSegment::Field(name),
+        None => PathSegment::Index(index),
+    }
+}
+
+/// Something to call on each value in a top down traversal, along with the path
+/// to that value, until it breaks.
+pub(crate) trait PathedVisitor {
+    fn visit<T>(&mut self, t: &T, path: &Path) -> ControlFlow<()>
+    where
+        T: Term;
+}
+
+/// Walk `t` top down and left to right, calling `v` on each value along with
+/// the path to it, until it breaks.
+#[inline]
+pub(crate) fn walk_with_paths<T, V>(t: &T, v: V) -> V
+where
+    T: Term,
+    V: PathedVisitor,
+{
+    let mut walk = PathedWalk::new(v);
+    walk.query(t);
+    walk.v
+}
+
+/// Searches for the first value matching a query.
+struct Finder<Q> {
+    q: Q,
+    found: Option<Path>,
+}
+
+impl<Q> PathedVisitor for Finder<Q>
+where
+    Q: GenericQuery<bool>,
+{
+    #[inline]
+    fn visit<T>(&mut self, t: &T, path: &Path) -> ControlFlow<()>
+    where
+        T: Term,
+    {
+        if self.q.query(t) {
+            self.found = Some(path.clone());
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Calls a function on each `U` and the path to it.
+struct EachWithPath<F, U>
+where
+    F: FnMut(&Path, &U),
+{
+    f: F,
+    phantom: PhantomData<fn(&U)>,
+}
+
+impl<F, U> PathedVisitor for EachWithPath<F, U>
+where
+    F: FnMut(&Path, &U),
+{
+    #[inline]
+    fn visit<T>(&mut self, t: &T, path: &Path) -> ControlFlow<()>
+    where
+        T: Term,
+    {
+        if let Some(u) = t.view_as() {
+            (self.f)(path, u);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Walks a data structure top down, keeping track of the path to each value
+/// and calling a visitor on it.
+struct PathedWalk<V> {
+    v: V,
+    path: Path,
+    broke: bool,
+}
+
+impl<V> PathedWalk<V>
+where
+    V: PathedVisitor,
+{
+    #[inline]
+    fn new(v: V) -> PathedWalk<V> {
+        PathedWalk {
+            v,
+            path: Path::new(),
+            broke: false,
+        }
+    }
+}
+
+impl<V> GenericQuery<()> for PathedWalk<V>
+where
+    V: PathedVisitor,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T)
+    where
+        T: Term,
+    {
+        if self.broke {
+            return;
+        }
+        if self.v.visit(t, &self.path).is_break() {
+            self.broke = true;
+            return;
+        }
+        let names = t.maybe_child_names();
+        let mut index = 0;
+        t.map_one_query(
+            &mut WalkChild {
+                walk: self,
+                names,
+                index: &mut index,
+            },
+            |_, _| {},
+        );
+    }
+}
+
+/// Continues a `PathedWalk` into each child of a value, with a step to that
+/// child added to its path.
+struct WalkChild<'a, V: 'a> {
+    walk: &'a mut PathedWalk<V>,
+    names: Option<&'static [&'static str]>,
+    index: &'a mut usize,
+}
+
+impl<'a, V> GenericQuery<()> for WalkChild<'a, V>
+where
+    V: PathedVisitor,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T)
+    where
+        T: Term,
+    {
+        let index = *self.index;
+        *self.index += 1;
+        self.walk.path.push(segment(self.names, index), index);
+        self.walk.query(t);
+        self.walk.path.pop();
+    }
+}
+
+/// Call `f` on every value of type `U` within `t`, in top down, left to right
+/// order, along with the path from `t` to it.
+#[inline]
+pub fn for_each_with_path<U, T, F>(t: &T, f: F)
+where
+    T: Term,
+    F: FnMut(&Path, &U),
+{
+    walk_with_paths(
+        t,
+        EachWithPath {
+            f,
+            phantom: PhantomData,
+        },
+    );
+}
+
+/// Collect a clone of every value of type `U` within `t` for which `pred`
+/// returns true, in top down, left to right order, each paired with the path
+/// from `t` to it.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = vec![(1u32, 'a'), (2u32, 'b')];
+/// let found = collect_with_paths(&value, |n: &u32| *n > 1);
+/// assert_eq!(found.len(), 1);
+/// assert_eq!(found[0].0.to_string(), "[1][0]");
+/// assert_eq!(found[0].1, 2);
+/// ```
+pub fn collect_with_paths<U, T, P>(t: &T, mut pred: P) -> Vec<(Path, U)>
+where
+    T: Term,
+    U: Clone,
+    P: FnMut(&U) -> bool,
+{
+    let mut found = vec![];
+    for_each_with_path(t, |path, u: &U| {
+        if pred(u) {
+            found.push((path.clone(), u.clone()));
+        }
+    });
+    found
+}
+
+/// A transformation that is also given the location of the value it is
+/// transforming. This is roughly equivalent to `for<T> FnMut(T, &Path) -> T`.
+pub trait GenericPathedTransform {
+    /// Call the transform function on any `T` at the given location.
+    fn transform<T>(&mut self, t: T, path: &Path) -> T
+    where
+        T: Term;
+}
+
+impl<F> GenericPathedTransform for &mut F
+where
+    F: GenericPathedTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, path: &Path) -> T
+    where
+        T: Term,
+    {
+        (**self).transform(t, path)
+    }
+}
+
+/// A pathed transformation takes some value `U` and its location, and returns a
+/// new, transformed version of it. It can be called on values of *any* type
+/// `T`, not just on values of type `U`, in which case it is simply the identity
+/// function.
+///
+/// This essentially lifts a `FnMut(U, &Path) -> U` into a `for<T> FnMut(T,
+/// &Path) -> T`.
+#[derive(Debug)]
+pub struct PathedTransformation<F, U>
+where
+    F: FnMut(U, &Path) -> U,
+{
+    f: F,
+    phantom: PhantomData<fn(U) -> U>,
+}
+
+impl<F, U> PathedTransformation<F, U>
+where
+    F: FnMut(U, &Path) -> U,
+{
+    /// Construct a new `PathedTransformation` from the given function.
+    #[inline]
+    pub fn new(f: F) -> PathedTransformation<F, U> {
+        PathedTransformation {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U> GenericPathedTransform for PathedTransformation<F, U>
+where
+    F: FnMut(U, &Path) -> U,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, path: &Path) -> T {
+        let f = &mut self.f;
+        t.map_as(|u| (f(u, path), ())).0
+    }
+}
+
+/// A child index aware transformation takes some value `U` and its position
+/// among its siblings, and returns a new, transformed version of it. It can be
+/// called on values of *any* type `T`, not just on values of type `U`, in which
+/// case it is simply the identity function.
+///
+/// The position counts from zero, in the order that `Term::map_one_transform`
+/// visits siblings, and is `None` for the value a traversal starts at. Traverse
+/// with `PathedEverywhere` to tell the transformation each value's position.
+#[derive(Debug)]
+pub struct TransformationWithIndex<F, U>
+where
+    F: FnMut(U, Option<usize>) -> U,
+{
+    f: F,
+    phantom: PhantomData<fn(U) -> U>,
+}
+
+impl<F, U> TransformationWithIndex<F, U>
+where
+    F: FnMut(U, Option<usize>) -> U,
+{
+    /// Construct a new `TransformationWithIndex` from the given function.
+    #[inline]
+    pub fn new(f: F) -> TransformationWithIndex<F, U> {
+        TransformationWithIndex {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U> GenericPathedTransform for TransformationWithIndex<F, U>
+where
+    F: FnMut(U, Option<usize>) -> U,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, path: &Path) -> T {
+        let f = &mut self.f;
+        t.map_as(|u| (f(u, path.child_index()), ())).0
+    }
+}
+
+/// Recursively perform a pathed transformation in a bottom up manner across a
+/// complete data structure, telling it the location of each value relative to
+/// where the traversal started.
+#[derive(Debug)]
+pub struct PathedEverywhere<F>
+where
+    F: GenericPathedTransform,
+{
+    f: F,
+    path: Path,
+}
+
+impl<F> PathedEverywhere<F>
+where
+    F: GenericPathedTransform,
+{
+    /// Construct a new pathed transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> PathedEverywhere<F> {
+        PathedEverywhere {
+            f,
+            path: Path::new(),
+        }
+    }
+}
+
+impl<F> GenericTransform for PathedEverywhere<F>
+where
+    F: GenericPathedTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let names = t.maybe_child_names();
+        let t = t.map_one_transform(&mut Children {
+            everywhere: self,
+            names,
+            index: 0,
+        });
+        self.f.transform(t, &self.path)
+    }
+}
+
+/// Traverses each child of a value with a `PathedEverywhere`, with a step to
+/// that child added to its path.
+struct Children<'a, F>
+where
+    F: 'a + GenericPathedTransform,
+{
+    everywhere: &'a mut PathedEverywhere<F>,
+    names: Option<&'static [&'static str]>,
+    index: usize,
+}
+
+impl<'a, F> GenericTransform for Children<'a, F>
+where
+    F: GenericPathedTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        self.everywhere
+            .path
+            .push(segment(self.names, self.index), self.index);
+        self.index += 1;
+        let t = self.everywhere.transform(t);
+        self.everywhere.path.pop();
+        t
+    }
+}
+
+/// Perform a transformation on only the values along a path: the value at the
+/// end of the path and each of its ancestors, from there back up to the root.
+///
+/// Nothing off the path is visited at all, which makes this a cheap way to
+/// bubble a change at one location up to the root. Find the path to a value
+/// matching a query with `Path::find`. If the path leads to a child that
+/// doesn't exist, the transformation is applied to the values along the part of
+/// it that does.
+#[derive(Debug)]
+pub struct Spine<F>
+where
+    F: GenericTransform,
+{
+    f: F,
+    path: Path,
+    depth: usize,
+}
+
+impl<F> Spine<F>
+where
+    F: GenericTransform,
+{
+    /// Construct a new traversal along the given path.
+    #[inline]
+    pub fn new(path: Path, f: F) -> Spine<F> {
+        Spine { f, path, depth: 0 }
+    }
+}
+
+impl<F> GenericTransform for Spine<F>
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let t = match self.path.indices().get(self.depth).cloned() {
+            Some(target) => {
+                self.depth += 1;
+                let t = t.map_one_transform(&mut SpineChild {
+                    spine: self,
+                    target,
+                    index: 0,
+                });
+                self.depth -= 1;
+                t
+            }
+            None => t,
+        };
+        self.f.transform(t)
+    }
+}
+
+/// Continues a `Spine` traversal into only the child on its path.
+struct SpineChild<'a, F>
+where
+    F: 'a + GenericTransform,
+{
+    spine: &'a mut Spine<F>,
+    target: usize,
+    index: usize,
+}
+
+impl<'a, F> GenericTransform for SpineChild<'a, F>
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let index = self.index;
+        self.index += 1;
+        if index == self.target {
+            self.spine.transform(t)
+        } else {
+            t
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Query, Transformation};
+
+    #[derive(Debug, PartialEq)]
+    struct Config {
+        name: &'static str,
+        routes: Vec<Route>,
+    }
+    impl_term!(Config { name, routes });
+
+    impl TermMeta for Config {
+        fn type_name(&self) -> &'static str {
+            "Config"
+        }
+
+        fn child_names(&self) -> &'static [&'static str] {
+            &["name", "routes"]
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Route {
+        path: &'static str,
+        handler: &'static str,
+    }
+    impl_term!(Route { path, handler });
+
+    impl TermMeta for Route {
+        fn type_name(&self) -> &'static str {
+            "Route"
+        }
+
+        fn child_names(&self) -> &'static [&'static str] {
+            &["path", "handler"]
+        }
+    }
+
+    #[test]
+    fn pathed_everywhere() {
+        let config = Config {
+            name: "app",
+            routes: vec![
+                Route {
+                    path: "/",
+                    handler: "index",
+                },
+                Route {
+                    path: "/about",
+                    handler: "about",
+                },
+            ],
+        };
+
+        let mut paths = vec![];
+        let config = PathedEverywhere::new(PathedTransformation::new(|s: &'static str, path: &Path| {
+            paths.push(path.to_string());
+            if path.matches(".routes[*].handler") {
+                "handler"
+            } else {
+                s
+            }
+        })).transform(config);
+
+        assert_eq!(config.routes[0].handler, "handler");
+        assert_eq!(config.routes[1].handler, "handler");
+        assert_eq!(config.routes[1].path, "/about");
+        assert_eq!(config.name, "app");
+        assert_eq!(
+            paths,
+            vec![
+                ".name",
+                ".routes[0].path",
+                ".routes[0].handler",
+                ".routes[1].path",
+                ".routes[1].handler",
+            ]
+        );
+    }
+
+    #[test]
+    fn transformation_with_index() {
+        let mut first = PathedEverywhere::new(TransformationWithIndex::new(|n: u32, index| {
+            if index == Some(0) {
+                n * 10
+            } else {
+                n
+            }
+        }));
+        let calls = vec![(1u32, 2u32), (3u32, 4u32)];
+        assert_eq!(first.transform(calls), vec![(10, 2), (30, 4)]);
+        assert_eq!(first.transform(5u32), 5);
+    }
+
+    #[test]
+    fn path_patterns() {
+        let mut path = Path::new();
+        assert!(path.matches(""));
+        path.push(PathSegment::Field("routes"), 1);
+        path.push(PathSegment::Index(3), 3);
+        assert!(path.matches(".routes[3]"));
+        assert!(path.matches(".*[*]"));
+        assert!(!path.matches(".routes[2]"));
+        assert!(!path.matches(".routes"));
+        assert!(!path.matches("[0][3]"));
+        assert!(!path.matches(".routes[3].handler"));
+        assert!(!path.matches(".routes[3"));
+        assert_eq!(path.indices(), &[1, 3]);
+        assert_eq!(path.child_index(), Some(3));
+    }
+
+    #[test]
+    fn spine() {
+        let value = vec![(1u32, vec![2u32, 3]), (4u32, vec![5u32, 6])];
+
+        let path = Path::find(&value, Query::new(|n: &u32| *n == 5)).unwrap();
+        assert_eq!(path.to_string(), "[1][1][0]");
+        assert!(Path::find(&value, Query::new(|n: &u32| *n == 7)).is_none());
+
+        let mut lengths = vec![];
+        let mut spine = Spine::new(path, Transformation::new(|v: Vec<u32>| {
+            lengths.push(v.len());
+            v.into_iter().map(|n| n * 10).collect()
+        }));
+        let value = spine.transform(value);
+        assert_eq!(value, vec![(1, vec![2, 3]), (4, vec![50, 60])]);
+        assert_eq!(lengths, vec![2]);
+    }
+
+    #[test]
+    fn collect_with_paths() {
+        let config = Config {
+            name: "app",
+            routes: vec![
+                Route {
+                    path: "/",
+                    handler: "",
+                },
+                Route {
+                    path: "/about",
+                    handler: "about",
+                },
+            ],
+        };
+
+        let empty = super::collect_with_paths(&config, |s: &&str| s.is_empty());
+        let empty: Vec<_> = empty.iter().map(|(path, _)| path.to_string()).collect();
+        assert_eq!(empty, vec![".routes[0].handler"]);
+
+        let mut routes = vec![];
+        for_each_with_path(&config, |path, route: &Route| {
+            routes.push((path.to_string(), route.path));
+        });
+        assert_eq!(
+            routes,
+            vec![(".routes[0]".to_string(), "/"), (".routes[1]".to_string(), "/about")]
+        );
+    }
+}
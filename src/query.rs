@@ -1,4 +1,4 @@
-use super::{Cast, Term};
+use super::{CastThrough, Term};
 use std::marker::PhantomData;
 
 /// A similar work around as `GenericTransform`, but returning a query type, rather
@@ -8,6 +8,182 @@ pub trait GenericQuery<R> {
     fn query<T>(&mut self, t: &T) -> R
     where
         T: Term;
+
+    /// Transform this query's result for every value with `f`.
+    #[inline]
+    fn map<S, F>(self, f: F) -> QueryMap<Self, F, R>
+    where
+        Self: Sized,
+        F: FnMut(R) -> S,
+    {
+        QueryMap {
+            q: self,
+            f,
+            phantom: PhantomData,
+        }
+    }
+
+    /// For queries with an `Option` result, fall back to querying with
+    /// `other` for values where this query's result is `None`.
+    ///
+    /// Typed queries constructed with `Query::new(|u: &U| Some(...))` result in
+    /// `None` for values that aren't a `U`, so this combines typed queries on
+    /// different types into one query on all of them:
+    ///
+    /// ```
+    /// use scrapmetal::*;
+    ///
+    /// let mut describe = Query::new(|n: &u32| Some(format!("number {}", n)))
+    ///     .or(Query::new(|c: &char| Some(format!("char {}", c))));
+    /// assert_eq!(describe.query(&1u32), Some("number 1".to_string()));
+    /// assert_eq!(describe.query(&'a'), Some("char a".to_string()));
+    /// assert_eq!(describe.query(&true), None);
+    /// ```
+    #[inline]
+    fn or<B>(self, other: B) -> QueryOr<Self, B>
+    where
+        Self: Sized,
+        B: GenericQuery<R>,
+    {
+        QueryOr { a: self, b: other }
+    }
+
+    /// Extend this query with a special case for values of type `U`, answering
+    /// with `f` for them and with this query for everything else, like SYB's
+    /// `extQ`.
+    ///
+    /// ```
+    /// use scrapmetal::*;
+    ///
+    /// let mut size = Query::or_else(|| 1, |s: &&str| s.len())
+    ///     .ext(|v: &Vec<u8>| v.len())
+    ///     .ext(|_: &()| 0);
+    /// assert_eq!(size.query(&"abc"), 3);
+    /// assert_eq!(size.query(&vec![1u8, 2]), 2);
+    /// assert_eq!(size.query(&()), 0);
+    /// assert_eq!(size.query(&'a'), 1);
+    /// ```
+    #[inline]
+    fn ext<U, F>(self, f: F) -> ExtQuery<Self, F, U>
+    where
+        Self: Sized,
+        F: FnMut(&U) -> R,
+    {
+        ExtQuery {
+            q: self,
+            f,
+            phantom: PhantomData,
+        }
+    }
+
+    /// For queries with an `Option` result, chain `f` onto the result for
+    /// values where it is `Some`, like `Option::and_then`.
+    #[inline]
+    fn and_then<F>(self, f: F) -> QueryAndThen<Self, F, R>
+    where
+        Self: Sized,
+    {
+        QueryAndThen {
+            q: self,
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// A query whose result is transformed by a function. See `GenericQuery::map`.
+#[derive(Debug)]
+pub struct QueryMap<Q, F, R> {
+    q: Q,
+    f: F,
+    phantom: PhantomData<fn() -> R>,
+}
+
+impl<Q, F, R, S> GenericQuery<S> for QueryMap<Q, F, R>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(R) -> S,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> S
+    where
+        T: Term,
+    {
+        (self.f)(self.q.query(t))
+    }
+}
+
+/// A query with a special case for values of type `U`. See
+/// `GenericQuery::ext`.
+#[derive(Debug)]
+pub struct ExtQuery<Q, F, U> {
+    q: Q,
+    f: F,
+    phantom: PhantomData<fn(&U)>,
+}
+
+impl<Q, F, U, R> GenericQuery<R> for ExtQuery<Q, F, U>
+where
+    Q: GenericQuery<R>,
+    F: FnMut(&U) -> R,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> R
+    where
+        T: Term,
+    {
+        match t.view_as() {
+            Some(u) => (self.f)(u),
+            None => self.q.query(t),
+        }
+    }
+}
+
+/// A query that falls back to another query. See `GenericQuery::or`.
+#[derive(Debug)]
+pub struct QueryOr<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B, R> GenericQuery<Option<R>> for QueryOr<A, B>
+where
+    A: GenericQuery<Option<R>>,
+    B: GenericQuery<Option<R>>,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> Option<R>
+    where
+        T: Term,
+    {
+        match self.a.query(t) {
+            Some(r) => Some(r),
+            None => self.b.query(t),
+        }
+    }
+}
+
+/// A query whose result is chained onto by a function. See
+/// `GenericQuery::and_then`.
+#[derive(Debug)]
+pub struct QueryAndThen<Q, F, R> {
+    q: Q,
+    f: F,
+    phantom: PhantomData<fn() -> R>,
+}
+
+impl<Q, F, R, S> GenericQuery<Option<S>> for QueryAndThen<Q, F, Option<R>>
+where
+    Q: GenericQuery<Option<R>>,
+    F: FnMut(R) -> Option<S>,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> Option<S>
+    where
+        T: Term,
+    {
+        self.q.query(t).and_then(&mut self.f)
+    }
 }
 
 /// A query non-destructively creates some value `R` from references to a
@@ -61,6 +237,21 @@ where
     }
 }
 
+impl<Q, U, D, R> Clone for Query<Q, U, D, R>
+where
+    Q: Clone + FnMut(&U) -> R,
+    D: Clone + FnMut() -> R,
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        Query {
+            make_default: self.make_default.clone(),
+            query: self.query.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
 impl<Q, U, D, R> GenericQuery<R> for Query<Q, U, D, R>
 where
     Q: FnMut(&U) -> R,
@@ -71,17 +262,52 @@ where
     where
         T: Term,
     {
-        match Cast::<&U>::cast(t) {
-            Ok(u) => (self.query)(u),
-            Err(_) => (self.make_default)(),
+        match t.view_as() {
+            Some(u) => (self.query)(u),
+            None => (self.make_default)(),
         }
     }
 }
 
+/// Query each of the direct children of `t`, without recursing any further, and
+/// collect the results in order. This is SYB's `gmapQ`.
+///
+/// This is the one-layer building block that recursive query traversals like
+/// `Everything` are made of, for building other traversal schemes.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (vec![1u32, 2], 3u32, 'a');
+/// let mut count = Everything::new(Query::new(|_: &u32| 1), |a, b| a + b);
+/// assert_eq!(query_children(&value, &mut count), vec![2, 1, 0]);
+/// ```
+pub fn query_children<T, Q, R>(t: &T, q: &mut Q) -> Vec<R>
+where
+    T: Term,
+    Q: GenericQuery<R>,
+{
+    let mut rs = vec![];
+    t.map_one_query(q, |_, r| rs.push(r));
+    rs
+}
+
 /// Recursively perform a query in a top-down, left-to-right manner across a
 /// data structure. The `Q: Query<R>` queries individual values, while the `F:
 /// FnMut(R, R) -> R` joins the results of multiple queries into a single
 /// result.
+///
+/// This is the classic SYB `everything`: fold with `+` to count or sum, with
+/// `max` to find the largest result, or by extending one `Vec` with another to
+/// collect results.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let value = (vec![3u32, 1], Box::new(4u32));
+/// let mut max = Everything::new(Query::new(|n: &u32| *n), std::cmp::max);
+/// assert_eq!(max.query(&value), 4);
+/// ```
 #[derive(Debug)]
 pub struct Everything<Q, R, F>
 where
@@ -90,6 +316,7 @@ where
 {
     q: Q,
     fold: F,
+    rev: bool,
     phantom: PhantomData<fn(R, R) -> R>,
 }
 
@@ -104,6 +331,35 @@ where
         Everything {
             q,
             fold,
+            rev: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Construct a new `Everything` query traversal that goes right-to-left,
+    /// visiting each value's children from last to first.
+    #[inline]
+    pub fn new_rev(q: Q, fold: F) -> Everything<Q, R, F> {
+        Everything {
+            q,
+            fold,
+            rev: true,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<Q, R, F> Clone for Everything<Q, R, F>
+where
+    Q: Clone + GenericQuery<R>,
+    F: Clone + FnMut(R, R) -> R,
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        Everything {
+            q: self.q.clone(),
+            fold: self.fold.clone(),
+            rev: self.rev,
             phantom: PhantomData,
         }
     }
@@ -119,6 +375,72 @@ where
     where
         T: Term,
     {
+        let mut r = Some(self.q.query(t));
+        let each = |me: &mut Self, rr| {
+            r = Some((me.fold)(r.take().unwrap(), rr));
+        };
+        if self.rev {
+            t.map_one_query_rev(self, each);
+        } else {
+            t.map_one_query(self, each);
+        }
+        r.unwrap()
+    }
+}
+
+/// Recursively perform a query in a top-down, left-to-right manner across a
+/// data structure, like `Everything`, but ignoring branches where the given
+/// pruning query evaluates to false.
+///
+/// A value in an ignored branch is neither queried nor descended into, and
+/// results in `R::default()`, so ignoring a branch is cheap however big it is.
+#[derive(Debug)]
+pub struct EverythingBut<P, Q, R, F>
+where
+    P: GenericQuery<bool>,
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+{
+    p: P,
+    q: Q,
+    fold: F,
+    phantom: PhantomData<fn(R, R) -> R>,
+}
+
+impl<P, Q, R, F> EverythingBut<P, Q, R, F>
+where
+    P: GenericQuery<bool>,
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+    R: Default,
+{
+    /// Construct a new `EverythingBut` query traversal.
+    #[inline]
+    pub fn new(p: P, q: Q, fold: F) -> EverythingBut<P, Q, R, F> {
+        EverythingBut {
+            p,
+            q,
+            fold,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P, Q, R, F> GenericQuery<R> for EverythingBut<P, Q, R, F>
+where
+    P: GenericQuery<bool>,
+    Q: GenericQuery<R>,
+    F: FnMut(R, R) -> R,
+    R: Default,
+{
+    #[inline]
+    fn query<T>(&mut self, t: &T) -> R
+    where
+        T: Term,
+    {
+        if !self.p.query(t) {
+            return R::default();
+        }
         let mut r = Some(self.q.query(t));
         t.map_one_query(self, |me, rr| {
             r = Some((me.fold)(r.take().unwrap(), rr));
@@ -138,4 +460,75 @@ mod tests {
         assert_eq!(char_to_u32.query(&'b'), 98);
         assert_eq!(char_to_u32.query(&vec![1, 2, 3]), 42);
     }
+
+    #[test]
+    fn combinators() {
+        let mut len = Query::new(|s: &&str| s.len()).map(|n| n * 2);
+        assert_eq!(len.query(&"abc"), 6);
+        assert_eq!(len.query(&'a'), 0);
+
+        let mut digit = Query::new(|c: &char| Some(*c))
+            .and_then(|c: char| c.to_digit(10))
+            .or(Query::new(|n: &u32| Some(*n)));
+        assert_eq!(digit.query(&'7'), Some(7));
+        assert_eq!(digit.query(&'x'), None);
+        assert_eq!(digit.query(&9u32), Some(9));
+
+        let mut digits = Everything::new(digit.map(|d: Option<u32>| d.unwrap_or(0)), |a, b| a + b);
+        assert_eq!(digits.query(&(vec!['1', 'x', '2'], 3u32)), 6);
+    }
+
+    #[test]
+    fn ext() {
+        let mut kind = Query::or_else(|| "other", |_: &u32| "number")
+            .ext(|_: &char| "char")
+            .ext(|_: &u32| "shadowed number");
+        assert_eq!(kind.query(&1u32), "shadowed number");
+        assert_eq!(kind.query(&'a'), "char");
+        assert_eq!(kind.query(&true), "other");
+    }
+
+    #[test]
+    fn query_children() {
+        let mut type_of = Query::or_else(|| "other", |_: &u32| "u32");
+        assert_eq!(super::query_children(&(1u32, 'a'), &mut type_of), vec!["u32", "other"]);
+        assert_eq!(super::query_children(&vec![1u32, 2], &mut type_of), vec!["u32", "u32"]);
+        assert!(super::query_children(&1u32, &mut type_of).is_empty());
+    }
+
+    #[test]
+    fn everything() {
+        let value = (vec![(1u32, 'a')], Box::new((2u32, 'b')));
+
+        let mut sum = Everything::new(Query::new(|n: &u32| *n), |a, b| a + b);
+        assert_eq!(sum.query(&value), 3);
+
+        let mut chars = Everything::new(Query::new(|c: &char| vec![*c]), |mut a, b| {
+            a.extend(b);
+            a
+        });
+        assert_eq!(chars.query(&value), vec!['a', 'b']);
+    }
+
+    #[test]
+    fn right_to_left() {
+        let value = (vec!['a', 'b'], 'c');
+        let mut chars = Everything::new(Query::new(|c: &char| c.to_string()), |a, b| a + &b);
+        assert_eq!(chars.query(&value), "abc");
+        let mut chars = Everything::new_rev(Query::new(|c: &char| c.to_string()), |a, b| a + &b);
+        assert_eq!(chars.query(&value), "cba");
+    }
+
+    #[test]
+    fn everything_but() {
+        let value = (vec![1u32, 2], (vec![3u32], 4u32));
+        let not_vec = || Query::or_else(|| true, |_: &Vec<u32>| false);
+
+        let mut sum = EverythingBut::new(not_vec(), Query::new(|n: &u32| *n), |a, b| a + b);
+        assert_eq!(sum.query(&value), 4);
+        assert_eq!(sum.query(&vec![5u32]), 0);
+
+        let mut count = EverythingBut::new(not_vec(), Query::new(|_: &Vec<u32>| 1), |a, b| a + b);
+        assert_eq!(count.query(&value), 0);
+    }
 }
@@ -0,0 +1,143 @@
+use super::dispatch::erased_type_id;
+use super::{walk_with_paths, Path, PathedVisitor, Term};
+use std::any::{self, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::ControlFlow;
+
+/// The type of a value a traversal visits, for talking about types at run time
+/// without knowing them statically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TypeInfo {
+    /// The `TypeId` of the type.
+    pub type_id: TypeId,
+    /// The name of the type, from `std::any::type_name`.
+    pub type_name: &'static str,
+}
+
+impl TypeInfo {
+    /// Get the type information for `T`.
+    #[inline]
+    pub fn of<T>() -> TypeInfo
+    where
+        T: ?Sized + 'static,
+    {
+        TypeInfo {
+            type_id: TypeId::of::<T>(),
+            type_name: any::type_name::<T>(),
+        }
+    }
+
+    /// Get the type information for the type of the given value.
+    #[inline]
+    pub fn of_val<T>(_: &T) -> TypeInfo
+    where
+        T: ?Sized + 'static,
+    {
+        TypeInfo::of::<T>()
+    }
+
+    /// Get the type information for a `T` that might not be `'static`, with
+    /// its lifetimes ignored, like casting does.
+    #[inline]
+    pub(crate) fn erased<T>() -> TypeInfo
+    where
+        T: ?Sized,
+    {
+        TypeInfo {
+            type_id: erased_type_id::<T>(),
+            type_name: any::type_name::<T>(),
+        }
+    }
+
+    /// Is this the type information for `U`?
+    #[inline]
+    pub fn is<U>(&self) -> bool
+    where
+        U: ?Sized + 'static,
+    {
+        self.type_id == TypeId::of::<U>()
+    }
+}
+
+impl fmt::Display for TypeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.type_name)
+    }
+}
+
+/// Call `f` with the type of every value within `t`, including `t` itself, in
+/// top down, left to right order, along with the path from `t` to it.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut dump = vec![];
+/// reflect(&vec![(1u32, 'a')], |ty, path| dump.push(format!("{}: {}", path, ty)));
+/// assert_eq!(
+///     dump,
+///     vec![
+///         ": alloc::vec::Vec<(u32, char)>",
+///         "[0]: (u32, char)",
+///         "[0][0]: u32",
+///         "[0][1]: char",
+///     ]
+/// );
+/// ```
+#[inline]
+pub fn reflect<T, F>(t: &T, f: F)
+where
+    T: Term,
+    F: FnMut(TypeInfo, &Path),
+{
+    walk_with_paths(t, Reflect(f));
+}
+
+/// Count how many values of each type a traversal of `t` visits, including `t`
+/// itself, keyed by `TypeInfo`.
+///
+/// This is `census` with each type's `TypeId` and name together in one key.
+pub fn type_census<T>(t: &T) -> HashMap<TypeInfo, usize>
+where
+    T: Term,
+{
+    let mut census = HashMap::new();
+    reflect(t, |ty, _| *census.entry(ty).or_insert(0) += 1);
+    census
+}
+
+/// Calls a function with the type of each value, and the path to it.
+struct Reflect<F>(F);
+
+impl<F> PathedVisitor for Reflect<F>
+where
+    F: FnMut(TypeInfo, &Path),
+{
+    #[inline]
+    fn visit<T>(&mut self, _: &T, path: &Path) -> ControlFlow<()>
+    where
+        T: Term,
+    {
+        (self.0)(TypeInfo::erased::<T>(), path);
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_info() {
+        let s: &str = "a";
+        assert_eq!(TypeInfo::of_val(&s), TypeInfo::of::<&'static str>());
+        assert!(TypeInfo::of::<u32>().is::<u32>());
+        assert!(!TypeInfo::of::<u32>().is::<u64>());
+        assert_eq!(TypeInfo::of::<u32>().to_string(), "u32");
+
+        let census = type_census(&(vec![1u32, 2], s));
+        assert_eq!(census[&TypeInfo::of::<u32>()], 2);
+        assert_eq!(census[&TypeInfo::of::<&str>()], 1);
+        assert_eq!(census.len(), 4);
+    }
+}
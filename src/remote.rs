@@ -0,0 +1,253 @@
+use super::{GenericMutate, GenericQuery, GenericTransform, Term};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// One-layer traversal for a foreign type `T` that can't implement `Term`
+/// itself because of the orphan rules.
+///
+/// This is implemented by a local "mirror" definition of `T` with
+/// `#[derive(Term)]` and `#[scrap(remote = "path::to::T")]`, similar to serde's
+/// remote derive. Fields of type `T` are then traversed through the mirror with
+/// `#[scrap(with = "Mirror")]`, which wraps them in a `Remote<T, Mirror>`.
+pub trait RemoteTerm<T> {
+    /// Perform one-layer traversal and transformation of `this` value's direct
+    /// children.
+    fn map_one_transform<F>(this: T, f: &mut F) -> T
+    where
+        F: GenericTransform;
+
+    /// Perform one-layer traversal and immutable querying of `this` value's
+    /// direct children.
+    fn map_one_query<Q, R, F>(this: &T, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R);
+
+    /// Perform one-layer traversal and mutable querying of `this` value's
+    /// direct children.
+    fn map_one_mutation<M, R, F>(this: &mut T, mutation: &mut M, each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R);
+
+    /// Like `map_one_transform`, but visiting the direct children from last to
+    /// first.
+    ///
+    /// This defaults to visiting them in the usual first to last order.
+    #[inline]
+    fn map_one_transform_rev<F>(this: T, f: &mut F) -> T
+    where
+        F: GenericTransform,
+    {
+        Self::map_one_transform(this, f)
+    }
+
+    /// Like `map_one_query`, but visiting the direct children from last to
+    /// first.
+    ///
+    /// This defaults to visiting them in the usual first to last order.
+    #[inline]
+    fn map_one_query_rev<Q, R, F>(this: &T, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        Self::map_one_query(this, query, each)
+    }
+
+    /// Like `map_one_mutation`, but visiting the direct children from last to
+    /// first.
+    ///
+    /// This defaults to visiting them in the usual first to last order.
+    #[inline]
+    fn map_one_mutation_rev<M, R, F>(this: &mut T, mutation: &mut M, each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        Self::map_one_mutation(this, mutation, each)
+    }
+}
+
+/// A foreign `T` value that is a `Term` by way of its `M: RemoteTerm<T>` mirror
+/// definition.
+///
+/// Transformations and queries see a `Remote<T, M>` rather than a `T`, so to
+/// target the foreign type itself, write them in terms of `Remote<T, M>`.
+#[repr(transparent)]
+pub struct Remote<T, M> {
+    value: T,
+    mirror: PhantomData<fn() -> M>,
+}
+
+impl<T, M> Remote<T, M>
+where
+    M: RemoteTerm<T>,
+{
+    /// Wrap the given foreign value.
+    #[inline]
+    pub fn new(value: T) -> Remote<T, M> {
+        Remote {
+            value,
+            mirror: PhantomData,
+        }
+    }
+
+    /// View a reference to a foreign value as a reference to a `Remote`.
+    #[inline]
+    pub fn from_ref(value: &T) -> &Remote<T, M> {
+        // Safe because `Remote` is `repr(transparent)` over `T`.
+        unsafe { &*(value as *const T as *const Remote<T, M>) }
+    }
+
+    /// View a mutable reference to a foreign value as a mutable reference to a
+    /// `Remote`.
+    #[inline]
+    pub fn from_mut(value: &mut T) -> &mut Remote<T, M> {
+        // Safe because `Remote` is `repr(transparent)` over `T`.
+        unsafe { &mut *(value as *mut T as *mut Remote<T, M>) }
+    }
+
+    /// Unwrap the foreign value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// Get a reference to the foreign value.
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Get a mutable reference to the foreign value.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T, M> fmt::Debug for Remote<T, M>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Remote").field(&self.value).finish()
+    }
+}
+
+impl<T, M> Term for Remote<T, M>
+where
+    M: RemoteTerm<T>,
+{
+    #[inline]
+    fn map_one_transform<F>(self, f: &mut F) -> Self
+    where
+        F: GenericTransform,
+    {
+        Remote::new(M::map_one_transform(self.value, f))
+    }
+
+    #[inline]
+    fn map_one_query<Q, R, F>(&self, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        M::map_one_query(&self.value, query, each)
+    }
+
+    #[inline]
+    fn map_one_mutation<M2, R, F>(&mut self, mutation: &mut M2, each: F)
+    where
+        M2: GenericMutate<R>,
+        F: FnMut(&mut M2, R),
+    {
+        M::map_one_mutation(&mut self.value, mutation, each)
+    }
+
+    #[inline]
+    fn map_one_transform_rev<F>(self, f: &mut F) -> Self
+    where
+        F: GenericTransform,
+    {
+        Remote::new(M::map_one_transform_rev(self.value, f))
+    }
+
+    #[inline]
+    fn map_one_query_rev<Q, R, F>(&self, query: &mut Q, each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        M::map_one_query_rev(&self.value, query, each)
+    }
+
+    #[inline]
+    fn map_one_mutation_rev<M2, R, F>(&mut self, mutation: &mut M2, each: F)
+    where
+        M2: GenericMutate<R>,
+        F: FnMut(&mut M2, R),
+    {
+        M::map_one_mutation_rev(&mut self.value, mutation, each)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Everywhere, Transformation};
+
+    struct Pair(u32, u32);
+
+    struct PairDef;
+
+    impl RemoteTerm<Pair> for PairDef {
+        fn map_one_transform<F>(this: Pair, f: &mut F) -> Pair
+        where
+            F: GenericTransform,
+        {
+            Pair(f.transform(this.0), f.transform(this.1))
+        }
+
+        fn map_one_query<Q, R, F>(this: &Pair, q: &mut Q, mut each: F)
+        where
+            Q: GenericQuery<R>,
+            F: FnMut(&mut Q, R),
+        {
+            let r = q.query(&this.0);
+            each(q, r);
+            let r = q.query(&this.1);
+            each(q, r);
+        }
+
+        fn map_one_mutation<M, R, F>(this: &mut Pair, m: &mut M, mut each: F)
+        where
+            M: GenericMutate<R>,
+            F: FnMut(&mut M, R),
+        {
+            let r = m.mutate(&mut this.0);
+            each(m, r);
+            let r = m.mutate(&mut this.1);
+            each(m, r);
+        }
+    }
+
+    #[test]
+    fn remote() {
+        let mut incr = Everywhere::new(Transformation::new(|n: u32| n + 1));
+        let pairs = vec![Remote::<_, PairDef>::new(Pair(1, 2))];
+        let pair = incr.transform(pairs).pop().unwrap().into_inner();
+        assert_eq!((pair.0, pair.1), (2, 3));
+
+        // Without reverse methods of its own, a mirror visits children first to
+        // last even in reverse.
+        let mut seen = vec![];
+        Everywhere::new_rev(Transformation::new(|n: u32| {
+            seen.push(n);
+            n
+        }))
+        .transform(Remote::<_, PairDef>::new(pair));
+        assert_eq!(seen, vec![2, 3]);
+    }
+}
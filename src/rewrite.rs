@@ -0,0 +1,1141 @@
+use super::{CastThrough, GenericQuery, GenericTransform, Term};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A transformation that might not apply to a given value, and reports whether
+/// it did. This is roughly equivalent to `for<T> FnMut(T) -> Result<T, T>`.
+///
+/// Returning `Ok` means the rewrite fired and produced a new value, and
+/// returning `Err` means it did not fire and hands back the original value
+/// unchanged. Rewriting strategies such as `OnceTopDown` use this to decide
+/// where to stop.
+pub trait GenericRewrite {
+    /// Call the rewrite function on any `T`.
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term;
+
+    /// Try this rewrite, and only if it doesn't fire, try `other`. This is
+    /// `Choice(self, other)`.
+    ///
+    /// A `Transformation` fires on exactly the values of its type, so chaining
+    /// typed transformations like this combines them into one transformation
+    /// with a case for each type, like SYB's `extT`.
+    ///
+    /// ```
+    /// use scrapmetal::*;
+    ///
+    /// let not = Transformation::new(|b: bool| !b);
+    /// let inc = Transformation::new(|n: u32| n + 1);
+    /// let upper = Transformation::new(|c: char| c.to_ascii_uppercase());
+    /// let mut each = Everywhere::new(not.or_else(inc).or_else(upper));
+    /// assert_eq!(each.transform((true, 1u32, vec!['a'])), (false, 2, vec!['A']));
+    /// ```
+    #[inline]
+    fn or_else<B>(self, other: B) -> Choice<Self, B>
+    where
+        Self: Sized,
+        B: GenericRewrite,
+    {
+        Choice(self, other)
+    }
+}
+
+impl<F> GenericRewrite for &mut F
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        (**self).rewrite(t)
+    }
+}
+
+/// A rewrite takes some value `U` and either returns a new, rewritten version
+/// of it or gives it back unchanged. It can be called on values of *any* type
+/// `T`, not just on values of type `U`, in which case it never fires.
+///
+/// This essentially lifts a `FnMut(U) -> Result<U, U>` into a `for<T> FnMut(T)
+/// -> Result<T, T>`.
+#[derive(Debug)]
+pub struct Rewrite<F, U>
+where
+    F: FnMut(U) -> Result<U, U>,
+{
+    f: F,
+    phantom: PhantomData<fn(U) -> U>,
+}
+
+impl<F, U> Rewrite<F, U>
+where
+    F: FnMut(U) -> Result<U, U>,
+{
+    /// Construct a new `Rewrite` from the given function.
+    #[inline]
+    pub fn new(f: F) -> Rewrite<F, U> {
+        Rewrite {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U> GenericRewrite for Rewrite<F, U>
+where
+    F: FnMut(U) -> Result<U, U>,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T> {
+        let f = &mut self.f;
+        match t.map_as(|u| match f(u) {
+            Ok(u) => (u, true),
+            Err(u) => (u, false),
+        }) {
+            (t, Some(true)) => Ok(t),
+            (t, _) => Err(t),
+        }
+    }
+}
+
+impl<F, U> GenericTransform for Rewrite<F, U>
+where
+    F: FnMut(U) -> Result<U, U>,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+/// A partial transformation looks at some value `U` and either returns a new
+/// value to replace it with, or `None` if it doesn't apply, leaving the value
+/// as it was. It can be called on values of *any* type `T`, not just on values
+/// of type `U`, in which case it never applies. This is SYB's `mkM` for
+/// `Maybe`.
+///
+/// Like a `Rewrite`, it is a `GenericRewrite` that fires whenever it applies,
+/// so strategies like `OnceTopDown` and `Somewhere` know whether it did,
+/// without comparing values.
+///
+/// This essentially lifts a `FnMut(&U) -> Option<U>` into a `for<T> FnMut(T)
+/// -> Result<T, T>`.
+#[derive(Debug)]
+pub struct PartialTransformation<F, U>
+where
+    F: FnMut(&U) -> Option<U>,
+{
+    f: F,
+    phantom: PhantomData<fn(&U) -> U>,
+}
+
+impl<F, U> PartialTransformation<F, U>
+where
+    F: FnMut(&U) -> Option<U>,
+{
+    /// Construct a new `PartialTransformation` from the given function.
+    #[inline]
+    pub fn new(f: F) -> PartialTransformation<F, U> {
+        PartialTransformation {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U> GenericRewrite for PartialTransformation<F, U>
+where
+    F: FnMut(&U) -> Option<U>,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T> {
+        let f = &mut self.f;
+        match t.map_as(|u| match f(&u) {
+            Some(u) => (u, true),
+            None => (u, false),
+        }) {
+            (t, Some(true)) => Ok(t),
+            (t, _) => Err(t),
+        }
+    }
+}
+
+impl<F, U> GenericTransform for PartialTransformation<F, U>
+where
+    F: FnMut(&U) -> Option<U>,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+/// Get the value out of a rewrite's result, whether or not it fired.
+#[inline]
+fn unwrap<T>(result: Result<T, T>) -> T {
+    match result {
+        Ok(t) | Err(t) => t,
+    }
+}
+
+/// Apply one transformation or rewrite, and then another, to the same value.
+///
+/// As a `GenericRewrite`, this fires if either of them fired.
+#[derive(Debug)]
+pub struct Seq<A, B>(pub A, pub B);
+
+impl<A, B> GenericTransform for Seq<A, B>
+where
+    A: GenericTransform,
+    B: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let t = self.0.transform(t);
+        self.1.transform(t)
+    }
+}
+
+impl<A, B> GenericRewrite for Seq<A, B>
+where
+    A: GenericRewrite,
+    B: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        match self.0.rewrite(t) {
+            Ok(t) => Ok(unwrap(self.1.rewrite(t))),
+            Err(t) => self.1.rewrite(t),
+        }
+    }
+}
+
+/// Try one rewrite, and only if it doesn't fire, try another.
+///
+/// As a `GenericRewrite`, this fires if either of them fired.
+#[derive(Debug)]
+pub struct Choice<A, B>(pub A, pub B);
+
+impl<A, B> GenericRewrite for Choice<A, B>
+where
+    A: GenericRewrite,
+    B: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        match self.0.rewrite(t) {
+            Ok(t) => Ok(t),
+            Err(t) => self.1.rewrite(t),
+        }
+    }
+}
+
+impl<A, B> GenericTransform for Choice<A, B>
+where
+    A: GenericRewrite,
+    B: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+/// Try a rewrite, succeeding whether or not it fires.
+///
+/// As a `GenericRewrite`, this always fires, returning the original value if
+/// the inner rewrite didn't.
+#[derive(Debug)]
+pub struct Attempt<A>(pub A);
+
+impl<A> GenericRewrite for Attempt<A>
+where
+    A: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        Ok(unwrap(self.0.rewrite(t)))
+    }
+}
+
+impl<A> GenericTransform for Attempt<A>
+where
+    A: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.0.rewrite(t))
+    }
+}
+
+/// Rewrites each child it is called on with the given rewrite, until one of
+/// them fires, and leaves the remaining children alone.
+struct First<'a, F: 'a> {
+    f: &'a mut F,
+    fired: bool,
+}
+
+impl<'a, F> GenericTransform for First<'a, F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        if self.fired {
+            return t;
+        }
+        match self.f.rewrite(t) {
+            Ok(t) => {
+                self.fired = true;
+                t
+            }
+            Err(t) => t,
+        }
+    }
+}
+
+/// Rewrites every child it is called on with the given rewrite, and records
+/// whether it fired for any of them.
+struct Each<'a, F: 'a> {
+    f: &'a mut F,
+    fired: bool,
+}
+
+impl<'a, F> GenericTransform for Each<'a, F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        match self.f.rewrite(t) {
+            Ok(t) => {
+                self.fired = true;
+                t
+            }
+            Err(t) => t,
+        }
+    }
+}
+
+/// Rewrite the first value where the given rewrite fires, searching depth first
+/// and trying each value before its children, and leave the rest of the data
+/// structure untouched.
+///
+/// As a `GenericRewrite`, this fires if the rewrite fired anywhere.
+#[derive(Debug)]
+pub struct OnceTopDown<F>
+where
+    F: GenericRewrite,
+{
+    f: F,
+}
+
+impl<F> OnceTopDown<F>
+where
+    F: GenericRewrite,
+{
+    /// Construct a new once top down rewriting traversal.
+    #[inline]
+    pub fn new(f: F) -> OnceTopDown<F> {
+        OnceTopDown { f }
+    }
+}
+
+impl<F> GenericRewrite for OnceTopDown<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        let t = match self.f.rewrite(t) {
+            Ok(t) => return Ok(t),
+            Err(t) => t,
+        };
+        let mut first = First {
+            f: self,
+            fired: false,
+        };
+        let t = t.map_one_transform(&mut first);
+        if first.fired {
+            Ok(t)
+        } else {
+            Err(t)
+        }
+    }
+}
+
+impl<F> GenericTransform for OnceTopDown<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+/// Rewrite the first value where the given rewrite fires, searching depth first
+/// and trying each value's children before the value itself, and leave the rest
+/// of the data structure untouched.
+///
+/// As a `GenericRewrite`, this fires if the rewrite fired anywhere.
+#[derive(Debug)]
+pub struct OnceBottomUp<F>
+where
+    F: GenericRewrite,
+{
+    f: F,
+}
+
+impl<F> OnceBottomUp<F>
+where
+    F: GenericRewrite,
+{
+    /// Construct a new once bottom up rewriting traversal.
+    #[inline]
+    pub fn new(f: F) -> OnceBottomUp<F> {
+        OnceBottomUp { f }
+    }
+}
+
+impl<F> GenericRewrite for OnceBottomUp<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        let mut first = First {
+            f: self,
+            fired: false,
+        };
+        let t = t.map_one_transform(&mut first);
+        if first.fired {
+            Ok(t)
+        } else {
+            self.f.rewrite(t)
+        }
+    }
+}
+
+impl<F> GenericTransform for OnceBottomUp<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+/// Recursively rewrite a data structure in a top down manner, without
+/// descending into the result of any value where the rewrite fires.
+///
+/// This keeps a rewrite from matching again inside the new values it produces.
+/// As a `GenericRewrite`, this fires if the rewrite fired anywhere.
+#[derive(Debug)]
+pub struct StopTopDown<F>
+where
+    F: GenericRewrite,
+{
+    f: F,
+}
+
+impl<F> StopTopDown<F>
+where
+    F: GenericRewrite,
+{
+    /// Construct a new stopping top down rewriting traversal.
+    #[inline]
+    pub fn new(f: F) -> StopTopDown<F> {
+        StopTopDown { f }
+    }
+}
+
+impl<F> GenericRewrite for StopTopDown<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        let t = match self.f.rewrite(t) {
+            Ok(t) => return Ok(t),
+            Err(t) => t,
+        };
+        let mut each = Each {
+            f: self,
+            fired: false,
+        };
+        let t = t.map_one_transform(&mut each);
+        if each.fired {
+            Ok(t)
+        } else {
+            Err(t)
+        }
+    }
+}
+
+impl<F> GenericTransform for StopTopDown<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+/// Transform exactly one value in a data structure: the first, or `n`th, value
+/// for which the given query evaluates to true, in top down order.
+///
+/// As a `GenericRewrite`, this fires if there was such a value to transform,
+/// and otherwise fails, returning the data structure untouched. Each call
+/// searches the data structure from the start again.
+#[derive(Debug)]
+pub struct Somewhere<P, F>
+where
+    P: GenericQuery<bool>,
+    F: GenericTransform,
+{
+    p: P,
+    f: F,
+    n: usize,
+}
+
+impl<P, F> Somewhere<P, F>
+where
+    P: GenericQuery<bool>,
+    F: GenericTransform,
+{
+    /// Construct a new traversal that transforms the first value matching `p`.
+    #[inline]
+    pub fn new(p: P, f: F) -> Somewhere<P, F> {
+        Somewhere::nth(0, p, f)
+    }
+
+    /// Construct a new traversal that transforms the `n`th value, counting
+    /// from zero, that matches `p`.
+    #[inline]
+    pub fn nth(n: usize, p: P, f: F) -> Somewhere<P, F> {
+        Somewhere { p, f, n }
+    }
+}
+
+impl<P, F> GenericRewrite for Somewhere<P, F>
+where
+    P: GenericQuery<bool>,
+    F: GenericTransform,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        let mut search = Search {
+            somewhere: self,
+            seen: 0,
+            done: false,
+        };
+        let t = search.transform(t);
+        if search.done {
+            Ok(t)
+        } else {
+            Err(t)
+        }
+    }
+}
+
+impl<P, F> GenericTransform for Somewhere<P, F>
+where
+    P: GenericQuery<bool>,
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+/// Searches top down for the value a `Somewhere` transforms.
+struct Search<'a, P, F>
+where
+    P: 'a + GenericQuery<bool>,
+    F: 'a + GenericTransform,
+{
+    somewhere: &'a mut Somewhere<P, F>,
+    seen: usize,
+    done: bool,
+}
+
+impl<'a, P, F> GenericTransform for Search<'a, P, F>
+where
+    P: GenericQuery<bool>,
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        if self.done {
+            return t;
+        }
+        if self.somewhere.p.query(&t) {
+            if self.seen == self.somewhere.n {
+                self.done = true;
+                return self.somewhere.f.transform(t);
+            }
+            self.seen += 1;
+        }
+        t.map_one_transform(self)
+    }
+}
+
+/// Rewrites every value it is called on, in bottom up order, and records
+/// whether the rewrite fired anywhere.
+pub(crate) struct RewritePass<'a, F: 'a> {
+    pub(crate) f: &'a mut F,
+    pub(crate) rev: bool,
+    pub(crate) fired: bool,
+}
+
+impl<'a, F> GenericTransform for RewritePass<'a, F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let t = if self.rev {
+            t.map_one_transform_rev(self)
+        } else {
+            t.map_one_transform(self)
+        };
+        match self.f.rewrite(t) {
+            Ok(t) => {
+                self.fired = true;
+                t
+            }
+            Err(t) => t,
+        }
+    }
+}
+
+/// Recursively rewrite a data structure in a bottom up manner, traversing the
+/// result of each rewrite that fires again, children first, before moving on.
+///
+/// This way, new values that a rewrite produces, like the expansion of a macro,
+/// get rewritten in the same pass. The rewrite must eventually stop firing on
+/// its own results, or this will loop forever. As a `GenericRewrite`, this
+/// fires if the rewrite fired anywhere.
+#[derive(Debug)]
+pub struct EverywhereRetraversing<F>
+where
+    F: GenericRewrite,
+{
+    f: F,
+    fired: bool,
+}
+
+impl<F> EverywhereRetraversing<F>
+where
+    F: GenericRewrite,
+{
+    /// Construct a new retraversing rewriting traversal.
+    #[inline]
+    pub fn new(f: F) -> EverywhereRetraversing<F> {
+        EverywhereRetraversing { f, fired: false }
+    }
+}
+
+impl<F> GenericRewrite for EverywhereRetraversing<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        let outer = self.fired;
+        self.fired = false;
+        let t = self.transform(t);
+        let fired = self.fired;
+        self.fired = outer || fired;
+        if fired {
+            Ok(t)
+        } else {
+            Err(t)
+        }
+    }
+}
+
+impl<F> GenericTransform for EverywhereRetraversing<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let t = t.map_one_transform(self);
+        match self.f.rewrite(t) {
+            Ok(t) => {
+                self.fired = true;
+                self.transform(t)
+            }
+            Err(t) => t,
+        }
+    }
+}
+
+/// Repeatedly apply a rewrite to a value until it no longer fires, giving up
+/// with a `DivergenceError` if it is still firing after a maximum number of
+/// passes.
+///
+/// Each pass is a single call of the rewrite on the whole value, so this is
+/// usually given a traversal such as `Everywhere::new(rewrite)` or
+/// `OnceBottomUp::new(rewrite)`.
+#[derive(Debug)]
+pub struct Fixpoint<F>
+where
+    F: GenericRewrite,
+{
+    f: F,
+    max_iters: usize,
+}
+
+impl<F> Fixpoint<F>
+where
+    F: GenericRewrite,
+{
+    /// Construct a new fixpoint runner that gives up after `max_iters` passes.
+    #[inline]
+    pub fn new(max_iters: usize, f: F) -> Fixpoint<F> {
+        Fixpoint { f, max_iters }
+    }
+
+    /// Rewrite the given value until the rewrite stops firing, and return the
+    /// result, or an error with the last value if that takes too many passes.
+    pub fn run<T>(&mut self, mut t: T) -> Result<T, DivergenceError<T>>
+    where
+        T: Term,
+    {
+        for _ in 0..self.max_iters {
+            t = match self.f.rewrite(t) {
+                Ok(t) => t,
+                Err(t) => return Ok(t),
+            };
+        }
+        Err(DivergenceError {
+            value: t,
+            passes: self.max_iters,
+        })
+    }
+}
+
+/// A `Fixpoint` rewrite kept firing for its maximum number of passes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DivergenceError<T> {
+    /// The value after the last pass.
+    pub value: T,
+    /// How many passes ran, all of which fired.
+    pub passes: usize,
+}
+
+impl<T> fmt::Display for DivergenceError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "rewrite did not reach a fixpoint after {} passes",
+            self.passes
+        )
+    }
+}
+
+impl<T> Error for DivergenceError<T>
+where
+    T: fmt::Debug,
+{
+}
+
+/// Exhaustively normalize a data structure by repeatedly rewriting it bottom
+/// up, until a whole pass over it doesn't fire the rewrite anywhere.
+///
+/// The rewrite must eventually stop firing, or this will loop forever. As a
+/// `GenericRewrite`, this fires if any pass fired the rewrite.
+#[derive(Debug)]
+pub struct Innermost<F>
+where
+    F: GenericRewrite,
+{
+    f: F,
+}
+
+impl<F> Innermost<F>
+where
+    F: GenericRewrite,
+{
+    /// Construct a new innermost normalizing traversal.
+    #[inline]
+    pub fn new(f: F) -> Innermost<F> {
+        Innermost { f }
+    }
+}
+
+impl<F> GenericRewrite for Innermost<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, mut t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        let mut fired = false;
+        loop {
+            let mut pass = RewritePass {
+                f: &mut self.f,
+                rev: false,
+                fired: false,
+            };
+            t = pass.transform(t);
+            if !pass.fired {
+                return if fired { Ok(t) } else { Err(t) };
+            }
+            fired = true;
+        }
+    }
+}
+
+impl<F> GenericTransform for Innermost<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+/// Exhaustively normalize a data structure by repeatedly rewriting the topmost
+/// value where the rewrite fires, until it doesn't fire anywhere.
+///
+/// The rewrite must eventually stop firing, or this will loop forever. As a
+/// `GenericRewrite`, this fires if the rewrite fired at all.
+#[derive(Debug)]
+pub struct Outermost<F>
+where
+    F: GenericRewrite,
+{
+    f: F,
+}
+
+impl<F> Outermost<F>
+where
+    F: GenericRewrite,
+{
+    /// Construct a new outermost normalizing traversal.
+    #[inline]
+    pub fn new(f: F) -> Outermost<F> {
+        Outermost { f }
+    }
+}
+
+impl<F> GenericRewrite for Outermost<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        let mut once = OnceTopDown::new(&mut self.f);
+        let mut t = once.rewrite(t)?;
+        loop {
+            t = match once.rewrite(t) {
+                Ok(t) => t,
+                Err(t) => return Ok(t),
+            };
+        }
+    }
+}
+
+impl<F> GenericTransform for Outermost<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        unwrap(self.rewrite(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Everywhere, Query, Transformation};
+
+    #[derive(Debug, PartialEq)]
+    enum Expr {
+        Num(u32),
+        Add(Box<Expr>, Box<Expr>),
+        Sub(Box<Expr>, Box<Expr>),
+    }
+    impl_term!(enum Expr {
+        Num(n),
+        Add(a, b),
+        Sub(a, b),
+    });
+
+    fn num(n: u32) -> Expr {
+        Expr::Num(n)
+    }
+
+    fn add(a: Expr, b: Expr) -> Expr {
+        Expr::Add(Box::new(a), Box::new(b))
+    }
+
+    fn sub(a: Expr, b: Expr) -> Expr {
+        Expr::Sub(Box::new(a), Box::new(b))
+    }
+
+    type Rule = Rewrite<fn(Expr) -> Result<Expr, Expr>, Expr>;
+
+    /// Turns one addition into a subtraction.
+    fn negate() -> Rule {
+        Rewrite::new(|e| match e {
+            Expr::Add(a, b) => Ok(Expr::Sub(a, b)),
+            e => Err(e),
+        })
+    }
+
+    #[test]
+    fn once_top_down() {
+        let mut once = OnceTopDown::new(Rewrite::new(|v: Vec<u32>| {
+            if v.len() > 1 {
+                Ok(vec![v.iter().sum()])
+            } else {
+                Err(v)
+            }
+        }));
+
+        let v = vec![vec![vec![1u32, 2], vec![3]], vec![vec![4, 5]]];
+        let v = once.rewrite(v).unwrap();
+        assert_eq!(v, vec![vec![vec![3], vec![3]], vec![vec![4, 5]]]);
+        let v = once.rewrite(v).unwrap();
+        assert_eq!(v, vec![vec![vec![3], vec![3]], vec![vec![9]]]);
+        assert_eq!(
+            once.rewrite(v),
+            Err(vec![vec![vec![3], vec![3]], vec![vec![9]]])
+        );
+    }
+
+    #[test]
+    fn once_bottom_up() {
+        let e = add(add(num(1), num(2)), add(num(3), num(4)));
+        let e = OnceTopDown::new(negate()).rewrite(e).unwrap();
+        assert_eq!(e, sub(add(num(1), num(2)), add(num(3), num(4))));
+
+        let mut once = OnceBottomUp::new(negate());
+        let e = once.rewrite(e).unwrap();
+        assert_eq!(e, sub(sub(num(1), num(2)), add(num(3), num(4))));
+        let e = once.rewrite(e).unwrap();
+        assert_eq!(e, sub(sub(num(1), num(2)), sub(num(3), num(4))));
+        assert!(once.rewrite(e).is_err());
+    }
+
+    #[test]
+    fn innermost() {
+        let mut fold = Innermost::new(Rewrite::new(|e| match e {
+            Expr::Add(a, b) => match (*a, *b) {
+                (Expr::Num(a), Expr::Num(b)) => Ok(num(a + b)),
+                (a, b) => Err(add(a, b)),
+            },
+            e => Err(e),
+        }));
+
+        let e = add(add(num(1), num(2)), sub(add(num(3), num(4)), num(5)));
+        let e = fold.rewrite(e).unwrap();
+        assert_eq!(e, add(num(3), sub(num(7), num(5))));
+        assert!(fold.rewrite(e).is_err());
+        assert_eq!(fold.transform(add(add(num(1), num(2)), num(3))), num(6));
+    }
+
+    #[test]
+    fn outermost() {
+        // (a + b) - c = a - (c - b)
+        let mut distribute = Outermost::new(Rewrite::new(|e| match e {
+            Expr::Sub(a, c) => match *a {
+                Expr::Add(a, b) => Ok(Expr::Sub(a, Box::new(sub(*c, *b)))),
+                a => Err(sub(a, *c)),
+            },
+            e => Err(e),
+        }));
+
+        let e = sub(add(add(num(1), num(2)), num(3)), num(4));
+        let e = distribute.rewrite(e).unwrap();
+        assert_eq!(e, sub(num(1), sub(sub(num(4), num(3)), num(2))));
+        assert!(distribute.rewrite(e).is_err());
+    }
+
+    #[test]
+    fn stop_top_down() {
+        // a + b = 0 + (a + b)
+        let mut pad = StopTopDown::new(Rewrite::new(|e| match e {
+            Expr::Add(a, b) => Ok(add(num(0), Expr::Add(a, b))),
+            e => Err(e),
+        }));
+
+        let e = sub(add(num(1), num(2)), add(num(3), num(4)));
+        let e = pad.rewrite(e).unwrap();
+        assert_eq!(
+            e,
+            sub(
+                add(num(0), add(num(1), num(2))),
+                add(num(0), add(num(3), num(4)))
+            )
+        );
+        assert!(pad.rewrite(sub(num(1), num(2))).is_err());
+    }
+
+    #[test]
+    fn combinators() {
+        let double = || Rewrite::new(|n: u32| if n % 2 == 1 { Ok(n * 2) } else { Err(n) });
+        let shrink = || Rewrite::new(|n: u32| if n > 4 { Ok(n - 4) } else { Err(n) });
+
+        let mut seq = Everywhere::new(Seq(double(), shrink()));
+        assert_eq!(seq.transform(vec![1u32, 2, 3, 4]), vec![2, 2, 2, 4]);
+
+        let mut choice = Choice(shrink(), double());
+        assert_eq!(choice.rewrite(8u32), Ok(4));
+        assert_eq!(choice.rewrite(3u32), Ok(6));
+        assert_eq!(choice.rewrite(2u32), Err(2));
+        assert_eq!(Attempt(Choice(shrink(), double())).rewrite(2u32), Ok(2));
+    }
+
+    #[test]
+    fn partial_transformation() {
+        let mut halve = PartialTransformation::new(|n: &u32| {
+            if n.is_multiple_of(2) {
+                Some(n / 2)
+            } else {
+                None
+            }
+        });
+        assert_eq!(halve.rewrite(4u32), Ok(2));
+        assert_eq!(halve.rewrite(3u32), Err(3));
+        assert_eq!(halve.rewrite('a'), Err('a'));
+
+        let mut once = OnceTopDown::new(halve);
+        assert_eq!(once.rewrite(vec![1u32, 3, 6, 8]), Ok(vec![1, 3, 3, 8]));
+        assert_eq!(once.rewrite(vec![1u32, 3]), Err(vec![1, 3]));
+    }
+
+    #[test]
+    fn somewhere() {
+        let odd = || Query::new(|n: &u32| n % 2 == 1);
+        let zero = || Transformation::new(|_: u32| 0);
+
+        let v = vec![1u32, 2, 3, 5];
+        let v = Somewhere::new(odd(), zero()).rewrite(v).unwrap();
+        assert_eq!(v, vec![0, 2, 3, 5]);
+        let mut second = Somewhere::nth(1, odd(), zero());
+        let v = second.rewrite(v).unwrap();
+        assert_eq!(v, vec![0, 2, 3, 0]);
+        assert_eq!(second.rewrite(v), Err(vec![0, 2, 3, 0]));
+    }
+
+    #[test]
+    fn everywhere_retraversing() {
+        // Expand each number n > 1 into (n / 2) + (n - n / 2).
+        let split = || {
+            Rewrite::new(|e| match e {
+                Expr::Num(n) if n > 1 => Ok(add(num(n / 2), num(n - n / 2))),
+                e => Err(e),
+            })
+        };
+
+        let e = sub(num(3), num(1));
+        let once = Everywhere::new(split()).transform(sub(num(3), num(1)));
+        assert_eq!(once, sub(add(num(1), num(2)), num(1)));
+
+        let mut retraversing = EverywhereRetraversing::new(split());
+        let e = retraversing.rewrite(e).unwrap();
+        assert_eq!(e, sub(add(num(1), add(num(1), num(1))), num(1)));
+        assert!(retraversing.rewrite(e).is_err());
+    }
+
+    #[test]
+    fn fixpoint() {
+        let halve = || Everywhere::new(Rewrite::new(|n: u32| if n > 1 { Ok(n / 2) } else { Err(n) }));
+        assert_eq!(Fixpoint::new(3, halve()).run(vec![5u32, 2]), Ok(vec![1, 1]));
+
+        let err = Fixpoint::new(1, halve()).run(vec![5u32, 2]).unwrap_err();
+        assert_eq!(err, DivergenceError { value: vec![2, 1], passes: 1 });
+        assert_eq!(err.to_string(), "rewrite did not reach a fixpoint after 1 passes");
+    }
+}
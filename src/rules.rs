@@ -0,0 +1,534 @@
+use super::dispatch::erased_type_id;
+use super::term_impls::replace_in_place;
+use super::{AnyTerm, GenericRewrite, GenericTransform, Term};
+use std::any::{self, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::MaybeUninit;
+
+/// Which of the rules for a value's type a `RuleSet` applies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Matching {
+    /// Apply only the first rule that fires.
+    FirstMatch,
+    /// Apply every rule in turn, each to the result of the ones before it.
+    ApplyAll,
+}
+
+/// A typed rewrite with its type erased, which reads its `U` from the given
+/// pointer, writes the result back, and returns whether it fired.
+type ErasedRule<'f> = Box<dyn FnMut(*mut ()) -> bool + 'f>;
+
+/// A set of typed rewrites, any number for each type, applied in a fixed order
+/// of priority.
+///
+/// Where several rules apply to the same type, the ones with higher priorities
+/// are tried first, and rules with equal priorities are tried in the order
+/// they were added. By default, only the first rule that fires is applied, but
+/// `Matching::ApplyAll` applies every one of them in turn. Like a
+/// `TransformationSet`, the rules for a value's type are found in a single
+/// lookup, values are matched on their exact type, and values of types without
+/// rules are left alone.
+///
+/// As a `GenericRewrite`, a rule set fires if any of its rules fired.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let rules = || {
+///     RuleSet::new()
+///         .rule(|n: u32| if n % 2 == 0 { Ok(n / 2) } else { Err(n) })
+///         .rule_with_priority(1, |n: u32| if n > 10 { Ok(10) } else { Err(n) })
+/// };
+///
+/// let mut first = rules();
+/// assert_eq!(first.rewrite(14u32), Ok(10));
+/// assert_eq!(first.rewrite(3u32), Err(3));
+///
+/// let mut all = rules().matching(Matching::ApplyAll);
+/// assert_eq!(all.rewrite(14u32), Ok(5));
+/// ```
+pub struct RuleSet<'f> {
+    rules: HashMap<TypeId, Vec<(i32, ErasedRule<'f>)>>,
+    matching: Matching,
+    len: usize,
+}
+
+impl<'f> RuleSet<'f> {
+    /// Construct a new, empty rule set, that applies only the first rule that
+    /// fires.
+    #[inline]
+    pub fn new() -> RuleSet<'f> {
+        RuleSet {
+            rules: HashMap::new(),
+            matching: Matching::FirstMatch,
+            len: 0,
+        }
+    }
+
+    /// Set which of the rules for a value's type get applied.
+    #[inline]
+    pub fn matching(mut self, matching: Matching) -> RuleSet<'f> {
+        self.matching = matching;
+        self
+    }
+
+    /// Add a rule rewriting values of type `U`, with priority zero.
+    #[inline]
+    pub fn rule<U, F>(self, f: F) -> RuleSet<'f>
+    where
+        U: 'static,
+        F: 'f + FnMut(U) -> Result<U, U>,
+    {
+        self.rule_with_priority(0, f)
+    }
+
+    /// Add a rule rewriting values of type `U`, tried after the rules for `U`
+    /// with higher priorities, and with the same priority that were added
+    /// before it.
+    pub fn rule_with_priority<U, F>(mut self, priority: i32, mut f: F) -> RuleSet<'f>
+    where
+        U: 'static,
+        F: 'f + FnMut(U) -> Result<U, U>,
+    {
+        let erased = move |u: *mut ()| {
+            // Safe because the set only calls this with a pointer to a valid
+            // `U`.
+            let u = unsafe { &mut *(u as *mut U) };
+            let mut fired = false;
+            replace_in_place(u, |u| match f(u) {
+                Ok(u) => {
+                    fired = true;
+                    u
+                }
+                Err(u) => u,
+            });
+            fired
+        };
+        let rules = self.rules.entry(TypeId::of::<U>()).or_default();
+        let index = rules.iter().position(|&(p, _)| p < priority).unwrap_or(rules.len());
+        rules.insert(index, (priority, Box::new(erased)));
+        self.len += 1;
+        self
+    }
+
+    /// How many rules this set has.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Does this set not have any rules?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<'f> Default for RuleSet<'f> {
+    #[inline]
+    fn default() -> RuleSet<'f> {
+        RuleSet::new()
+    }
+}
+
+impl<'f> fmt::Debug for RuleSet<'f> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RuleSet")
+            .field("matching", &self.matching)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<'f> GenericRewrite for RuleSet<'f> {
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        if self.rules.is_empty() {
+            return Err(t);
+        }
+        let rules = match self.rules.get_mut(&erased_type_id::<T>()) {
+            Some(rules) => rules,
+            None => return Err(t),
+        };
+        let mut t = MaybeUninit::new(t);
+        let mut fired = false;
+        for (_, rule) in rules.iter_mut() {
+            if rule(t.as_mut_ptr() as *mut ()) {
+                fired = true;
+                if self.matching == Matching::FirstMatch {
+                    break;
+                }
+            }
+        }
+        // Safe because each rule wrote its result back.
+        let t = unsafe { t.assume_init() };
+        if fired {
+            Ok(t)
+        } else {
+            Err(t)
+        }
+    }
+}
+
+impl<'f> GenericTransform for RuleSet<'f> {
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        match self.rewrite(t) {
+            Ok(t) | Err(t) => t,
+        }
+    }
+}
+
+/// A rewrite rule on values of one type, chosen at run time, for a
+/// `RuleEngine`.
+///
+/// This is dyn compatible, so rules can be built from configuration, or
+/// supplied by plugins, as `Box<dyn DynRule>`s. `TypedRule` implements it for a
+/// predicate and rewrite function on a `U`.
+pub trait DynRule {
+    /// The name of this rule, for enabling, disabling, and reporting it.
+    fn name(&self) -> &str;
+
+    /// The `TypeId` of the type of values this rule rewrites.
+    fn target(&self) -> TypeId;
+
+    /// Rewrite the given value in place, if this rule applies to it, and
+    /// return whether it did.
+    fn rewrite_dyn(&mut self, t: AnyTerm) -> bool;
+}
+
+impl<R> DynRule for &mut R
+where
+    R: ?Sized + DynRule,
+{
+    #[inline]
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    #[inline]
+    fn target(&self) -> TypeId {
+        (**self).target()
+    }
+
+    #[inline]
+    fn rewrite_dyn(&mut self, t: AnyTerm) -> bool {
+        (**self).rewrite_dyn(t)
+    }
+}
+
+/// A `DynRule` that rewrites those values of type `U` that match a predicate.
+pub struct TypedRule<'f, U> {
+    name: String,
+    predicate: Box<dyn FnMut(&U) -> bool + 'f>,
+    rewrite: Box<dyn FnMut(U) -> U + 'f>,
+}
+
+impl<'f, U> TypedRule<'f, U>
+where
+    U: 'static,
+{
+    /// Construct a new rule named `name`, that rewrites the values of type `U`
+    /// for which `predicate` returns true with `rewrite`.
+    #[inline]
+    pub fn new<N, P, F>(name: N, predicate: P, rewrite: F) -> TypedRule<'f, U>
+    where
+        N: Into<String>,
+        P: 'f + FnMut(&U) -> bool,
+        F: 'f + FnMut(U) -> U,
+    {
+        TypedRule {
+            name: name.into(),
+            predicate: Box::new(predicate),
+            rewrite: Box::new(rewrite),
+        }
+    }
+}
+
+impl<'f, U> fmt::Debug for TypedRule<'f, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedRule")
+            .field("name", &self.name)
+            .field("target", &any::type_name::<U>())
+            .finish()
+    }
+}
+
+impl<'f, U> DynRule for TypedRule<'f, U>
+where
+    U: 'static,
+{
+    #[inline]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    fn target(&self) -> TypeId {
+        TypeId::of::<U>()
+    }
+
+    #[inline]
+    fn rewrite_dyn(&mut self, mut t: AnyTerm) -> bool {
+        match t.downcast_mut::<U>() {
+            Some(u) if (self.predicate)(u) => {
+                replace_in_place(u, &mut self.rewrite);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A registered rule, and its priority.
+type PrioritizedRule<'f> = (i32, Box<dyn DynRule + 'f>);
+
+/// A set of rewrite rules that is built at run time, such as from rules loaded
+/// per project, or from plugins.
+///
+/// Where a `RuleSet` is built once, from closures, an engine holds boxed
+/// `DynRule`s that can be registered and removed by name while the program
+/// runs. Rules are tried in the same order as in a `RuleSet`: by priority,
+/// highest first, and then in the order they were registered, applying either
+/// the first rule that fires, or all of them, per its `Matching`. The engine is
+/// a `GenericRewrite` that fires if any of its rules fired, so it runs under
+/// any of the usual strategies.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut engine = RuleEngine::new();
+/// engine.add(TypedRule::new(
+///     "no-shouting",
+///     |s: &&str| s.ends_with('!'),
+///     |s: &str| s.trim_end_matches('!'),
+/// ));
+/// engine.add(TypedRule::new("small-numbers", |n: &u32| *n > 100, |_| 100u32));
+/// assert_eq!(engine.names(), vec!["no-shouting", "small-numbers"]);
+///
+/// let value = Everywhere::new(&mut engine).transform(vec![("hi!", 1000u32), ("ok", 7)]);
+/// assert_eq!(value, vec![("hi", 100), ("ok", 7)]);
+///
+/// assert!(engine.remove("small-numbers"));
+/// assert_eq!(engine.rewrite(1000u32), Err(1000));
+/// ```
+pub struct RuleEngine<'f> {
+    rules: HashMap<TypeId, Vec<PrioritizedRule<'f>>>,
+    matching: Matching,
+    len: usize,
+}
+
+impl<'f> RuleEngine<'f> {
+    /// Construct a new engine without any rules, that applies only the first
+    /// rule that fires.
+    #[inline]
+    pub fn new() -> RuleEngine<'f> {
+        RuleEngine {
+            rules: HashMap::new(),
+            matching: Matching::FirstMatch,
+            len: 0,
+        }
+    }
+
+    /// Set which of the rules for a value's type get applied.
+    #[inline]
+    pub fn set_matching(&mut self, matching: Matching) {
+        self.matching = matching;
+    }
+
+    /// Register a rule, with priority zero.
+    #[inline]
+    pub fn add<R>(&mut self, rule: R)
+    where
+        R: 'f + DynRule,
+    {
+        self.register(0, Box::new(rule));
+    }
+
+    /// Register a boxed rule, tried after the rules for the same type with
+    /// higher priorities, and with the same priority that were registered
+    /// before it.
+    pub fn register(&mut self, priority: i32, rule: Box<dyn DynRule + 'f>) {
+        let rules = self.rules.entry(rule.target()).or_default();
+        let index = rules.iter().position(|&(p, _)| p < priority).unwrap_or(rules.len());
+        rules.insert(index, (priority, rule));
+        self.len += 1;
+    }
+
+    /// Remove every rule with the given name, and return whether there were
+    /// any.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.len;
+        for rules in self.rules.values_mut() {
+            rules.retain(|(_, rule)| rule.name() != name);
+        }
+        self.rules.retain(|_, rules| !rules.is_empty());
+        self.len = self.rules.values().map(Vec::len).sum();
+        self.len < len
+    }
+
+    /// Get the names of the rules in this engine, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self
+            .rules
+            .values()
+            .flat_map(|rules| rules.iter().map(|(_, rule)| rule.name()))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// How many rules this engine has.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Does this engine not have any rules?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<'f> Default for RuleEngine<'f> {
+    #[inline]
+    fn default() -> RuleEngine<'f> {
+        RuleEngine::new()
+    }
+}
+
+impl<'f> fmt::Debug for RuleEngine<'f> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RuleEngine")
+            .field("matching", &self.matching)
+            .field("rules", &self.names())
+            .finish()
+    }
+}
+
+impl<'f> GenericRewrite for RuleEngine<'f> {
+    fn rewrite<T>(&mut self, mut t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        if self.rules.is_empty() {
+            return Err(t);
+        }
+        let mut fired = false;
+        if let Some(rules) = self.rules.get_mut(&erased_type_id::<T>()) {
+            let mut any = AnyTerm::erased(&mut t);
+            for (_, rule) in rules.iter_mut() {
+                if rule.rewrite_dyn(any.reborrow()) {
+                    fired = true;
+                    if self.matching == Matching::FirstMatch {
+                        break;
+                    }
+                }
+            }
+        }
+        if fired {
+            Ok(t)
+        } else {
+            Err(t)
+        }
+    }
+}
+
+impl<'f> GenericTransform for RuleEngine<'f> {
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        match self.rewrite(t) {
+            Ok(t) | Err(t) => t,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Everywhere, Innermost};
+    use std::cell::RefCell;
+
+    #[test]
+    fn rule_set() {
+        let order = RefCell::new(vec![]);
+        {
+            let log = |name, fired| {
+                order.borrow_mut().push(name);
+                fired
+            };
+            let mut rules = RuleSet::new()
+                .matching(Matching::ApplyAll)
+                .rule(|n: u32| if log("a", false) { Ok(n) } else { Err(n) })
+                .rule_with_priority(-1, |n: u32| if log("low", true) { Ok(n + 1) } else { Err(n) })
+                .rule_with_priority(5, |n: u32| if log("high", true) { Ok(n * 10) } else { Err(n) })
+                .rule(|n: u32| if log("b", false) { Ok(n) } else { Err(n) })
+                .rule(|c: char| Ok(c.to_ascii_uppercase()));
+            assert_eq!(rules.len(), 5);
+            assert_eq!(rules.rewrite(1u32), Ok(11));
+            assert_eq!(rules.rewrite('a'), Ok('A'));
+            assert_eq!(rules.rewrite(1u64), Err(1));
+        }
+        assert_eq!(order.into_inner(), vec!["high", "a", "b", "low"]);
+
+        let mut collatz = RuleSet::new()
+            .rule(|n: u32| if n > 1 && n & 1 == 0 { Ok(n / 2) } else { Err(n) })
+            .rule(|n: u32| if n > 1 { Ok(3 * n + 1) } else { Err(n) });
+        assert_eq!(Innermost::new(&mut collatz).transform(vec![6u32, 7]), vec![1, 1]);
+        assert!(RuleSet::default().is_empty());
+    }
+
+    /// A plugin rule that only counts the values it sees.
+    struct Counter(u32);
+
+    impl DynRule for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn target(&self) -> TypeId {
+            TypeId::of::<char>()
+        }
+
+        fn rewrite_dyn(&mut self, _: AnyTerm) -> bool {
+            self.0 += 1;
+            false
+        }
+    }
+
+    #[test]
+    fn rule_engine() {
+        let mut counter = Counter(0);
+        {
+            let mut engine = RuleEngine::new();
+            engine.register(1, Box::new(&mut counter));
+            engine.add(TypedRule::new(
+                "upper",
+                |c: &char| c.is_lowercase(),
+                |c: char| c.to_ascii_uppercase(),
+            ));
+            engine.add(TypedRule::new("bang", |c: &char| *c == 'B', |_| '!'));
+            assert_eq!(engine.len(), 3);
+            assert_eq!(Everywhere::new(&mut engine).transform(vec!['a', 'b']), vec!['A', 'B']);
+
+            engine.set_matching(Matching::ApplyAll);
+            assert_eq!(engine.rewrite('b'), Ok('!'));
+            assert_eq!(engine.rewrite('?'), Err('?'));
+
+            assert!(engine.remove("upper") && !engine.remove("upper"));
+            assert_eq!(engine.names(), vec!["bang", "counter"]);
+        }
+        assert_eq!(counter.0, 4);
+        assert!(RuleEngine::default().is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use super::{DivergenceError, GenericRewrite, GenericTransform, Term};
+
+/// Which way a phase of a `Schedule` traverses the data structure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Rewrite each value after its children, like `Everywhere`.
+    BottomUp,
+    /// Rewrite each value before its children, like `EverywhereTopDown`.
+    TopDown,
+}
+
+/// One phase of a `Schedule`: a rewrite, the direction to traverse in when
+/// applying it, and how many times it has fired.
+#[derive(Debug)]
+pub struct Phase<F>
+where
+    F: GenericRewrite,
+{
+    direction: Direction,
+    f: F,
+    fired: usize,
+}
+
+/// A list of phases for a `Schedule` to run in order.
+///
+/// This is implemented for the lists that `Schedule::phase` builds, and isn't
+/// meant to be implemented anywhere else.
+pub trait Phases {
+    /// Run each phase once, in order, returning whether any of them fired.
+    fn run_phases<T>(&mut self, t: T) -> (T, bool)
+    where
+        T: Term;
+
+    /// Append how many times each phase has fired, in order.
+    fn fired(&self, counts: &mut Vec<usize>);
+}
+
+impl Phases for () {
+    #[inline]
+    fn run_phases<T>(&mut self, t: T) -> (T, bool)
+    where
+        T: Term,
+    {
+        (t, false)
+    }
+
+    #[inline]
+    fn fired(&self, _: &mut Vec<usize>) {}
+}
+
+impl<P, F> Phases for (P, Phase<F>)
+where
+    P: Phases,
+    F: GenericRewrite,
+{
+    #[inline]
+    fn run_phases<T>(&mut self, t: T) -> (T, bool)
+    where
+        T: Term,
+    {
+        let (t, fired) = self.0.run_phases(t);
+        let phase = &mut self.1;
+        let mut pass = PhasePass {
+            f: &mut phase.f,
+            direction: phase.direction,
+            fired: 0,
+        };
+        let t = pass.transform(t);
+        phase.fired += pass.fired;
+        (t, fired || pass.fired > 0)
+    }
+
+    #[inline]
+    fn fired(&self, counts: &mut Vec<usize>) {
+        self.0.fired(counts);
+        counts.push(self.1.fired);
+    }
+}
+
+/// Rewrites every value it is called on in the given direction, and counts how
+/// many times the rewrite fires.
+struct PhasePass<'a, F: 'a> {
+    f: &'a mut F,
+    direction: Direction,
+    fired: usize,
+}
+
+impl<'a, F> PhasePass<'a, F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        match self.f.rewrite(t) {
+            Ok(t) => {
+                self.fired += 1;
+                t
+            }
+            Err(t) => t,
+        }
+    }
+}
+
+impl<'a, F> GenericTransform for PhasePass<'a, F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        match self.direction {
+            Direction::BottomUp => {
+                let t = t.map_one_transform(self);
+                self.rewrite(t)
+            }
+            Direction::TopDown => {
+                let t = self.rewrite(t);
+                t.map_one_transform(self)
+            }
+        }
+    }
+}
+
+/// Statistics about the runs of a `Schedule`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScheduleStats {
+    /// How many rounds of all the phases have run.
+    pub rounds: usize,
+    /// How many times each phase's rewrite has fired, in the order the phases
+    /// were added.
+    pub fired: Vec<usize>,
+}
+
+/// Run an ordered list of rewriting phases, each traversing the data structure
+/// top down or bottom up, optionally repeating them all until none of them
+/// fires.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut schedule = Schedule::new()
+///     .phase(Direction::TopDown, Rewrite::new(|n: u32| if n > 9 { Ok(9) } else { Err(n) }))
+///     .phase(Direction::BottomUp, Rewrite::new(|n: u32| if n % 2 == 1 { Ok(n - 1) } else { Err(n) }))
+///     .to_fixpoint(10);
+/// assert_eq!(schedule.run(vec![3u32, 12, 4]), Ok(vec![2, 8, 4]));
+/// assert_eq!(schedule.stats().rounds, 2);
+/// assert_eq!(schedule.stats().fired, vec![1, 2]);
+/// ```
+#[derive(Debug)]
+pub struct Schedule<P>
+where
+    P: Phases,
+{
+    phases: P,
+    max_rounds: Option<usize>,
+    rounds: usize,
+}
+
+impl Schedule<()> {
+    /// Construct a new, empty schedule.
+    #[inline]
+    pub fn new() -> Schedule<()> {
+        Schedule {
+            phases: (),
+            max_rounds: None,
+            rounds: 0,
+        }
+    }
+}
+
+impl Default for Schedule<()> {
+    #[inline]
+    fn default() -> Schedule<()> {
+        Schedule::new()
+    }
+}
+
+impl<P> Schedule<P>
+where
+    P: Phases,
+{
+    /// Add a phase that traverses in the given direction applying `f`, to run
+    /// after all the phases added so far.
+    #[inline]
+    pub fn phase<F>(self, direction: Direction, f: F) -> Schedule<(P, Phase<F>)>
+    where
+        F: GenericRewrite,
+    {
+        Schedule {
+            phases: (
+                self.phases,
+                Phase {
+                    direction,
+                    f,
+                    fired: 0,
+                },
+            ),
+            max_rounds: self.max_rounds,
+            rounds: self.rounds,
+        }
+    }
+
+    /// Repeat rounds of all the phases until a whole round doesn't fire any of
+    /// them, running at most `max_rounds` rounds.
+    #[inline]
+    pub fn to_fixpoint(mut self, max_rounds: usize) -> Schedule<P> {
+        self.max_rounds = Some(max_rounds);
+        self
+    }
+
+    /// Run the phases over the given value.
+    ///
+    /// Without `to_fixpoint`, this runs a single round and always succeeds.
+    /// Otherwise, it returns a `DivergenceError` if the phases are still firing
+    /// after the maximum number of rounds.
+    pub fn run<T>(&mut self, mut t: T) -> Result<T, DivergenceError<T>>
+    where
+        T: Term,
+    {
+        let max_rounds = match self.max_rounds {
+            Some(max_rounds) => max_rounds,
+            None => {
+                self.rounds += 1;
+                return Ok(self.phases.run_phases(t).0);
+            }
+        };
+        for _ in 0..max_rounds {
+            self.rounds += 1;
+            let (new_t, fired) = self.phases.run_phases(t);
+            t = new_t;
+            if !fired {
+                return Ok(t);
+            }
+        }
+        Err(DivergenceError {
+            value: t,
+            passes: max_rounds,
+        })
+    }
+
+    /// Get statistics about all the runs of this schedule so far.
+    pub fn stats(&self) -> ScheduleStats {
+        let mut fired = vec![];
+        self.phases.fired(&mut fired);
+        ScheduleStats {
+            rounds: self.rounds,
+            fired,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Rewrite;
+
+    #[test]
+    fn schedule() {
+        let incr = || Rewrite::new(|n: u32| Ok(n + 1));
+
+        let mut once = Schedule::new()
+            .phase(Direction::BottomUp, incr())
+            .phase(Direction::TopDown, incr());
+        assert_eq!(once.run((1u32, 2u32)), Ok((3, 4)));
+        assert_eq!(
+            once.stats(),
+            ScheduleStats {
+                rounds: 1,
+                fired: vec![2, 2],
+            }
+        );
+
+        let mut forever = Schedule::new().phase(Direction::BottomUp, incr()).to_fixpoint(3);
+        assert_eq!(
+            forever.run(vec![0u32]),
+            Err(DivergenceError {
+                value: vec![3],
+                passes: 3,
+            })
+        );
+    }
+}
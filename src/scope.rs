@@ -0,0 +1,163 @@
+use super::{CastThrough, GenericQuery, GenericTransform, Term};
+use std::marker::PhantomData;
+
+/// A transformation that can also see the environment of scopes enclosing the
+/// value it is transforming. This is roughly equivalent to `for<T> FnMut(T,
+/// &[V]) -> T`.
+pub trait GenericScopedTransform<V> {
+    /// Call the transform function on any `T`, within the given environment,
+    /// from the outermost scope to the innermost.
+    fn transform<T>(&mut self, t: T, env: &[V]) -> T
+    where
+        T: Term;
+}
+
+/// A scoped transformation takes some value `U` and the environment of scopes
+/// enclosing it, and returns a new, transformed version of it. It can be called
+/// on values of *any* type `T`, not just on values of type `U`, in which case it
+/// is simply the identity function.
+///
+/// This essentially lifts a `FnMut(U, &[V]) -> U` into a `for<T> FnMut(T, &[V])
+/// -> T`.
+#[derive(Debug)]
+pub struct ScopedTransformation<F, U, V>
+where
+    F: FnMut(U, &[V]) -> U,
+{
+    f: F,
+    phantom: PhantomData<fn(U, &[V]) -> U>,
+}
+
+impl<F, U, V> ScopedTransformation<F, U, V>
+where
+    F: FnMut(U, &[V]) -> U,
+{
+    /// Construct a new `ScopedTransformation` from the given function.
+    #[inline]
+    pub fn new(f: F) -> ScopedTransformation<F, U, V> {
+        ScopedTransformation {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U, V> GenericScopedTransform<V> for ScopedTransformation<F, U, V>
+where
+    F: FnMut(U, &[V]) -> U,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, env: &[V]) -> T {
+        let f = &mut self.f;
+        t.map_as(|u| (f(u, env), ())).0
+    }
+}
+
+/// Recursively perform a scoped transformation in a bottom up manner across a
+/// data structure, keeping track of an environment of enclosing scopes.
+///
+/// On the way down, the `enter` query is asked about each value, and the scope
+/// it returns, if any, is pushed onto the environment. The scope stays there
+/// while the value's children are transformed and then the value itself, and
+/// is popped on the way back up, so it is only ever seen within that value.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Expr {
+///     Var(&'static str),
+///     Let(&'static str, Box<Expr>),
+/// }
+/// impl_term!(enum Expr {
+///     Var(name),
+///     Let(name, body),
+/// });
+///
+/// // Replace each variable with how many scopes out its binding is.
+/// let binds = Query::new(|e: &Expr| match *e {
+///     Expr::Let(name, _) => Some(name),
+///     Expr::Var(_) => None,
+/// });
+/// let depth = ScopedTransformation::new(|e: Expr, env: &[&'static str]| match e {
+///     Expr::Var(name) => match env.iter().rev().position(|bound| *bound == name) {
+///         Some(0) => Expr::Var("0"),
+///         Some(_) => Expr::Var("1+"),
+///         None => Expr::Var("free"),
+///     },
+///     e => e,
+/// });
+///
+/// let expr = Expr::Let("x", Box::new(Expr::Let("y", Box::new(Expr::Var("x")))));
+/// assert_eq!(
+///     Scoped::new(binds, depth).transform(expr),
+///     Expr::Let("x", Box::new(Expr::Let("y", Box::new(Expr::Var("1+")))))
+/// );
+/// ```
+#[derive(Debug)]
+pub struct Scoped<E, F, V>
+where
+    E: GenericQuery<Option<V>>,
+    F: GenericScopedTransform<V>,
+{
+    enter: E,
+    f: F,
+    env: Vec<V>,
+}
+
+impl<E, F, V> Scoped<E, F, V>
+where
+    E: GenericQuery<Option<V>>,
+    F: GenericScopedTransform<V>,
+{
+    /// Construct a new scoped transformation traversal.
+    #[inline]
+    pub fn new(enter: E, f: F) -> Scoped<E, F, V> {
+        Scoped {
+            enter,
+            f,
+            env: vec![],
+        }
+    }
+}
+
+impl<E, F, V> GenericTransform for Scoped<E, F, V>
+where
+    E: GenericQuery<Option<V>>,
+    F: GenericScopedTransform<V>,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let scope = self.enter.query(&t);
+        let entered = scope.is_some();
+        self.env.extend(scope);
+        let t = t.map_one_transform(self);
+        let t = self.f.transform(t, &self.env);
+        if entered {
+            self.env.pop();
+        }
+        t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Query;
+
+    #[test]
+    fn scoped() {
+        // Number each u32 with how many vectors it is nested within.
+        let enter = Query::or_else(|| None, |_: &Vec<(u32, Vec<u32>)>| Some(()));
+        let enter = enter.ext(|_: &Vec<u32>| Some(()));
+        let depth = ScopedTransformation::new(|_: u32, env: &[()]| env.len() as u32);
+
+        let mut scoped = Scoped::new(enter, depth);
+        let value = (0u32, vec![(0u32, vec![0u32])]);
+        assert_eq!(scoped.transform(value), (0, vec![(1, vec![2])]));
+        assert_eq!(scoped.transform(0u32), 0);
+    }
+}
@@ -0,0 +1,171 @@
+use super::{GenericPathedTransform, GenericRewrite, GenericTransform, Path, PathedEverywhere, Term};
+
+/// A rewrite of a data structure to normal form, one rewrite at a time, that
+/// can be inspected and resumed between steps.
+///
+/// Each step rewrites the first value where the rewrite fires, searching depth
+/// first and trying each value's children before the value itself, just like
+/// `OnceBottomUp`. Stepping until the rewrite doesn't fire anywhere gives the
+/// same result as `Innermost` would, but a debugger-like tool can look at the
+/// intermediate data structure, and where it changed, after each step.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let halve = Rewrite::new(|n: u32| if n > 1 && n % 2 == 0 { Ok(n / 2) } else { Err(n) });
+/// let mut state = TraversalState::new(vec![4u32, 3], halve);
+///
+/// assert_eq!(state.step().map(|p| p.to_string()), Some("[0]".to_string()));
+/// assert_eq!(state.get(), &vec![2, 3]);
+/// assert_eq!(state.step().map(|p| p.to_string()), Some("[0]".to_string()));
+/// assert_eq!(state.step(), None);
+/// assert_eq!((state.steps(), state.into_inner()), (2, vec![1, 3]));
+/// ```
+#[derive(Debug)]
+pub struct TraversalState<T, F>
+where
+    T: Term,
+    F: GenericRewrite,
+{
+    t: Option<T>,
+    f: F,
+    steps: usize,
+    done: bool,
+}
+
+impl<T, F> TraversalState<T, F>
+where
+    T: Term,
+    F: GenericRewrite,
+{
+    /// Start rewriting `t` with `f`, without rewriting anything yet.
+    #[inline]
+    pub fn new(t: T, f: F) -> TraversalState<T, F> {
+        TraversalState {
+            t: Some(t),
+            f,
+            steps: 0,
+            done: false,
+        }
+    }
+
+    /// Rewrite the next value where the rewrite fires, and return the path to
+    /// it, or `None` if the rewrite doesn't fire anywhere anymore.
+    pub fn step(&mut self) -> Option<Path> {
+        if self.done {
+            return None;
+        }
+        let t = self.t.take().expect("the value is only taken while stepping");
+        let mut first = FirstFired {
+            f: &mut self.f,
+            fired: None,
+        };
+        self.t = Some(PathedEverywhere::new(&mut first).transform(t));
+        match first.fired {
+            Some(path) => {
+                self.steps += 1;
+                Some(path)
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+
+    /// Step until the rewrite doesn't fire anywhere anymore, and return how
+    /// many steps that took.
+    pub fn run(&mut self) -> usize {
+        let mut steps = 0;
+        while self.step().is_some() {
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Has a step found that the rewrite doesn't fire anywhere anymore?
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Get how many steps have rewritten something so far.
+    #[inline]
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Get the data structure as rewritten so far.
+    #[inline]
+    pub fn get(&self) -> &T {
+        self.t.as_ref().expect("the value is only taken while stepping")
+    }
+
+    /// Stop stepping, and get the data structure as rewritten so far.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.t.expect("the value is only taken while stepping")
+    }
+}
+
+/// Applies a rewrite until it first fires, recording where.
+struct FirstFired<'a, F: 'a> {
+    f: &'a mut F,
+    fired: Option<Path>,
+}
+
+impl<'a, F> GenericPathedTransform for FirstFired<'a, F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, path: &Path) -> T
+    where
+        T: Term,
+    {
+        if self.fired.is_some() {
+            return t;
+        }
+        match self.f.rewrite(t) {
+            Ok(t) => {
+                self.fired = Some(path.clone());
+                t
+            }
+            Err(t) => t,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{GenericTransform, Innermost, Rewrite};
+
+    type Rule = Rewrite<fn(Vec<u32>) -> Result<Vec<u32>, Vec<u32>>, Vec<u32>>;
+
+    /// Collapses a vector of numbers into one element, their sum.
+    fn collapse() -> Rule {
+        Rewrite::new(|v| {
+            if v.len() > 1 {
+                Ok(vec![v.iter().sum()])
+            } else {
+                Err(v)
+            }
+        })
+    }
+
+    #[test]
+    fn traversal_state() {
+        let value = vec![vec![vec![1u32, 2], vec![3]], vec![vec![4, 5]]];
+        let mut state = TraversalState::new(value.clone(), collapse());
+        let paths: Vec<_> = (0..2).filter_map(|_| state.step()).map(|p| p.to_string()).collect();
+        assert_eq!(paths, vec!["[0][0]", "[1][0]"]);
+        assert_eq!(state.get(), &vec![vec![vec![3u32], vec![3]], vec![vec![9]]]);
+        assert!(!state.is_done());
+        assert_eq!(state.run(), 0);
+        assert!(state.is_done());
+        assert_eq!(state.step(), None);
+        assert_eq!(state.steps(), 2);
+        assert_eq!(state.into_inner(), Innermost::new(collapse()).transform(value));
+    }
+}
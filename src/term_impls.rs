@@ -1,6 +1,21 @@
-use super::{GenericMutate, GenericQuery, GenericTransform, Term};
+use super::{CowView, GenericMutate, GenericQuery, GenericTransform, Term};
+use std::borrow::{Borrow, Cow};
+use std::cell::{Cell, RefCell};
 use std::collections::*;
-use std::iter::FromIterator;
+use std::ffi::OsString;
+use std::iter::{self, FromIterator};
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::num::{
+    NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU16, NonZeroU32, NonZeroU64,
+    NonZeroU8, NonZeroUsize,
+};
+use std::path::PathBuf;
+use std::process;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
 macro_rules! impl_trivial_term {
     ( $name:ty ) => {
@@ -32,6 +47,7 @@ macro_rules! impl_trivial_term {
 
 impl_trivial_term!(());
 impl_trivial_term!(&'static str);
+impl_trivial_term!(String);
 impl_trivial_term!(bool);
 impl_trivial_term!(char);
 impl_trivial_term!(f32);
@@ -46,10 +62,32 @@ impl_trivial_term!(i8);
 impl_trivial_term!(i16);
 impl_trivial_term!(i32);
 impl_trivial_term!(i64);
+impl_trivial_term!(NonZeroU8);
+impl_trivial_term!(NonZeroU16);
+impl_trivial_term!(NonZeroU32);
+impl_trivial_term!(NonZeroU64);
+impl_trivial_term!(NonZeroUsize);
+impl_trivial_term!(NonZeroI8);
+impl_trivial_term!(NonZeroI16);
+impl_trivial_term!(NonZeroI32);
+impl_trivial_term!(NonZeroI64);
+impl_trivial_term!(NonZeroIsize);
+impl_trivial_term!(Duration);
+impl_trivial_term!(Instant);
+impl_trivial_term!(SystemTime);
+impl_trivial_term!(PathBuf);
+impl_trivial_term!(OsString);
+impl_trivial_term!(IpAddr);
+impl_trivial_term!(Ipv4Addr);
+impl_trivial_term!(Ipv6Addr);
+impl_trivial_term!(SocketAddr);
+impl_trivial_term!(SocketAddrV4);
+impl_trivial_term!(SocketAddrV6);
 
+// The trailing commas make the 1-tuple `(A,)` rather than just `(A)`.
 macro_rules! impl_tuple_term {
-    ( $name:ident $( , $names:ident )* ) => {
-        impl<$name $( , $names )* > Term for ($name $( , $names )* )
+    ( $name:ident $( , $names:ident )* ; $( $rev:ident ),* ) => {
+        impl<$name $( , $names )* > Term for ($name, $( $names, )* )
         where
             $name: Term $(, $names : Term )*
         {
@@ -59,8 +97,8 @@ macro_rules! impl_tuple_term {
             where
                 FF: GenericTransform,
             {
-                let ( $name $( , $names )* ) = self;
-                ( f.transform( $name ) $( , f.transform( $names ) )* )
+                let ( $name, $( $names, )* ) = self;
+                ( f.transform( $name ), $( f.transform( $names ), )* )
             }
 
             #[inline]
@@ -70,7 +108,7 @@ macro_rules! impl_tuple_term {
                 Q: GenericQuery<R>,
                 FF: FnMut(&mut Q, R),
             {
-                let ( ref $name $( , ref $names )* ) = *self;
+                let ( ref $name, $( ref $names, )* ) = *self;
                 let r = q.query( $name );
                 each(q, r);
                 $(
@@ -86,7 +124,7 @@ macro_rules! impl_tuple_term {
                 M: GenericMutate<R>,
                 FF: FnMut(&mut M, R),
             {
-                let ( ref mut $name $( , ref mut $names )* ) = *self;
+                let ( ref mut $name, $( ref mut $names, )* ) = *self;
                 let r = m.mutate( $name );
                 each(m, r);
                 $(
@@ -94,32 +132,73 @@ macro_rules! impl_tuple_term {
                     each(m, r);
                 )*
             }
+
+            #[inline]
+            #[allow(non_snake_case)]
+            fn map_one_transform_rev<FF>(self, f: &mut FF) -> Self
+            where
+                FF: GenericTransform,
+            {
+                let ( $name, $( $names, )* ) = self;
+                $( let $rev = f.transform( $rev ); )*
+                ( $name, $( $names, )* )
+            }
+
+            #[inline]
+            #[allow(non_snake_case)]
+            fn map_one_query_rev<Q, R, FF>(&self, q: &mut Q, mut each: FF)
+            where
+                Q: GenericQuery<R>,
+                FF: FnMut(&mut Q, R),
+            {
+                let ( ref $name, $( ref $names, )* ) = *self;
+                $(
+                    let r = q.query( $rev );
+                    each(q, r);
+                )*
+            }
+
+            #[inline]
+            #[allow(non_snake_case)]
+            fn map_one_mutation_rev<M, R, FF>(&mut self, m: &mut M, mut each: FF)
+            where
+                M: GenericMutate<R>,
+                FF: FnMut(&mut M, R),
+            {
+                let ( ref mut $name, $( ref mut $names, )* ) = *self;
+                $(
+                    let r = m.mutate( $rev );
+                    each(m, r);
+                )*
+            }
         }
     }
 }
 
-impl_tuple_term!(A, B);
-impl_tuple_term!(A, B, C);
-impl_tuple_term!(A, B, C, D);
-impl_tuple_term!(A, B, C, D, E);
-impl_tuple_term!(A, B, C, D, E, F);
-impl_tuple_term!(A, B, C, D, E, F, G);
-impl_tuple_term!(A, B, C, D, E, F, G, H);
-impl_tuple_term!(A, B, C, D, E, F, G, H, I);
-impl_tuple_term!(A, B, C, D, E, F, G, H, I, J);
-impl_tuple_term!(A, B, C, D, E, F, G, H, I, J, K);
-impl_tuple_term!(A, B, C, D, E, F, G, H, I, J, K, L);
+impl_tuple_term!(A; A);
+impl_tuple_term!(A, B; B, A);
+impl_tuple_term!(A, B, C; C, B, A);
+impl_tuple_term!(A, B, C, D; D, C, B, A);
+impl_tuple_term!(A, B, C, D, E; E, D, C, B, A);
+impl_tuple_term!(A, B, C, D, E, F; F, E, D, C, B, A);
+impl_tuple_term!(A, B, C, D, E, F, G; G, F, E, D, C, B, A);
+impl_tuple_term!(A, B, C, D, E, F, G, H; H, G, F, E, D, C, B, A);
+impl_tuple_term!(A, B, C, D, E, F, G, H, I; I, H, G, F, E, D, C, B, A);
+impl_tuple_term!(A, B, C, D, E, F, G, H, I, J; J, I, H, G, F, E, D, C, B, A);
+impl_tuple_term!(A, B, C, D, E, F, G, H, I, J, K; K, J, I, H, G, F, E, D, C, B, A);
+impl_tuple_term!(A, B, C, D, E, F, G, H, I, J, K, L; L, K, J, I, H, G, F, E, D, C, B, A);
 
 impl<T> Term for Vec<T>
 where
     T: Term,
 {
     #[inline]
-    fn map_one_transform<F>(mut self, f: &mut F) -> Vec<T>
+    fn map_one_transform<F>(self, f: &mut F) -> Vec<T>
     where
         F: GenericTransform,
     {
-        self.drain(..).map(|t| f.transform(t)).collect()
+        let len = self.len();
+        transform_vec_in_place(self, f, 0..len)
     }
 
     #[inline]
@@ -149,6 +228,113 @@ where
             })
             .count();
     }
+
+    #[inline]
+    fn map_one_transform_rev<F>(self, f: &mut F) -> Vec<T>
+    where
+        F: GenericTransform,
+    {
+        let len = self.len();
+        transform_vec_in_place(self, f, (0..len).rev())
+    }
+
+    #[inline]
+    fn map_one_query_rev<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        self.iter().rev().for_each(|t| {
+            let r = query.query(t);
+            each(query, r);
+        });
+    }
+
+    #[inline]
+    fn map_one_mutation_rev<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        self.iter_mut().rev().for_each(|t| {
+            let r = mutation.mutate(t);
+            each(mutation, r);
+        });
+    }
+}
+
+impl<T, const N: usize> Term for [T; N]
+where
+    T: Term,
+{
+    #[inline]
+    fn map_one_transform<F>(self, f: &mut F) -> [T; N]
+    where
+        F: GenericTransform,
+    {
+        // `array::map` builds the new array in place, without needing a
+        // default element or allocating.
+        self.map(|t| f.transform(t))
+    }
+
+    #[inline]
+    fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        self.iter().for_each(|t| {
+            let r = query.query(t);
+            each(query, r);
+        });
+    }
+
+    #[inline]
+    fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        self.iter_mut().for_each(|t| {
+            let r = mutation.mutate(t);
+            each(mutation, r);
+        });
+    }
+
+    #[inline]
+    fn map_one_transform_rev<F>(mut self, f: &mut F) -> [T; N]
+    where
+        F: GenericTransform,
+    {
+        self.reverse();
+        let mut a = self.map(|t| f.transform(t));
+        a.reverse();
+        a
+    }
+
+    #[inline]
+    fn map_one_query_rev<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        self.iter().rev().for_each(|t| {
+            let r = query.query(t);
+            each(query, r);
+        });
+    }
+
+    #[inline]
+    fn map_one_mutation_rev<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        self.iter_mut().rev().for_each(|t| {
+            let r = mutation.mutate(t);
+            each(mutation, r);
+        });
+    }
 }
 
 impl<T> Term for Box<T>
@@ -156,11 +342,13 @@ where
     T: Sized + Term,
 {
     #[inline]
-    fn map_one_transform<F>(self, f: &mut F) -> Box<T>
+    fn map_one_transform<F>(mut self, f: &mut F) -> Box<T>
     where
         F: GenericTransform,
     {
-        Box::new(f.transform(*self))
+        // Move the value out and back into the same allocation.
+        *self = f.transform(*self);
+        self
     }
 
     #[inline]
@@ -184,8 +372,335 @@ where
     }
 }
 
+/// Aborts the process if dropped, which only happens while unwinding.
+struct AbortOnUnwind;
+
+impl Drop for AbortOnUnwind {
+    fn drop(&mut self) {
+        process::abort();
+    }
+}
+
+/// Replace the value behind a mutable reference with `f` of it, without a
+/// placeholder.
+///
+/// Nothing else can see the reference until the result is written back, and if
+/// `f` panics instead, the process aborts before anything can see the
+/// moved-out value.
+#[inline]
+pub(crate) fn replace_in_place<T, F>(t: &mut T, f: F)
+where
+    F: FnOnce(T) -> T,
+{
+    let guard = AbortOnUnwind;
+    // Safe because of the above.
+    unsafe {
+        let rewritten = f(ptr::read(t));
+        ptr::write(t, rewritten);
+    }
+    mem::forget(guard);
+}
+
+/// Transform each element of a vector where it is, in the given order of
+/// indices, so that the vector keeps its allocation.
+///
+/// The vector's length is zero while its elements are being transformed, so if
+/// `f` panics, the elements are leaked rather than dropped while one of them is
+/// moved out.
+#[inline]
+fn transform_vec_in_place<T, F, I>(mut v: Vec<T>, f: &mut F, indices: I) -> Vec<T>
+where
+    T: Term,
+    F: GenericTransform,
+    I: Iterator<Item = usize>,
+{
+    let len = v.len();
+    // Safe because of the above, and because `indices` only yields indices of
+    // elements, each of them once.
+    unsafe {
+        v.set_len(0);
+        let elements = v.as_mut_ptr();
+        for i in indices {
+            let t = ptr::read(elements.add(i));
+            ptr::write(elements.add(i), f.transform(t));
+        }
+        v.set_len(len);
+    }
+    v
+}
+
+// Transforming an `Rc` or `Arc` by value unwraps it if this is the only
+// reference to its value, and clones its value otherwise. Mutating one is
+// copy-on-write, with `make_mut`.
+macro_rules! impl_shared_term {
+    ($shared:ident) => {
+        impl<T> Term for $shared<T>
+        where
+            T: Clone + Term,
+        {
+            #[inline]
+            fn map_one_transform<F>(self, f: &mut F) -> $shared<T>
+            where
+                F: GenericTransform,
+            {
+                $shared::new(f.transform($shared::unwrap_or_clone(self)))
+            }
+
+            #[inline]
+            fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+            where
+                Q: GenericQuery<R>,
+                F: FnMut(&mut Q, R),
+            {
+                let r = query.query(&**self);
+                each(query, r);
+            }
+
+            #[inline]
+            fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+            where
+                M: GenericMutate<R>,
+                F: FnMut(&mut M, R),
+            {
+                let r = mutation.mutate($shared::make_mut(self));
+                each(mutation, r);
+            }
+        }
+    };
+}
+
+impl_shared_term!(Rc);
+impl_shared_term!(Arc);
+
+impl<T, E> Term for Result<T, E>
+where
+    T: Term,
+    E: Term,
+{
+    #[inline]
+    fn map_one_transform<F>(self, f: &mut F) -> Result<T, E>
+    where
+        F: GenericTransform,
+    {
+        match self {
+            Ok(t) => Ok(f.transform(t)),
+            Err(e) => Err(f.transform(e)),
+        }
+    }
+
+    #[inline]
+    fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        let r = match *self {
+            Ok(ref t) => query.query(t),
+            Err(ref e) => query.query(e),
+        };
+        each(query, r);
+    }
+
+    #[inline]
+    fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        let r = match *self {
+            Ok(ref mut t) => mutation.mutate(t),
+            Err(ref mut e) => mutation.mutate(e),
+        };
+        each(mutation, r);
+    }
+}
+
+// A `Cow`'s one child is its value as the owned type, whether or not it is
+// borrowed, so that the same typed transformations and mutations apply either
+// way. Transforming or mutating a borrowed value traverses an owned copy of
+// it, which replaces the borrowed value only if it ended up different. Queries
+// don't copy anything, so they see either kind of value as a `CowView<B>`.
+impl<'a, B> Term for Cow<'a, B>
+where
+    B: 'a + ?Sized + ToOwned + PartialEq,
+    B::Owned: Term,
+{
+    #[inline]
+    fn map_one_transform<F>(self, f: &mut F) -> Cow<'a, B>
+    where
+        F: GenericTransform,
+    {
+        match self {
+            Cow::Owned(o) => Cow::Owned(f.transform(o)),
+            Cow::Borrowed(b) => {
+                let o = f.transform(b.to_owned());
+                if *o.borrow() == *b {
+                    Cow::Borrowed(b)
+                } else {
+                    Cow::Owned(o)
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        let r = CowView::with(&**self, |view| query.query(view));
+        each(query, r);
+    }
+
+    #[inline]
+    fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        let r = match *self {
+            Cow::Owned(ref mut o) => mutation.mutate(o),
+            Cow::Borrowed(b) => {
+                let mut o = b.to_owned();
+                let r = mutation.mutate(&mut o);
+                if *o.borrow() != *b {
+                    *self = Cow::Owned(o);
+                }
+                r
+            }
+        };
+        each(mutation, r);
+    }
+}
+
+/// Queries see a copy of a `Cell`'s value, since there's no way to borrow it.
+impl<T> Term for Cell<T>
+where
+    T: Copy + Term,
+{
+    #[inline]
+    fn map_one_transform<F>(self, f: &mut F) -> Cell<T>
+    where
+        F: GenericTransform,
+    {
+        Cell::new(f.transform(self.into_inner()))
+    }
+
+    #[inline]
+    fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        let r = query.query(&self.get());
+        each(query, r);
+    }
+
+    #[inline]
+    fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        let r = mutation.mutate(self.get_mut());
+        each(mutation, r);
+    }
+}
+
+/// Queries borrow a `RefCell`'s value.
+///
+/// # Panics
+///
+/// Querying a `RefCell` panics if its value is currently mutably borrowed, just
+/// like `RefCell::borrow`. Transforming and mutating one never panic, since
+/// they have the `RefCell` to themselves.
+impl<T> Term for RefCell<T>
+where
+    T: Term,
+{
+    #[inline]
+    fn map_one_transform<F>(self, f: &mut F) -> RefCell<T>
+    where
+        F: GenericTransform,
+    {
+        RefCell::new(f.transform(self.into_inner()))
+    }
+
+    #[inline]
+    fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+    where
+        Q: GenericQuery<R>,
+        F: FnMut(&mut Q, R),
+    {
+        let r = query.query(&*self.borrow());
+        each(query, r);
+    }
+
+    #[inline]
+    fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+    where
+        M: GenericMutate<R>,
+        F: FnMut(&mut M, R),
+    {
+        let r = mutation.mutate(self.get_mut());
+        each(mutation, r);
+    }
+}
+
+// Queries lock a `Mutex` or `RwLock` while querying its value, waiting for any
+// other thread holding it. Poisoned locks are traversed like any other, since a
+// traversal of the value doesn't rely on whatever invariant the panicking
+// thread might have broken.
+macro_rules! impl_lock_term {
+    ($lock:ident, $guard:ident) => {
+        impl<T> Term for $lock<T>
+        where
+            T: Term,
+        {
+            #[inline]
+            fn map_one_transform<F>(self, f: &mut F) -> $lock<T>
+            where
+                F: GenericTransform,
+            {
+                let t = self.into_inner().unwrap_or_else(PoisonError::into_inner);
+                $lock::new(f.transform(t))
+            }
+
+            #[inline]
+            fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+            where
+                Q: GenericQuery<R>,
+                F: FnMut(&mut Q, R),
+            {
+                let r = {
+                    let guard = self.$guard().unwrap_or_else(PoisonError::into_inner);
+                    query.query(&*guard)
+                };
+                each(query, r);
+            }
+
+            #[inline]
+            fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+            where
+                M: GenericMutate<R>,
+                F: FnMut(&mut M, R),
+            {
+                let r = mutation.mutate(self.get_mut().unwrap_or_else(PoisonError::into_inner));
+                each(mutation, r);
+            }
+        }
+    };
+}
+
+impl_lock_term!(Mutex, lock);
+impl_lock_term!(RwLock, read);
+
 macro_rules! impl_iter_term {
     ($iter:ty) => {
+        impl_iter_term!($iter {});
+    };
+    ($iter:ty { $( $rev:tt )* }) => {
         impl <T> Term for $iter
         where
             $iter: IntoIterator<Item = T> + FromIterator<T>,
@@ -211,7 +726,7 @@ macro_rules! impl_iter_term {
                 });
             }
 
-            fn map_one_mutation<'a, M, R, F>(&'a mut self, mutation: &mut M, mut each: F)
+            fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
             where
                 M: GenericMutate<R>,
                 F: FnMut(&mut M, R)
@@ -221,31 +736,74 @@ macro_rules! impl_iter_term {
                     each(mutation, r);
                 });
             }
+
+            $( $rev )*
         }
     }
 }
 
-impl_iter_term!(LinkedList<T>);
-impl_iter_term!(HashSet<T>);
-impl_iter_term!(BTreeSet<T>);
-impl_iter_term!(BinaryHeap<T>);
-impl_iter_term!(VecDeque<T>);
+/// Reverse order traversal for sequences that can be built back to front.
+macro_rules! impl_rev_methods {
+    ($iter:ty) => {
+        fn map_one_transform_rev<F>(mut self, f: &mut F) -> $iter
+        where
+            F: GenericTransform
+        {
+            let mut rev = <$iter>::new();
+            while let Some(t) = self.pop_back() {
+                rev.push_front(f.transform(t));
+            }
+            rev
+        }
 
+        fn map_one_query_rev<Q, R, F>(&self, query: &mut Q, mut each: F)
+        where
+            Q: GenericQuery<R>,
+            F: FnMut(&mut Q, R)
+        {
+            self.iter().rev().for_each(|t| {
+                let r = query.query(t);
+                each(query, r);
+            });
+        }
 
-macro_rules! impl_iterkey_term {
-    ($iter:ty) => {
-        impl <K,T> Term for $iter
+        fn map_one_mutation_rev<M, R, F>(&mut self, mutation: &mut M, mut each: F)
         where
-            $iter: IntoIterator<Item = (K,T)> + FromIterator<(K,T)>,
-            for <'b> &'b $iter: IntoIterator<Item = &'b (K,T)>,
-            for <'b> &'b mut $iter: IntoIterator<Item = &'b mut (K,T)>,
-            (K,T): Term
+            M: GenericMutate<R>,
+            F: FnMut(&mut M, R)
         {
-            fn map_one_transform<F>(self, f: &mut F) -> $iter
+            self.iter_mut().rev().for_each(|t| {
+                let r = mutation.mutate(t);
+                each(mutation, r);
+            });
+        }
+    }
+}
+
+impl_iter_term!(LinkedList<T> { impl_rev_methods!(LinkedList<T>); });
+impl_iter_term!(VecDeque<T> { impl_rev_methods!(VecDeque<T>); });
+
+// The elements of sets and heaps are their children, but they can't be mutated
+// where they are, since that could break the invariants of the collection. So
+// both transforming and mutating one rebuilds it from its elements, after which
+// elements that became equal are merged in sets, and heaps are reordered.
+macro_rules! impl_set_term {
+    ($set:ty; $( $param:ident ),*) => {
+        impl_set_term!($set; $( $param ),* where {});
+    };
+    ($set:ty; $( $param:ident ),* where $( $bounded:ident: $bound:path ),* { $( $rev:tt )* }) => {
+        impl< $( $param ),* > Term for $set
+        where
+            $( $bounded: $bound, )*
+            $set: IntoIterator<Item = T> + FromIterator<T> + Default,
+            for <'b> &'b $set: IntoIterator<Item = &'b T>,
+            T: Term
+        {
+            fn map_one_transform<F>(self, f: &mut F) -> $set
             where
                 F: GenericTransform
             {
-                self.into_iter().map(|x| f.transform(x)).collect()
+                self.into_iter().map(|t| f.transform(t)).collect()
             }
 
             fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
@@ -259,22 +817,157 @@ macro_rules! impl_iterkey_term {
                 });
             }
 
-            fn map_one_mutation<'a, M, R, F>(&'a mut self, mutation: &mut M, mut each: F)
+            fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+            where
+                M: GenericMutate<R>,
+                F: FnMut(&mut M, R)
+            {
+                *self = mem::take(self)
+                    .into_iter()
+                    .map(|mut t| {
+                        let r = mutation.mutate(&mut t);
+                        each(mutation, r);
+                        t
+                    })
+                    .collect();
+            }
+
+            $( $rev )*
+        }
+    }
+}
+
+/// Reverse order traversal for sets that are ordered.
+macro_rules! impl_set_rev_methods {
+    ($set:ty) => {
+        fn map_one_transform_rev<F>(mut self, f: &mut F) -> $set
+        where
+            F: GenericTransform
+        {
+            iter::from_fn(|| self.pop_last()).map(|t| f.transform(t)).collect()
+        }
+
+        fn map_one_query_rev<Q, R, F>(&self, query: &mut Q, mut each: F)
+        where
+            Q: GenericQuery<R>,
+            F: FnMut(&mut Q, R)
+        {
+            self.iter().rev().for_each(|t| {
+                let r = query.query(t);
+                each(query, r);
+            });
+        }
+
+        fn map_one_mutation_rev<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+        where
+            M: GenericMutate<R>,
+            F: FnMut(&mut M, R)
+        {
+            let mut old = mem::take(self);
+            *self = iter::from_fn(|| old.pop_last())
+                .map(|mut t| {
+                    let r = mutation.mutate(&mut t);
+                    each(mutation, r);
+                    t
+                })
+                .collect();
+        }
+    }
+}
+
+impl_set_term!(HashSet<T, S>; T, S);
+impl_set_term!(BTreeSet<T>; T where T: Ord { impl_set_rev_methods!(BTreeSet<T>); });
+impl_set_term!(BinaryHeap<T>; T);
+
+// The values of maps are their children, and their keys are not traversed, so
+// that mutating a map changes its values in place, and transforming one never
+// makes keys collide. To rewrite keys, transform the map as a whole with a
+// typed transformation that rebuilds it.
+macro_rules! impl_map_term {
+    ($map:ty; $( $param:ident ),*) => {
+        impl_map_term!($map; $( $param ),* where {});
+    };
+    ($map:ty; $( $param:ident ),* where $( $bounded:ident: $bound:path ),* { $( $rev:tt )* }) => {
+        impl< $( $param ),* > Term for $map
+        where
+            $( $bounded: $bound, )*
+            $map: IntoIterator<Item = (K, T)> + FromIterator<(K, T)>,
+            for <'b> &'b $map: IntoIterator<Item = (&'b K, &'b T)>,
+            for <'b> &'b mut $map: IntoIterator<Item = (&'b K, &'b mut T)>,
+            T: Term
+        {
+            fn map_one_transform<F>(self, f: &mut F) -> $map
+            where
+                F: GenericTransform
+            {
+                self.into_iter().map(|(k, t)| (k, f.transform(t))).collect()
+            }
+
+            fn map_one_query<Q, R, F>(&self, query: &mut Q, mut each: F)
+            where
+                Q: GenericQuery<R>,
+                F: FnMut(&mut Q, R)
+            {
+                self.into_iter().for_each(|(_, t)| {
+                    let r = query.query(t);
+                    each(query, r);
+                });
+            }
+
+            fn map_one_mutation<M, R, F>(&mut self, mutation: &mut M, mut each: F)
             where
                 M: GenericMutate<R>,
                 F: FnMut(&mut M, R)
             {
-                self.into_iter().for_each(|t: &mut (K,T)| {
+                self.into_iter().for_each(|(_, t)| {
                     let r = mutation.mutate(t);
                     each(mutation, r);
                 });
             }
+
+            $( $rev )*
+        }
+    }
+}
+
+/// Reverse order traversal for maps that are ordered by key.
+macro_rules! impl_map_rev_methods {
+    ($map:ty) => {
+        fn map_one_transform_rev<F>(mut self, f: &mut F) -> $map
+        where
+            F: GenericTransform
+        {
+            iter::from_fn(|| self.pop_last())
+                .map(|(k, t)| (k, f.transform(t)))
+                .collect()
+        }
+
+        fn map_one_query_rev<Q, R, F>(&self, query: &mut Q, mut each: F)
+        where
+            Q: GenericQuery<R>,
+            F: FnMut(&mut Q, R)
+        {
+            self.values().rev().for_each(|t| {
+                let r = query.query(t);
+                each(query, r);
+            });
+        }
+
+        fn map_one_mutation_rev<M, R, F>(&mut self, mutation: &mut M, mut each: F)
+        where
+            M: GenericMutate<R>,
+            F: FnMut(&mut M, R)
+        {
+            self.values_mut().rev().for_each(|t| {
+                let r = mutation.mutate(t);
+                each(mutation, r);
+            });
         }
     }
 }
 
-impl_iterkey_term!(HashMap<K,T>);
-impl_iterkey_term!(BTreeMap<K,T>);
+impl_map_term!(HashMap<K, T, S>; K, T, S);
+impl_map_term!(BTreeMap<K, T>; K, T where K: Ord { impl_map_rev_methods!(BTreeMap<K, T>); });
 
 // TODO
 //
@@ -282,7 +975,6 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // is either all the stable `std` types, or pretty close to them. We need to
 // implement `Term` for all of these :)
 //
-// enum std::borrow::Cow
 // enum std::cmp::Ordering
 // enum std::collections::Bound
 // enum std::collections::btree_map::Entry
@@ -290,16 +982,13 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // enum std::io::CharsError
 // enum std::io::ErrorKind
 // enum std::io::SeekFrom
-// enum std::net::IpAddr
 // enum std::net::Ipv6MulticastScope
 // enum std::net::Shutdown
-// enum std::net::SocketAddr
 // enum std::num::FpCategory
 // enum std::option::Option
 // enum std::os::raw::c_void
 // enum std::path::Component
 // enum std::path::Prefix
-// enum std::result::Result
 // enum std::str::pattern::SearchStep
 // enum std::string::ParseError
 // enum std::sync::TryLockError
@@ -312,9 +1001,7 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // struct std::ascii::EscapeDefault
 // struct std::cell::BorrowError
 // struct std::cell::BorrowMutError
-// struct std::cell::Cell
 // struct std::cell::Ref
-// struct std::cell::RefCell
 // struct std::cell::RefMut
 // struct std::cell::UnsafeCell
 // struct std::char::CharTryFromError
@@ -378,7 +1065,6 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // struct std::ffi::IntoStringError
 // struct std::ffi::NulError
 // struct std::ffi::OsStr
-// struct std::ffi::OsString
 // struct std::fmt::Arguments
 // struct std::fmt::Error
 // struct std::fs::DirBuilder
@@ -431,11 +1117,7 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // struct std::mem::Discriminant
 // struct std::net::AddrParseError
 // struct std::net::Incoming
-// struct std::net::Ipv4Addr
-// struct std::net::Ipv6Addr
 // struct std::net::LookupHost
-// struct std::net::SocketAddrV4
-// struct std::net::SocketAddrV6
 // struct std::net::TcpListener
 // struct std::net::TcpStream
 // struct std::net::UdpSocket
@@ -463,7 +1145,6 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // struct std::path::Display
 // struct std::path::Iter
 // struct std::path::Path
-// struct std::path::PathBuf
 // struct std::path::PrefixComponent
 // struct std::path::StripPrefixError
 // struct std::process::Child
@@ -474,7 +1155,6 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // struct std::process::ExitStatus
 // struct std::process::Output
 // struct std::process::Stdio
-// struct std::rc::Rc
 // struct std::rc::Weak
 // struct std::result::IntoIter
 // struct std::result::Iter
@@ -518,8 +1198,6 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // struct std::string::FromUtf16Error
 // struct std::string::FromUtf8Error
 // struct std::string::Splice
-// struct std::string::String
-// struct std::sync::Arc
 // struct std::sync::Barrier
 // struct std::sync::BarrierWaitResult
 // struct std::sync::Condvar
@@ -551,9 +1229,6 @@ impl_iterkey_term!(BTreeMap<K,T>);
 // struct std::thread::LocalKey
 // struct std::thread::Thread
 // struct std::thread::ThreadId
-// struct std::time::Duration
-// struct std::time::Instant
-// struct std::time::SystemTime
 // struct std::time::SystemTimeError
 // struct std::vec::Drain
 // struct std::vec::IntoIter
@@ -0,0 +1,543 @@
+use super::{
+    for_each_with_path, GenericPathedTransform, GenericRewrite, GenericTransform, Path,
+    PathedEverywhere, Term,
+};
+use std::any;
+#[cfg(feature = "tracing")]
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// Render a value with its `Debug` implementation, if it has one.
+trait MaybeDebug {
+    fn maybe_debug(&self) -> Option<String>;
+}
+
+impl<T> MaybeDebug for T {
+    #[inline(always)]
+    default fn maybe_debug(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<T> MaybeDebug for T
+where
+    T: Debug,
+{
+    #[inline(always)]
+    fn maybe_debug(&self) -> Option<String> {
+        Some(format!("{:?}", self))
+    }
+}
+
+/// A record of one value that a traced rewrite changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The name of the value's type, from `std::any::type_name`.
+    pub type_name: &'static str,
+    /// The path from the root to the value.
+    pub path: Path,
+    /// The value before it was rewritten, if recording renderings and its type
+    /// implements `Debug`.
+    pub before: Option<String>,
+    /// The value after it was rewritten, if recording renderings and its type
+    /// implements `Debug`.
+    pub after: Option<String>,
+}
+
+/// Wrap a rewrite to record each value it changes. Traverse with
+/// `PathedEverywhere` to apply it everywhere.
+///
+/// A value counts as changed when the rewrite fires on it. Rendering each
+/// changed value before and after with `Debug` is opt in, with
+/// `with_renderings`, since it is costly: the rewrite consumes the value, so
+/// whether it will fire isn't known until it is too late to render the value
+/// as it was, and every value the traversal visits is rendered beforehand.
+/// Each rendering includes everything within the value, so a traversal with
+/// renderings takes time proportional to the size of the data structure times
+/// its depth, which is quadratic for deeply nested values such as long lists.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut trace = Traced::new(Rewrite::new(|n: u32| if n > 9 { Ok(9) } else { Err(n) }))
+///     .with_renderings();
+/// let value = PathedEverywhere::new(&mut trace).transform(vec![3u32, 12]);
+/// assert_eq!(value, vec![3, 9]);
+///
+/// let entry = &trace.entries()[0];
+/// assert_eq!((entry.type_name, entry.path.to_string()), ("u32", "[1]".to_string()));
+/// assert_eq!((entry.before.as_deref(), entry.after.as_deref()), (Some("12"), Some("9")));
+/// ```
+#[derive(Debug)]
+pub struct Traced<F>
+where
+    F: GenericRewrite,
+{
+    f: F,
+    renderings: bool,
+    entries: Vec<TraceEntry>,
+}
+
+impl<F> Traced<F>
+where
+    F: GenericRewrite,
+{
+    /// Wrap the given rewrite to record the type and path of each value it
+    /// changes.
+    #[inline]
+    pub fn new(f: F) -> Traced<F> {
+        Traced {
+            f,
+            renderings: false,
+            entries: vec![],
+        }
+    }
+
+    /// Also record `Debug` renderings of each changed value, before and after.
+    ///
+    /// This renders every visited value whose type implements `Debug`, not
+    /// just the changed ones, and discards the renderings of those the rewrite
+    /// doesn't fire on.
+    #[inline]
+    pub fn with_renderings(mut self) -> Traced<F> {
+        self.renderings = true;
+        self
+    }
+
+    /// Get the record of each value changed so far, in the order they were
+    /// changed.
+    #[inline]
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// Take the record of each value changed so far, leaving it empty.
+    #[inline]
+    pub fn take_entries(&mut self) -> Vec<TraceEntry> {
+        mem::take(&mut self.entries)
+    }
+}
+
+impl<F> GenericPathedTransform for Traced<F>
+where
+    F: GenericRewrite,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, path: &Path) -> T
+    where
+        T: Term,
+    {
+        let before = if self.renderings {
+            t.maybe_debug()
+        } else {
+            None
+        };
+        match self.f.rewrite(t) {
+            Ok(t) => {
+                let after = if self.renderings {
+                    t.maybe_debug()
+                } else {
+                    None
+                };
+                self.entries.push(TraceEntry {
+                    type_name: any::type_name::<T>(),
+                    path: path.clone(),
+                    before,
+                    after,
+                });
+                t
+            }
+            Err(t) => t,
+        }
+    }
+}
+
+/// Find where a partial transformation would change `t`, without changing it.
+///
+/// This calls `f` on a reference to every value of type `U` within `t`, in top
+/// down, left to right order, discarding the replacements it returns, and
+/// returns the path to each value where it returned one. Nothing is cloned or
+/// rewritten, which makes this a cheap `--dry-run` for a `PartialTransformation`
+/// of `f`.
+///
+/// Since `f` is called on the original values, a real traversal can differ
+/// where `f` depends on values that an earlier application of it would have
+/// rewritten.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let config = vec![("timeout", 0u32), ("retries", 3u32), ("backoff", 0u32)];
+/// let default_zero = |n: &u32| if *n == 0 { Some(30) } else { None };
+/// let paths: Vec<_> = dry_run(&config, default_zero).iter().map(Path::to_string).collect();
+/// assert_eq!(paths, vec!["[0][1]", "[2][1]"]);
+/// ```
+pub fn dry_run<U, T, F>(t: &T, mut f: F) -> Vec<Path>
+where
+    T: Term,
+    F: FnMut(&U) -> Option<U>,
+{
+    let mut paths = vec![];
+    for_each_with_path(t, |path, u: &U| {
+        if f(u).is_some() {
+            paths.push(path.clone());
+        }
+    });
+    paths
+}
+
+/// Hooks for observing an `Instrumented` traversal, such as to forward what it
+/// does to a logging or tracing framework.
+///
+/// Each hook does nothing by default, so implement only the ones of interest.
+pub trait TraversalObserver {
+    /// Called when a traversal of a value of the named type starts.
+    fn traversal_start(&mut self, type_name: &'static str) {
+        let _ = type_name;
+    }
+
+    /// Called when a traversal of a value of the named type ends, with how many
+    /// values it rewrote and how long it took.
+    fn traversal_end(&mut self, type_name: &'static str, rewrites: usize, elapsed: Duration) {
+        let _ = (type_name, rewrites, elapsed);
+    }
+
+    /// Called each time the rewrite fires, with the name of the type of the
+    /// value it rewrote and the path to it.
+    fn rewrite(&mut self, type_name: &'static str, path: &Path) {
+        let _ = (type_name, path);
+    }
+}
+
+impl<O> TraversalObserver for &mut O
+where
+    O: TraversalObserver,
+{
+    #[inline]
+    fn traversal_start(&mut self, type_name: &'static str) {
+        (**self).traversal_start(type_name)
+    }
+
+    #[inline]
+    fn traversal_end(&mut self, type_name: &'static str, rewrites: usize, elapsed: Duration) {
+        (**self).traversal_end(type_name, rewrites, elapsed)
+    }
+
+    #[inline]
+    fn rewrite(&mut self, type_name: &'static str, path: &Path) {
+        (**self).rewrite(type_name, path)
+    }
+}
+
+/// Recursively rewrite a data structure in a bottom up manner, like
+/// `Everywhere`, telling an observer when the traversal starts and ends and
+/// each time the rewrite fires.
+///
+/// This is how to get observability into a production traversal without
+/// instrumenting every closure by hand: implement `TraversalObserver` to
+/// forward to whatever logging or tracing framework is in use.
+///
+/// ```
+/// use scrapmetal::*;
+/// use std::collections::BTreeMap;
+///
+/// #[derive(Default)]
+/// struct Counts(BTreeMap<&'static str, usize>);
+///
+/// impl TraversalObserver for Counts {
+///     fn rewrite(&mut self, type_name: &'static str, _: &Path) {
+///         *self.0.entry(type_name).or_insert(0) += 1;
+///     }
+/// }
+///
+/// let clamp = Rewrite::new(|n: u32| if n > 9 { Ok(9) } else { Err(n) });
+/// let mut counts = Counts::default();
+/// let value = Instrumented::new(clamp, &mut counts).transform(vec![3u32, 12, 40]);
+/// assert_eq!(value, vec![3, 9, 9]);
+/// assert_eq!(counts.0["u32"], 2);
+/// ```
+#[derive(Debug)]
+pub struct Instrumented<F, O>
+where
+    F: GenericRewrite,
+    O: TraversalObserver,
+{
+    f: F,
+    observer: O,
+}
+
+impl<F, O> Instrumented<F, O>
+where
+    F: GenericRewrite,
+    O: TraversalObserver,
+{
+    /// Construct a new instrumented rewriting traversal.
+    #[inline]
+    pub fn new(f: F, observer: O) -> Instrumented<F, O> {
+        Instrumented { f, observer }
+    }
+
+    /// Get the observer.
+    #[inline]
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Rewrite `t` everywhere, telling the observer about it.
+    pub fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let type_name = any::type_name::<T>();
+        self.observer.traversal_start(type_name);
+        let start = Instant::now();
+        let mut observe = Observe {
+            f: &mut self.f,
+            observer: &mut self.observer,
+            rewrites: 0,
+        };
+        let t = PathedEverywhere::new(&mut observe).transform(t);
+        let rewrites = observe.rewrites;
+        self.observer.traversal_end(type_name, rewrites, start.elapsed());
+        t
+    }
+}
+
+/// Applies a rewrite, telling an observer each time it fires.
+struct Observe<'a, F: 'a, O: 'a> {
+    f: &'a mut F,
+    observer: &'a mut O,
+    rewrites: usize,
+}
+
+impl<'a, F, O> GenericPathedTransform for Observe<'a, F, O>
+where
+    F: GenericRewrite,
+    O: TraversalObserver,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T, path: &Path) -> T
+    where
+        T: Term,
+    {
+        match self.f.rewrite(t) {
+            Ok(t) => {
+                self.rewrites += 1;
+                self.observer.rewrite(any::type_name::<T>(), path);
+                t
+            }
+            Err(t) => t,
+        }
+    }
+}
+
+/// A `TraversalObserver` that reports what an `Instrumented` traversal does to
+/// the `tracing` framework.
+///
+/// Each traversal is a debug level `traversal` span, with the name of the type
+/// of the value traversed, and starts and ends with a debug level event. Before
+/// a traversal ends, it also emits a debug level event for each type of value
+/// it rewrote, with how many values of that type it rewrote. Each rewrite is a
+/// trace level event with the name of the type of the value rewritten and the
+/// path to it.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let clamp = Rewrite::new(|n: u32| if n > 9 { Ok(9) } else { Err(n) });
+/// let value = Instrumented::new(clamp, TracingObserver::new()).transform(vec![3u32, 12]);
+/// assert_eq!(value, vec![3, 9]);
+/// ```
+#[cfg(feature = "tracing")]
+#[derive(Debug, Default)]
+pub struct TracingObserver {
+    traversals: Vec<(tracing::span::EnteredSpan, BTreeMap<&'static str, usize>)>,
+}
+
+#[cfg(feature = "tracing")]
+impl TracingObserver {
+    /// Construct a new tracing observer.
+    #[inline]
+    pub fn new() -> TracingObserver {
+        TracingObserver::default()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl TraversalObserver for TracingObserver {
+    fn traversal_start(&mut self, type_name: &'static str) {
+        let span = tracing::debug_span!("traversal", type_name).entered();
+        tracing::debug!("traversal start");
+        self.traversals.push((span, BTreeMap::new()));
+    }
+
+    fn traversal_end(&mut self, type_name: &'static str, rewrites: usize, elapsed: Duration) {
+        if let Some((_span, counts)) = self.traversals.pop() {
+            for (type_name, count) in counts {
+                tracing::debug!(type_name, count, "rewrites of type");
+            }
+            tracing::debug!(type_name, rewrites, ?elapsed, "traversal end");
+        }
+    }
+
+    fn rewrite(&mut self, type_name: &'static str, path: &Path) {
+        if let Some(&mut (_, ref mut counts)) = self.traversals.last_mut() {
+            *counts.entry(type_name).or_insert(0) += 1;
+        }
+        tracing::trace!(type_name, %path, "rewrite");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Rewrite;
+
+    struct Opaque(u32);
+    impl_term!(Opaque { 0 });
+
+    #[test]
+    fn traced() {
+        let mut trace = Traced::new(Rewrite::new(|n: u32| if n > 9 { Ok(9) } else { Err(n) }));
+        let value = PathedEverywhere::new(&mut trace).transform((3u32, vec![10u32, 11]));
+        assert_eq!(value, (3, vec![9, 9]));
+        let paths: Vec<_> = trace.entries().iter().map(|e| e.path.to_string()).collect();
+        assert_eq!(paths, vec!["[1][0]", "[1][1]"]);
+        assert!(trace.entries().iter().all(|e| e.before.is_none()));
+        assert_eq!(trace.take_entries().len(), 2);
+        assert!(trace.entries().is_empty());
+
+        let mut trace = Traced::new(Rewrite::new(|o: Opaque| Ok(Opaque(o.0 + 1)))).with_renderings();
+        let value = PathedEverywhere::new(&mut trace).transform(Opaque(1));
+        assert_eq!(value.0, 2);
+        assert_eq!(trace.entries()[0].before, None);
+        assert!(trace.entries()[0].type_name.ends_with("Opaque"));
+    }
+
+    #[test]
+    fn dry_run() {
+        let value = (3u32, vec![10u32, 11]);
+        let mut called = 0;
+        let paths = super::dry_run(&value, |n: &u32| {
+            called += 1;
+            if *n > 9 {
+                Some(9)
+            } else {
+                None
+            }
+        });
+        let paths: Vec<_> = paths.iter().map(|p| p.to_string()).collect();
+        assert_eq!(paths, vec!["[1][0]", "[1][1]"]);
+        assert_eq!(called, 3);
+        assert_eq!(value, (3, vec![10, 11]));
+    }
+
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl TraversalObserver for Log {
+        fn traversal_start(&mut self, type_name: &'static str) {
+            self.0.push(format!("start {}", type_name));
+        }
+
+        fn traversal_end(&mut self, type_name: &'static str, rewrites: usize, _: Duration) {
+            self.0.push(format!("end {} after {}", type_name, rewrites));
+        }
+
+        fn rewrite(&mut self, type_name: &'static str, path: &Path) {
+            self.0.push(format!("rewrote {} at {}", type_name, path));
+        }
+    }
+
+    #[test]
+    fn instrumented() {
+        let clamp = Rewrite::new(|n: u32| if n > 9 { Ok(9) } else { Err(n) });
+        let mut instrumented = Instrumented::new(clamp, Log::default());
+        assert_eq!(instrumented.transform((3u32, vec![10u32])), (3, vec![9]));
+        assert_eq!(instrumented.transform('a'), 'a');
+        assert_eq!(
+            instrumented.observer().0,
+            vec![
+                "start (u32, alloc::vec::Vec<u32>)",
+                "rewrote u32 at [1][0]",
+                "end (u32, alloc::vec::Vec<u32>) after 1",
+                "start char",
+                "end char after 0",
+            ]
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_observer() {
+        use std::fmt;
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Renders the fields of a span or event, except for how long things
+        /// took.
+        struct Fields(String);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                if field.name() != "elapsed" {
+                    self.0 += &format!(" {}={:?}", field.name(), value);
+                }
+            }
+        }
+
+        /// Records each span and event.
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes) -> Id {
+                let metadata = span.metadata();
+                let mut fields = Fields(format!("{} span {}", metadata.level(), metadata.name()));
+                span.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _: &Id, _: &Record) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event) {
+                let mut fields = Fields(event.metadata().level().to_string());
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let clamp = Rewrite::new(|n: u32| if n > 9 { Ok(9) } else { Err(n) });
+            let mut instrumented = Instrumented::new(clamp, TracingObserver::new());
+            assert_eq!(instrumented.transform((3u32, vec![10u32, 11])), (3, vec![9, 9]));
+        });
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                "DEBUG span traversal type_name=\"(u32, alloc::vec::Vec<u32>)\"",
+                "DEBUG message=traversal start",
+                "TRACE message=rewrite type_name=\"u32\" path=[1][0]",
+                "TRACE message=rewrite type_name=\"u32\" path=[1][1]",
+                "DEBUG message=rewrites of type type_name=\"u32\" count=2",
+                "DEBUG message=traversal end type_name=\"(u32, alloc::vec::Vec<u32>)\" rewrites=2",
+            ]
+        );
+    }
+}
@@ -1,5 +1,6 @@
-use super::{Cast, GenericQuery, Term};
+use super::{CastThrough, GenericQuery, GenericRewrite, RewritePass, SameType, Seq, Term};
 use std::marker::PhantomData;
+use std::ops::ControlFlow;
 
 /// Work around Rust's lack of higher-rank type polymorphism with a trait that
 /// has a generic `fn transform<T>` method. Essentially, we'd really prefer
@@ -12,6 +13,19 @@ pub trait GenericTransform {
         T: Term;
 }
 
+impl<F> GenericTransform for &mut F
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        (**self).transform(t)
+    }
+}
+
 /// A transformation takes some value `U` and returns a new, transformed version
 /// of it. It can be called on values of *any* type `T`, not just on values of
 /// type `U`, in which case it is simply the identity function.
@@ -46,15 +60,260 @@ where
 {
     #[inline]
     fn transform<T>(&mut self, t: T) -> T {
-        match Cast::<U>::cast(t) {
-            Ok(u) => match Cast::<T>::cast((self.f)(u)) {
-                Ok(t) => t,
-                Err(_) => unreachable!(
-                    "If T=U, then U=T. Cast isn't pub, so there aren't any \
-                     future specializations that could wreck this for us."
-                ),
-            },
-            Err(t) => t,
+        let f = &mut self.f;
+        t.map_as(|u| (f(u), ())).0
+    }
+}
+
+/// A transformation fires on every value of type `U`, and no others.
+impl<F, U> GenericRewrite for Transformation<F, U>
+where
+    F: FnMut(U) -> U,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        let f = &mut self.f;
+        match t.map_as(|u| (f(u), ())) {
+            (t, Some(())) => Ok(t),
+            (t, None) => Err(t),
+        }
+    }
+}
+
+/// A stateful transformation takes some value `U`, along with a mutable state
+/// `S` that it threads through every value it is called on, and returns a new,
+/// transformed version of it. It can be called on values of *any* type `T`, not
+/// just on values of type `U`, in which case it is simply the identity function
+/// and leaves the state alone.
+///
+/// The state is passed to the function explicitly, rather than captured, so it
+/// can be inspected or taken back once a traversal is done with it.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let mut gensym = StatefulTransformation::new(0u32, |next: &mut u32, _: &'static str| {
+///     *next += 1;
+///     if *next == 1 { "a" } else { "b" }
+/// });
+/// let names = Everywhere::new(&mut gensym).transform(vec!["x", "y"]);
+/// assert_eq!(names, vec!["a", "b"]);
+/// assert_eq!(gensym.into_state(), 2);
+/// ```
+#[derive(Debug)]
+pub struct StatefulTransformation<F, U, S>
+where
+    F: FnMut(&mut S, U) -> U,
+{
+    f: F,
+    state: S,
+    phantom: PhantomData<fn(U) -> U>,
+}
+
+impl<F, U, S> StatefulTransformation<F, U, S>
+where
+    F: FnMut(&mut S, U) -> U,
+{
+    /// Construct a new `StatefulTransformation` from the given initial state
+    /// and function.
+    #[inline]
+    pub fn new(state: S, f: F) -> StatefulTransformation<F, U, S> {
+        StatefulTransformation {
+            f,
+            state,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get a reference to the current state.
+    #[inline]
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Get a mutable reference to the current state.
+    #[inline]
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Take the final state.
+    #[inline]
+    pub fn into_state(self) -> S {
+        self.state
+    }
+}
+
+impl<F, U, S> GenericTransform for StatefulTransformation<F, U, S>
+where
+    F: FnMut(&mut S, U) -> U,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T {
+        let f = &mut self.f;
+        let state = &mut self.state;
+        t.map_as(|u| (f(state, u), ())).0
+    }
+}
+
+/// A transformation that can ask for the traversal it is part of to stop. This
+/// is roughly equivalent to `for<T> FnMut(T) -> ControlFlow<T, T>`.
+///
+/// Returning `ControlFlow::Continue` carries on with the traversal, and
+/// returning `ControlFlow::Break` stops it. Either way, the value returned is
+/// the transformed value.
+pub trait GenericControlTransform {
+    /// Call the transform function on any `T`.
+    fn control<T>(&mut self, t: T) -> ControlFlow<T, T>
+    where
+        T: Term;
+}
+
+/// A control transformation takes some value `U` and returns a new, transformed
+/// version of it, and whether to stop the traversal. It can be called on values
+/// of *any* type `T`, not just on values of type `U`, in which case it is
+/// simply the identity function and continues the traversal.
+///
+/// This essentially lifts a `FnMut(U) -> ControlFlow<U, U>` into a `for<T>
+/// FnMut(T) -> ControlFlow<T, T>`.
+#[derive(Debug)]
+pub struct ControlTransformation<F, U>
+where
+    F: FnMut(U) -> ControlFlow<U, U>,
+{
+    f: F,
+    phantom: PhantomData<fn(U) -> U>,
+}
+
+impl<F, U> ControlTransformation<F, U>
+where
+    F: FnMut(U) -> ControlFlow<U, U>,
+{
+    /// Construct a new `ControlTransformation` from the given function.
+    #[inline]
+    pub fn new(f: F) -> ControlTransformation<F, U> {
+        ControlTransformation {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, U> GenericControlTransform for ControlTransformation<F, U>
+where
+    F: FnMut(U) -> ControlFlow<U, U>,
+{
+    #[inline]
+    fn control<T>(&mut self, t: T) -> ControlFlow<T, T> {
+        let f = &mut self.f;
+        match t.map_as(|u| match f(u) {
+            ControlFlow::Continue(u) => (u, false),
+            ControlFlow::Break(u) => (u, true),
+        }) {
+            (t, Some(true)) => ControlFlow::Break(t),
+            (t, _) => ControlFlow::Continue(t),
+        }
+    }
+}
+
+/// Recursively perform a control transformation in a bottom up manner across a
+/// data structure, until it asks to stop.
+///
+/// Once it has stopped, no more values are transformed or descended into, and
+/// the partially transformed data structure is returned. As a
+/// `GenericControlTransform`, this breaks if the traversal was stopped.
+#[derive(Debug)]
+pub struct EverywhereControl<F>
+where
+    F: GenericControlTransform,
+{
+    f: F,
+    stopped: bool,
+}
+
+impl<F> EverywhereControl<F>
+where
+    F: GenericControlTransform,
+{
+    /// Construct a new stoppable transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> EverywhereControl<F> {
+        EverywhereControl { f, stopped: false }
+    }
+
+    #[inline]
+    fn walk<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        if self.stopped {
+            return t;
+        }
+        let t = t.map_one_transform(&mut Walk(self));
+        if self.stopped {
+            return t;
+        }
+        match self.f.control(t) {
+            ControlFlow::Continue(t) => t,
+            ControlFlow::Break(t) => {
+                self.stopped = true;
+                t
+            }
+        }
+    }
+}
+
+/// Continues an `EverywhereControl` traversal into a value's children.
+struct Walk<'a, F>(&'a mut EverywhereControl<F>)
+where
+    F: 'a + GenericControlTransform;
+
+impl<'a, F> GenericTransform for Walk<'a, F>
+where
+    F: GenericControlTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        self.0.walk(t)
+    }
+}
+
+impl<F> GenericControlTransform for EverywhereControl<F>
+where
+    F: GenericControlTransform,
+{
+    #[inline]
+    fn control<T>(&mut self, t: T) -> ControlFlow<T, T>
+    where
+        T: Term,
+    {
+        self.stopped = false;
+        let t = self.walk(t);
+        if self.stopped {
+            ControlFlow::Break(t)
+        } else {
+            ControlFlow::Continue(t)
+        }
+    }
+}
+
+impl<F> GenericTransform for EverywhereControl<F>
+where
+    F: GenericControlTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        match self.control(t) {
+            ControlFlow::Continue(t) | ControlFlow::Break(t) => t,
         }
     }
 }
@@ -67,6 +326,7 @@ where
     F: GenericTransform,
 {
     f: F,
+    rev: bool,
 }
 
 impl<F> Everywhere<F>
@@ -76,11 +336,283 @@ where
     /// Construct a new transformation traversal.
     #[inline]
     pub fn new(f: F) -> Everywhere<F> {
-        Everywhere { f }
+        Everywhere { f, rev: false }
+    }
+
+    /// Construct a new transformation traversal that visits each value's
+    /// children from last to first, using `Term::map_one_transform_rev`.
+    #[inline]
+    pub fn new_rev(f: F) -> Everywhere<F> {
+        Everywhere { f, rev: true }
+    }
+}
+
+impl<F> Everywhere<F>
+where
+    F: GenericTransform + GenericRewrite,
+{
+    /// Perform the traversal with `F`'s `GenericRewrite` implementation, and
+    /// also return whether the rewrite fired anywhere, i.e. whether anything
+    /// changed.
+    #[inline]
+    pub fn transform_with_flag<T>(&mut self, t: T) -> (T, bool)
+    where
+        T: Term,
+    {
+        let mut pass = RewritePass {
+            f: &mut self.f,
+            rev: self.rev,
+            fired: false,
+        };
+        let t = pass.transform(t);
+        (t, pass.fired)
+    }
+}
+
+impl<F> GenericRewrite for Everywhere<F>
+where
+    F: GenericTransform + GenericRewrite,
+{
+    #[inline]
+    fn rewrite<T>(&mut self, t: T) -> Result<T, T>
+    where
+        T: Term,
+    {
+        match self.transform_with_flag(t) {
+            (t, true) => Ok(t),
+            (t, false) => Err(t),
+        }
     }
 }
 
 impl<F> GenericTransform for Everywhere<F>
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let t = if self.rev {
+            t.map_one_transform_rev(self)
+        } else {
+            t.map_one_transform(self)
+        };
+        self.f.transform(t)
+    }
+}
+
+/// Recursively perform a transformation in a top down manner across a complete
+/// data structure.
+///
+/// The transformation is applied to each value before its children, and the
+/// children of its result are traversed afterwards. This is the `everywhere'`
+/// combinator from the paper.
+#[derive(Debug)]
+pub struct EverywhereTopDown<F>
+where
+    F: GenericTransform,
+{
+    f: F,
+}
+
+impl<F> EverywhereTopDown<F>
+where
+    F: GenericTransform,
+{
+    /// Construct a new top down transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> EverywhereTopDown<F> {
+        EverywhereTopDown { f }
+    }
+}
+
+impl<F> GenericTransform for EverywhereTopDown<F>
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let t = self.f.transform(t);
+        t.map_one_transform(self)
+    }
+}
+
+/// Recursively perform a transformation across a complete data structure in
+/// breadth first order, level by level.
+///
+/// A value's children are one level below it, so a `Vec<Vec<u32>>` has its
+/// `u32`s two levels down. The transformation is applied to every value on one
+/// level, from first to last, before any value on the next level, and the next
+/// level is made up of the children of the transformed values.
+///
+/// Each level is reached by walking down from the root again, so this takes
+/// time proportional to the size of the data structure times its depth.
+#[derive(Debug)]
+pub struct BreadthFirst<F>
+where
+    F: GenericTransform,
+{
+    f: F,
+}
+
+impl<F> BreadthFirst<F>
+where
+    F: GenericTransform,
+{
+    /// Construct a new breadth first transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> BreadthFirst<F> {
+        BreadthFirst { f }
+    }
+}
+
+impl<F> GenericTransform for BreadthFirst<F>
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, mut t: T) -> T
+    where
+        T: Term,
+    {
+        let mut level = Level {
+            f: &mut self.f,
+            target: 0,
+            depth: 0,
+            deeper: true,
+        };
+        while level.deeper {
+            level.deeper = false;
+            t = level.transform(t);
+            level.target += 1;
+        }
+        t
+    }
+}
+
+/// Applies a transformation to only the values `target` levels down, and
+/// records whether there are any values below them.
+struct Level<'a, F: 'a> {
+    f: &'a mut F,
+    target: usize,
+    depth: usize,
+    deeper: bool,
+}
+
+impl<'a, F> GenericTransform for Level<'a, F>
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        if self.depth > self.target {
+            self.deeper = true;
+            return t;
+        }
+        let t = if self.depth == self.target {
+            self.f.transform(t)
+        } else {
+            t
+        };
+        self.depth += 1;
+        let t = t.map_one_transform(self);
+        self.depth -= 1;
+        t
+    }
+}
+
+/// Recursively traverse a complete data structure, applying one transformation
+/// to each value on the way down, before its children are traversed, and
+/// another on the way back up, after they have been.
+///
+/// This is handy for keeping track of scopes: push a scope when entering a
+/// block, and pop it when exiting. Since the two transformations are separate
+/// values, any state they share needs to live in a `Cell` or `RefCell`.
+#[derive(Debug)]
+pub struct EnterExit<E, X>
+where
+    E: GenericTransform,
+    X: GenericTransform,
+{
+    enter: E,
+    exit: X,
+}
+
+impl<E, X> EnterExit<E, X>
+where
+    E: GenericTransform,
+    X: GenericTransform,
+{
+    /// Construct a new traversal that applies `enter` on the way down and
+    /// `exit` on the way up.
+    #[inline]
+    pub fn new(enter: E, exit: X) -> EnterExit<E, X> {
+        EnterExit { enter, exit }
+    }
+}
+
+impl<E, X> GenericTransform for EnterExit<E, X>
+where
+    E: GenericTransform,
+    X: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        let t = self.enter.transform(t);
+        let t = t.map_one_transform(self);
+        self.exit.transform(t)
+    }
+}
+
+/// Recursively perform several transformations in a single bottom up walk
+/// across a complete data structure, rather than one walk for each of them.
+///
+/// At each value, the transformations are applied in the order they were added
+/// to the pipeline, each to the result of the one before, after the value's
+/// children have had all of them applied. This gives the same result as running
+/// an `Everywhere` traversal for each transformation in turn, as long as none
+/// of the transformations depends on how a later one changes a value's
+/// children.
+#[derive(Debug)]
+pub struct Pipeline<F>
+where
+    F: GenericTransform,
+{
+    f: F,
+}
+
+impl<F> Pipeline<F>
+where
+    F: GenericTransform,
+{
+    /// Construct a new pipeline with a single transformation.
+    #[inline]
+    pub fn new(f: F) -> Pipeline<F> {
+        Pipeline { f }
+    }
+
+    /// Add a transformation to the end of this pipeline.
+    #[inline]
+    pub fn then<G>(self, g: G) -> Pipeline<Seq<F, G>>
+    where
+        G: GenericTransform,
+    {
+        Pipeline { f: Seq(self.f, g) }
+    }
+}
+
+impl<F> GenericTransform for Pipeline<F>
 where
     F: GenericTransform,
 {
@@ -138,14 +670,429 @@ where
     }
 }
 
+/// Apply a transformation to a value a fixed number of times.
+#[derive(Debug)]
+pub struct Repeat<F>
+where
+    F: GenericTransform,
+{
+    times: usize,
+    f: F,
+}
+
+impl<F> Repeat<F>
+where
+    F: GenericTransform,
+{
+    /// Construct a new transformation that applies `f` the given number of
+    /// times.
+    #[inline]
+    pub fn new(times: usize, f: F) -> Repeat<F> {
+        Repeat { times, f }
+    }
+}
+
+impl<F> GenericTransform for Repeat<F>
+where
+    F: GenericTransform,
+{
+    #[inline]
+    fn transform<T>(&mut self, mut t: T) -> T
+    where
+        T: Term,
+    {
+        for _ in 0..self.times {
+            t = self.f.transform(t);
+        }
+        t
+    }
+}
+
+/// Apply a transformation to a value again and again, until the given query
+/// evaluates to true for the result.
+///
+/// The transformation is always applied at least once. If the query never
+/// evaluates to true, this will loop forever.
+#[derive(Debug)]
+pub struct RepeatUntil<F, P>
+where
+    F: GenericTransform,
+    P: GenericQuery<bool>,
+{
+    p: P,
+    f: F,
+}
+
+impl<F, P> RepeatUntil<F, P>
+where
+    F: GenericTransform,
+    P: GenericQuery<bool>,
+{
+    /// Construct a new transformation that applies `f` until `p` is true.
+    #[inline]
+    pub fn new(p: P, f: F) -> RepeatUntil<F, P> {
+        RepeatUntil { p, f }
+    }
+}
+
+impl<F, P> GenericTransform for RepeatUntil<F, P>
+where
+    F: GenericTransform,
+    P: GenericQuery<bool>,
+{
+    #[inline]
+    fn transform<T>(&mut self, mut t: T) -> T
+    where
+        T: Term,
+    {
+        loop {
+            t = self.f.transform(t);
+            if self.p.query(&t) {
+                return t;
+            }
+        }
+    }
+}
+
+/// A set of types, written as a tuple of them, like `(Span, SourceMap)`.
+pub trait TypeSet {
+    /// Is `T` one of the types in this set?
+    fn contains<T>() -> bool;
+}
+
+impl TypeSet for () {
+    #[inline(always)]
+    fn contains<T>() -> bool {
+        false
+    }
+}
+
+macro_rules! impl_type_set {
+    ( $( $name:ident ),* ) => {
+        impl< $( $name ),* > TypeSet for ( $( $name, )* ) {
+            #[inline(always)]
+            fn contains<T>() -> bool {
+                false $( || <T as SameType<$name>>::same_type() )*
+            }
+        }
+    }
+}
+
+impl_type_set!(A);
+impl_type_set!(A, B);
+impl_type_set!(A, B, C);
+impl_type_set!(A, B, C, D);
+impl_type_set!(A, B, C, D, E);
+impl_type_set!(A, B, C, D, E, F);
+impl_type_set!(A, B, C, D, E, F, G);
+impl_type_set!(A, B, C, D, E, F, G, H);
+
+/// Recursively perform a transformation in a bottom up manner across a data
+/// structure, skipping over values whose type is in the set `S`, and
+/// everything inside them.
+///
+/// This prunes the traversal by type alone, without calling a query on every
+/// value like `EverywhereBut` does. Name the set of types to skip when
+/// constructing the traversal, like
+/// `EverywhereButTypes::<_, (Span, SourceMap)>::new(f)`.
+#[derive(Debug)]
+pub struct EverywhereButTypes<F, S>
+where
+    F: GenericTransform,
+    S: TypeSet,
+{
+    f: F,
+    phantom: PhantomData<fn() -> S>,
+}
+
+impl<F, S> EverywhereButTypes<F, S>
+where
+    F: GenericTransform,
+    S: TypeSet,
+{
+    /// Construct a new type pruned transformation traversal.
+    #[inline]
+    pub fn new(f: F) -> EverywhereButTypes<F, S> {
+        EverywhereButTypes {
+            f,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, S> GenericTransform for EverywhereButTypes<F, S>
+where
+    F: GenericTransform,
+    S: TypeSet,
+{
+    #[inline]
+    fn transform<T>(&mut self, t: T) -> T
+    where
+        T: Term,
+    {
+        if S::contains::<T>() {
+            return t;
+        }
+        let t = t.map_one_transform(self);
+        self.f.transform(t)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::{CowView, Everything, Query, Rewrite};
+    use std::cell::Cell;
+
+    /// Records the type of every value it is called on.
+    struct Record<'a>(&'a mut Vec<&'static str>);
+
+    impl<'a> GenericTransform for Record<'a> {
+        fn transform<T>(&mut self, t: T) -> T
+        where
+            T: Term,
+        {
+            self.0.push(::std::any::type_name::<T>());
+            t
+        }
+    }
+
+    #[test]
+    fn top_down() {
+        let mut bottom_up = vec![];
+        Everywhere::new(Record(&mut bottom_up)).transform(vec![(1u8, 2u16)]);
+        let outer = ::std::any::type_name::<Vec<(u8, u16)>>();
+        assert_eq!(bottom_up, vec!["u8", "u16", "(u8, u16)", outer]);
+
+        let mut top_down = vec![];
+        EverywhereTopDown::new(Record(&mut top_down)).transform(vec![(1u8, 2u16)]);
+        assert_eq!(top_down, vec![outer, "(u8, u16)", "u8", "u16"]);
+    }
+
+    #[test]
+    fn transform_with_flag() {
+        let mut clamp = Everywhere::new(Rewrite::new(|n: u32| if n > 9 { Ok(9) } else { Err(n) }));
+        assert_eq!(clamp.transform_with_flag(vec![1u32, 20]), (vec![1, 9], true));
+        assert_eq!(clamp.transform_with_flag(vec![1u32, 9]), (vec![1, 9], false));
+    }
+
+    #[test]
+    fn right_to_left() {
+        let mut seen = vec![];
+        let value = (vec![1u32, 2], 3u32);
+        let value = Everywhere::new_rev(Transformation::new(|n: u32| {
+            seen.push(n);
+            n * 10
+        })).transform(value);
+        assert_eq!(value, (vec![10, 20], 30));
+        assert_eq!(seen, vec![3, 2, 1]);
+    }
+
+    /// Replaces each `u32` with its depth, counting every value it enters.
+    struct Enter<'a>(&'a Cell<u32>);
+
+    impl<'a> GenericTransform for Enter<'a> {
+        fn transform<T>(&mut self, t: T) -> T
+        where
+            T: Term,
+        {
+            let depth = self.0;
+            depth.set(depth.get() + 1);
+            Transformation::new(|_: u32| depth.get()).transform(t)
+        }
+    }
+
+    /// Counts every value it exits.
+    struct Exit<'a>(&'a Cell<u32>);
+
+    impl<'a> GenericTransform for Exit<'a> {
+        fn transform<T>(&mut self, t: T) -> T
+        where
+            T: Term,
+        {
+            self.0.set(self.0.get() - 1);
+            t
+        }
+    }
+
+    #[test]
+    fn enter_exit() {
+        let depth = Cell::new(0);
+        let mut depths = EnterExit::new(Enter(&depth), Exit(&depth));
+        assert_eq!(depths.transform((0u32, (0u32, vec![0u32]))), (2, (3, vec![4])));
+        assert_eq!(depth.get(), 0);
+    }
+
+    #[test]
+    fn everywhere_control() {
+        let mut seen = vec![];
+        let mut fix_first = EverywhereControl::new(ControlTransformation::new(|n: u32| {
+            seen.push(n);
+            if n > 9 {
+                ControlFlow::Break(9)
+            } else {
+                ControlFlow::Continue(n)
+            }
+        }));
+        let value = vec![vec![1u32, 20], vec![30, 4]];
+        assert_eq!(
+            fix_first.control(value),
+            ControlFlow::Break(vec![vec![1, 9], vec![30, 4]])
+        );
+        assert_eq!(fix_first.control(vec![1u32]), ControlFlow::Continue(vec![1]));
+        assert_eq!(seen, vec![1, 20, 1]);
+    }
+
+    #[test]
+    fn everywhere_but_types() {
+        let value = (1u32, vec![2u32], (3u32, 4u8));
+        let mut incr = EverywhereButTypes::<_, (Vec<u32>, u8)>::new(Transformation::new(|n: u32| n + 1));
+        assert_eq!(incr.transform(value), (2, vec![2], (4, 4)));
+
+        let mut incr = EverywhereButTypes::<_, ()>::new(Transformation::new(|n: u32| n + 1));
+        assert_eq!(incr.transform(vec![1u32]), vec![2]);
+    }
+
+    #[test]
+    fn pipeline() {
+        let mut pipeline = Pipeline::new(Transformation::new(|n: u32| n + 1))
+            .then(Transformation::new(|mut v: Vec<u32>| {
+                v.reverse();
+                v
+            }))
+            .then(Transformation::new(|n: u32| n * 10));
+        assert_eq!(pipeline.transform((1u32, vec![1u32, 2, 3])), (20, vec![40, 30, 20]));
+    }
+
+    #[test]
+    fn breadth_first() {
+        let mut seen = vec![];
+        let value = (1u32, (2u32, vec![3u32]), 4u32);
+        let value = BreadthFirst::new(Transformation::new(|n: u32| {
+            seen.push(n);
+            n * 10
+        })).transform(value);
+        assert_eq!(value, (10, (20, vec![30]), 40));
+        assert_eq!(seen, vec![1, 4, 2, 3]);
+    }
+
+    #[test]
+    fn repeat() {
+        let incr = || Everywhere::new(Transformation::new(|n: u32| n + 1));
+        assert_eq!(Repeat::new(3, incr()).transform(vec![1u32, 2]), vec![4, 5]);
+        assert_eq!(Repeat::new(0, incr()).transform(vec![1u32, 2]), vec![1, 2]);
+
+        let big = Query::new(|v: &Vec<u32>| v.iter().sum::<u32>() > 10);
+        let mut until = RepeatUntil::new(big, incr());
+        assert_eq!(until.transform(vec![1u32, 2]), vec![5, 6]);
+        assert_eq!(until.transform(vec![10u32, 20]), vec![11, 21]);
+    }
+
+    #[test]
+    fn stateful_transformation() {
+        use std::collections::BTreeMap;
+
+        let rename = |names: &mut BTreeMap<_, _>, name: &'static str| {
+            let next = ["a", "b", "c"][names.len()];
+            *names.entry(name).or_insert(next)
+        };
+        let mut rename = StatefulTransformation::new(BTreeMap::new(), rename);
+        let renamed = Everywhere::new(&mut rename).transform(vec![("x", "y"), ("y", "z")]);
+        assert_eq!(renamed, vec![("a", "b"), ("b", "c")]);
+        assert_eq!(rename.state().len(), 3);
+        assert_eq!(rename.transform(5u32), 5);
+        rename.state_mut().clear();
+        assert_eq!(rename.transform("w"), "a");
+    }
 
     #[test]
     fn transformation() {
         let mut not = Transformation::new(|b: bool| !b);
-        assert_eq!(not.transform(true), false);
+        assert!(!not.transform(true));
         assert_eq!(not.transform("string"), "string");
+
+        assert_eq!(not.rewrite(false), Ok(true));
+        assert_eq!(not.rewrite('a'), Err('a'));
+
+        let mut first = Transformation::new(|n: u32| n + 1).or_else(Transformation::new(|n: u32| n * 10));
+        assert_eq!(first.transform(1u32), 2);
+    }
+
+    #[test]
+    fn everywhere_keeps_allocations() {
+        let value = vec![Box::new(1u32), Box::new(2)];
+        let (outer, inner) = (value.as_ptr(), &*value[1] as *const u32);
+
+        let value = Everywhere::new(Transformation::new(|n: u32| n * 10)).transform(value);
+        assert_eq!(value, vec![Box::new(10), Box::new(20)]);
+        assert_eq!((value.as_ptr(), &*value[1] as *const u32), (outer, inner));
+
+        let value = Everywhere::new_rev(Transformation::new(|c: char| c)).transform(value);
+        assert_eq!((value.as_ptr(), &*value[1] as *const u32), (outer, inner));
+    }
+
+    #[test]
+    fn tuples() {
+        let mut inc = Everywhere::new(Transformation::new(|n: u32| n + 1));
+        assert_eq!(inc.transform((1u32,)), (2,));
+        assert_eq!(inc.transform(((1u32, 'a'), (2u32,))), ((2, 'a'), (3,)));
+
+        let twelve = (1u32, 2u32, 3u32, 4u32, 5u32, 6u32, 7u32, 8u32, 9u32, 10u32, 11u32, 12u32);
+        assert_eq!(inc.transform(twelve).11, 13);
+    }
+
+    #[test]
+    fn arrays() {
+        let mut seen = vec![];
+        let value = {
+            let mut inc = Everywhere::new_rev(Transformation::new(|n: u32| {
+                seen.push(n);
+                n + 1
+            }));
+            inc.transform([[1u32, 2], [3, 4]])
+        };
+        assert_eq!(value, [[2, 3], [4, 5]]);
+        assert_eq!(seen, vec![4, 3, 2, 1]);
+
+        let empty: [Box<u32>; 0] = [];
+        assert_eq!(Everywhere::new(Transformation::new(|n: u32| n)).transform(empty), []);
+    }
+
+    #[test]
+    fn results_and_cows() {
+        use std::borrow::Cow;
+
+        let mut inc = Everywhere::new(Transformation::new(|n: u32| n + 1));
+        let results: Vec<Result<u32, (u32, char)>> = vec![Ok(1), Err((2, 'a'))];
+        assert_eq!(inc.transform(results), vec![Ok(2), Err((3, 'a'))]);
+
+        static BORROWED: [u32; 2] = [1, 2];
+        let cows: Vec<Cow<[u32]>> = vec![Cow::Borrowed(&BORROWED), Cow::Owned(vec![3])];
+        let cows = inc.transform(cows);
+        assert_eq!(cows, vec![Cow::Owned::<[u32]>(vec![2, 3]), Cow::Owned(vec![4])]);
+
+        // Borrowed values stay borrowed unless they change.
+        let mut shout = Everywhere::new(Transformation::new(|s: String| {
+            if s.ends_with('!') {
+                s.to_uppercase()
+            } else {
+                s
+            }
+        }));
+        let cows = shout.transform(vec![Cow::Borrowed("hi"), Cow::Borrowed("hey!")]);
+        assert!(matches!(cows[0], Cow::Borrowed("hi")));
+        assert!(matches!(cows[1], Cow::Owned(ref s) if s == "HEY!"));
+
+        // Queries see borrowed and owned values alike, without copying them.
+        let cows: Vec<Cow<[u32]>> = vec![Cow::Borrowed(&BORROWED), Cow::Owned(vec![3])];
+        let mut sum = Everything::new(Query::or_else(|| 0, |n: &u32| *n), |a, b| a + b);
+        assert_eq!(sum.query(&cows), 6);
+        let cows: Vec<Cow<u32>> = vec![Cow::Borrowed(&BORROWED[0]), Cow::Owned(3)];
+        assert_eq!(sum.query(&cows), 4);
+        let cows: Vec<Cow<str>> = vec![Cow::Borrowed("hi"), Cow::Owned("hey".to_string())];
+        let mut views = Everything::new(Query::or_else(|| 0, |_: &CowView<str>| 1), |a, b| a + b);
+        assert_eq!(views.query(&cows), 2);
+        let mut strings = Everything::new(Query::or_else(|| 0, |_: &String| 1), |a, b| a + b);
+        assert_eq!(strings.query(&cows), 0);
     }
 }
@@ -0,0 +1,54 @@
+use super::Term;
+
+/// A newtype-like wrapper that traversals see straight through.
+///
+/// A `Transparent` wrapper's direct children are its inner value's direct
+/// children, and typed transformations, queries, and mutations on the inner
+/// value's type apply to the inner value of the wrapper. For example, a
+/// `Transformation<F, Expr>` fires inside a transparent `Spanned<Expr>`
+/// without needing a transformation for `Spanned<Expr>` itself.
+///
+/// This is implemented by `#[derive(Term)]` with `#[scrap(transparent)]`. The
+/// wrapper's one traversed field is its inner value, so it can't be
+/// `#[scrap(leaf)]`, or traversed through a `#[scrap(with = "...")]` mirror:
+///
+/// ```compile_fail
+/// extern crate scrapmetal_derive;
+///
+/// use scrapmetal::*;
+/// use scrapmetal_derive::Term;
+///
+/// mod other {
+///     pub struct Span {
+///         pub lo: u32,
+///         pub hi: u32,
+///     }
+/// }
+///
+/// #[derive(Term)]
+/// #[scrap(remote = "other::Span")]
+/// struct SpanDef {
+///     lo: u32,
+///     hi: u32,
+/// }
+///
+/// #[derive(Term)]
+/// #[scrap(transparent)]
+/// struct Spanned(#[scrap(with = "SpanDef")] other::Span);
+/// ```
+pub trait Transparent: Term {
+    /// The type of the wrapped value.
+    type Inner: Term;
+
+    /// Get a reference to the wrapped value.
+    fn inner(&self) -> &Self::Inner;
+
+    /// Get a mutable reference to the wrapped value.
+    fn inner_mut(&mut self) -> &mut Self::Inner;
+
+    /// Replace the wrapped value with the result of `f`, keeping the rest of
+    /// the wrapper as it is.
+    fn map_inner<F>(self, f: F) -> Self
+    where
+        F: FnOnce(Self::Inner) -> Self::Inner;
+}
@@ -0,0 +1,274 @@
+use super::{walk_with_paths, CastThrough, Path, PathedVisitor, Term};
+use std::any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
+
+/// How serious a `Diagnostic` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Something worth knowing, but not a problem.
+    Info,
+    /// Something that is probably a problem.
+    Warning,
+    /// Something that is definitely a problem.
+    Error,
+}
+
+/// A finding from a `Validator` about one value in a data structure.
+///
+/// Checks construct diagnostics with `Diagnostic::error`, `Diagnostic::warning`,
+/// or `Diagnostic::info`, and the validator fills in the location of the value
+/// that was checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// How serious the finding is.
+    pub severity: Severity,
+    /// A description of the finding.
+    pub message: String,
+    /// The path from the root to the value it is about.
+    pub path: Path,
+    /// The name of the type of the value it is about, from
+    /// `std::any::type_name`.
+    pub type_name: &'static str,
+}
+
+impl Diagnostic {
+    /// Construct a new diagnostic with the given severity and message.
+    #[inline]
+    pub fn new<M: Into<String>>(severity: Severity, message: M) -> Diagnostic {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            path: Path::new(),
+            type_name: "",
+        }
+    }
+
+    /// Construct a new error diagnostic.
+    #[inline]
+    pub fn error<M: Into<String>>(message: M) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    /// Construct a new warning diagnostic.
+    #[inline]
+    pub fn warning<M: Into<String>>(message: M) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    /// Construct a new informational diagnostic.
+    #[inline]
+    pub fn info<M: Into<String>>(message: M) -> Diagnostic {
+        Diagnostic::new(Severity::Info, message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.path, self.message)
+    }
+}
+
+/// One typed check of a `Validator`.
+#[derive(Debug)]
+pub struct Check<F, U>
+where
+    F: FnMut(&U) -> Vec<Diagnostic>,
+{
+    f: F,
+    phantom: PhantomData<fn(&U)>,
+}
+
+/// A list of checks for a `Validator` to run on each value.
+///
+/// This is implemented for the lists that `Validator::check` builds, and isn't
+/// meant to be implemented anywhere else.
+pub trait Checks {
+    /// Run each check that applies to `t`, appending its diagnostics.
+    fn run_checks<T>(&mut self, t: &T, diagnostics: &mut Vec<Diagnostic>)
+    where
+        T: Term;
+}
+
+impl Checks for () {
+    #[inline]
+    fn run_checks<T>(&mut self, _: &T, _: &mut Vec<Diagnostic>)
+    where
+        T: Term,
+    {
+    }
+}
+
+impl<C, F, U> Checks for (C, Check<F, U>)
+where
+    C: Checks,
+    F: FnMut(&U) -> Vec<Diagnostic>,
+{
+    #[inline]
+    fn run_checks<T>(&mut self, t: &T, diagnostics: &mut Vec<Diagnostic>)
+    where
+        T: Term,
+    {
+        self.0.run_checks(t, diagnostics);
+        if let Some(u) = t.view_as() {
+            diagnostics.extend((self.1.f)(u));
+        }
+    }
+}
+
+/// Check every value in a data structure with typed checks, all in one
+/// traversal, and collect the diagnostics they find along with where they
+/// found them.
+///
+/// ```
+/// use scrapmetal::*;
+///
+/// let routes = vec![("/", 0u32), ("about", 30u32)];
+/// let mut validator = Validator::new()
+///     .check(|path: &&str| {
+///         if path.starts_with('/') {
+///             vec![]
+///         } else {
+///             vec![Diagnostic::error(format!("`{}` is not absolute", path))]
+///         }
+///     })
+///     .check(|timeout: &u32| {
+///         if *timeout == 0 {
+///             vec![Diagnostic::warning("a timeout of zero never times out")]
+///         } else {
+///             vec![]
+///         }
+///     });
+///
+/// let diagnostics: Vec<_> = validator.validate(&routes).iter().map(|d| d.to_string()).collect();
+/// assert_eq!(
+///     diagnostics,
+///     vec![
+///         "warning: [0][1]: a timeout of zero never times out",
+///         "error: [1][0]: `about` is not absolute",
+///     ]
+/// );
+/// ```
+#[derive(Debug)]
+pub struct Validator<C>
+where
+    C: Checks,
+{
+    checks: C,
+}
+
+impl Validator<()> {
+    /// Construct a new validator without any checks.
+    #[inline]
+    pub fn new() -> Validator<()> {
+        Validator { checks: () }
+    }
+}
+
+impl Default for Validator<()> {
+    #[inline]
+    fn default() -> Validator<()> {
+        Validator::new()
+    }
+}
+
+impl<C> Validator<C>
+where
+    C: Checks,
+{
+    /// Add a check for values of type `U`.
+    #[inline]
+    pub fn check<F, U>(self, f: F) -> Validator<(C, Check<F, U>)>
+    where
+        F: FnMut(&U) -> Vec<Diagnostic>,
+    {
+        Validator {
+            checks: (
+                self.checks,
+                Check {
+                    f,
+                    phantom: PhantomData,
+                },
+            ),
+        }
+    }
+
+    /// Run every check on every value within `t`, including `t` itself, and
+    /// return the diagnostics, in top down, left to right order of the values
+    /// they are about, and in the order the checks were added for each value.
+    pub fn validate<T>(&mut self, t: &T) -> Vec<Diagnostic>
+    where
+        T: Term,
+    {
+        let run = RunChecks {
+            checks: &mut self.checks,
+            diagnostics: vec![],
+        };
+        walk_with_paths(t, run).diagnostics
+    }
+}
+
+/// Runs a validator's checks on each value, filling in where their diagnostics
+/// came from.
+struct RunChecks<'a, C: 'a> {
+    checks: &'a mut C,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a, C> PathedVisitor for RunChecks<'a, C>
+where
+    C: Checks,
+{
+    #[inline]
+    fn visit<T>(&mut self, t: &T, path: &Path) -> ControlFlow<()>
+    where
+        T: Term,
+    {
+        let start = self.diagnostics.len();
+        self.checks.run_checks(t, &mut self.diagnostics);
+        for d in &mut self.diagnostics[start..] {
+            d.path = path.clone();
+            d.type_name = any::type_name::<T>();
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validator() {
+        let mut validator = Validator::new()
+            .check(|n: &u32| vec![Diagnostic::info(format!("saw {}", n))])
+            .check(|n: &u32| {
+                if *n > 9 {
+                    vec![Diagnostic::error("too big"), Diagnostic::warning("really")]
+                } else {
+                    vec![]
+                }
+            });
+
+        let diagnostics = validator.validate(&(1u32, vec![10u32]));
+        let severities: Vec<_> = diagnostics.iter().map(|d| d.severity).collect();
+        assert_eq!(
+            severities,
+            vec![Severity::Info, Severity::Info, Severity::Error, Severity::Warning]
+        );
+        assert!(diagnostics.iter().all(|d| d.type_name == "u32"));
+        assert_eq!(diagnostics[3].to_string(), "warning: [1][0]: really");
+        assert_eq!(
+            diagnostics.iter().map(|d| d.severity).max(),
+            Some(Severity::Error)
+        );
+
+        assert!(Validator::new().validate(&1u32).is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+extern crate scrapmetal;
+
+use scrapmetal::*;
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+fn sum<T: Term>(t: &T) -> u32 {
+    Everything::new(Query::or_else(|| 0, |n: &u32| *n), |a, b| a + b).query(t)
+}
+
+#[test]
+fn cells() {
+    let value = (Cell::new(1u32), RefCell::new(vec![2u32]));
+    assert_eq!(sum(&value), 3);
+
+    let mut value = Everywhere::new(Transformation::new(|n: u32| n * 10)).transform(value);
+    EverywhereMut::new(Mutation::new(|n: &mut u32| *n += 1)).mutate(&mut value);
+    assert_eq!((value.0.get(), value.1.borrow()[0]), (11, 21));
+}
+
+#[test]
+#[should_panic]
+fn mutably_borrowed_ref_cell() {
+    let cell = RefCell::new(1u32);
+    let _borrowed = cell.borrow_mut();
+    sum(&cell);
+}
+
+#[test]
+fn locks() {
+    let value = (Mutex::new(1u32), RwLock::new(vec![2u32]));
+    assert_eq!(sum(&value), 3);
+
+    let mut value = Everywhere::new(Transformation::new(|n: u32| n * 10)).transform(value);
+    EverywhereMut::new(Mutation::new(|n: &mut u32| *n += 1)).mutate(&mut value);
+    assert_eq!((*value.0.lock().unwrap(), value.1.read().unwrap()[0]), (11, 21));
+}
+
+#[test]
+fn poisoned_locks() {
+    let lock = Arc::new(Mutex::new(1u32));
+    let poisoner = lock.clone();
+    let _ = thread::spawn(move || {
+        let _guard = poisoner.lock().unwrap();
+        panic!("poison the lock");
+    }).join();
+    assert!(lock.is_poisoned());
+
+    assert_eq!(sum(&*lock), 1);
+    let lock = Arc::try_unwrap(lock).unwrap();
+    let lock = Everywhere::new(Transformation::new(|n: u32| n + 1)).transform(lock);
+    assert_eq!(lock.into_inner().unwrap(), 2);
+}
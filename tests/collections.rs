@@ -0,0 +1,87 @@
+extern crate scrapmetal;
+
+use scrapmetal::*;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
+
+type Double = Everywhere<Transformation<fn(u32) -> u32, u32>>;
+type Bump = EverywhereMut<Mutation<fn(&mut u32), u32, fn(), ()>>;
+
+fn double() -> Double {
+    Everywhere::new(Transformation::new(|n| n * 2))
+}
+
+fn bump() -> Bump {
+    EverywhereMut::new(Mutation::new(|n| *n += 1))
+}
+
+#[test]
+fn sequences() {
+    let list: LinkedList<u32> = vec![1, 2].into_iter().collect();
+    let deque: VecDeque<u32> = vec![3, 4].into_iter().collect();
+    let mut both = double().transform((list, deque));
+    bump().mutate(&mut both);
+    let (list, deque) = both;
+    assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![3, 5]);
+    assert_eq!(deque.into_iter().collect::<Vec<_>>(), vec![7, 9]);
+}
+
+#[test]
+fn sets_are_rebuilt() {
+    let hash: HashSet<u32> = vec![1, 2].into_iter().collect();
+    let btree: BTreeSet<u32> = vec![1, 2].into_iter().collect();
+    let heap: BinaryHeap<u32> = vec![1, 2].into_iter().collect();
+    let collapse = || Everywhere::new(Transformation::new(|_: u32| 0u32));
+    let (hash, btree, heap) = collapse().transform((hash, btree, heap));
+    assert_eq!(hash.into_iter().collect::<Vec<_>>(), vec![0]);
+    assert_eq!(btree.into_iter().collect::<Vec<_>>(), vec![0]);
+    assert_eq!(heap.into_vec(), vec![0, 0]);
+
+    let mut heap: BinaryHeap<u32> = vec![1, 5].into_iter().collect();
+    EverywhereMut::new(Mutation::new(|n: &mut u32| *n = 10 - *n)).mutate(&mut heap);
+    assert_eq!(heap.peek(), Some(&9));
+    assert_eq!(count_all::<u32, _>(&heap), 2);
+}
+
+#[test]
+fn maps_traverse_values() {
+    let mut hash = HashMap::new();
+    hash.insert(1u32, 10u32);
+    let mut btree = BTreeMap::new();
+    btree.insert(2u32, 20u32);
+    btree.insert(3u32, 30u32);
+
+    let (hash, mut btree) = double().transform((hash, btree));
+    assert_eq!(hash[&1], 20);
+    bump().mutate(&mut btree);
+    assert_eq!(btree.into_iter().collect::<Vec<_>>(), vec![(2, 41), (3, 61)]);
+
+    let total = Everything::new(Query::or_else(|| 0, |n: &u32| *n), |a, b| a + b).query(&hash);
+    assert_eq!(total, 20);
+}
+
+#[test]
+fn ordered_collections_traverse_in_reverse() {
+    let set: BTreeSet<u32> = vec![1, 2, 3].into_iter().collect();
+    let mut map = BTreeMap::new();
+    map.insert('a', 10u32);
+    map.insert('b', 20u32);
+    let mut value = (set, map);
+
+    let mut seen = vec![];
+    let value_rev = Everywhere::new_rev(Transformation::new(|n: u32| {
+        seen.push(n);
+        n + 1
+    }))
+    .transform(value.clone());
+    assert_eq!(seen, vec![20, 10, 3, 2, 1]);
+    assert_eq!(value_rev.0.into_iter().collect::<Vec<_>>(), vec![2, 3, 4]);
+    assert_eq!(value_rev.1.into_iter().collect::<Vec<_>>(), vec![('a', 11), ('b', 21)]);
+
+    let mut seen = vec![];
+    Everything::new_rev(Query::new(|n: &u32| seen.push(*n)), |_, _| ()).query(&value);
+    assert_eq!(seen, vec![20, 10, 3, 2, 1]);
+
+    let mut seen = vec![];
+    MutateEverything::new_rev(Mutation::new(|n: &mut u32| seen.push(*n))).mutate(&mut value);
+    assert_eq!(seen, vec![20, 10, 3, 2, 1]);
+}
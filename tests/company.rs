@@ -1,9 +1,9 @@
 extern crate scrapmetal;
 
-#[macro_use]
 extern crate scrapmetal_derive;
 
 use scrapmetal::*;
+use scrapmetal_derive::Term;
 use std::cmp;
 use std::collections::LinkedList;
 
@@ -63,6 +63,7 @@ impl Default for Company {
 
 impl cmp::Eq for Salary {}
 
+#[allow(clippy::derive_ord_xor_partial_ord)]
 impl cmp::Ord for Salary {
     fn cmp(&self, rhs: &Salary) -> cmp::Ordering {
         assert!(!self.0.is_nan());
@@ -145,7 +146,7 @@ impl IncreaseInPlace for SubUnit {
     fn increase_in_place(&mut self, k: f64) {
         match *self {
             SubUnit::Person(ref mut e) => e.increase_in_place(k),
-            SubUnit::Group(ref mut g) => g.into_iter().for_each(|e| e.increase_in_place(k)),
+            SubUnit::Group(ref mut g) => g.iter_mut().for_each(|e| e.increase_in_place(k)),
             SubUnit::Department(ref mut d) => d.increase_in_place(k),
         }
     }
@@ -193,7 +194,7 @@ impl HighestSalary for SubUnit {
     fn highest_salary(&self) -> Option<Salary> {
         match *self {
             SubUnit::Person(ref e) => e.highest_salary(),
-            SubUnit::Group(ref g) => g.into_iter().map(|e| e.highest_salary()).max().unwrap(),
+            SubUnit::Group(ref g) => g.iter().map(|e| e.highest_salary()).max().unwrap(),
             SubUnit::Department(ref d) => d.highest_salary(),
         }
     }
@@ -0,0 +1,49 @@
+//! Tests for using `#[derive(Term)]` via `scrapmetal`'s `derive` feature.
+
+#![cfg(feature = "derive")]
+
+extern crate scrapmetal;
+
+use scrapmetal::{Everything, Everywhere, GenericQuery, GenericTransform, Query, Term,
+                 Transformation};
+
+#[derive(Clone, Debug, PartialEq, Term)]
+enum Expr {
+    Lit(u32),
+    Neg(Box<Expr>),
+    Add { lhs: Box<Expr>, rhs: Box<Expr> },
+    Hole,
+}
+
+#[test]
+fn derive_transform_named_and_tuple_variants() {
+    let expr = Expr::Add {
+        lhs: Box::new(Expr::Neg(Box::new(Expr::Lit(1)))),
+        rhs: Box::new(Expr::Hole),
+    };
+
+    let mut fill = Everywhere::new(Transformation::new(|e: Expr| match e {
+        Expr::Hole => Expr::Lit(0),
+        e => e,
+    }));
+    let mut incr = Everywhere::new(Transformation::new(|n: u32| n + 1));
+
+    assert_eq!(
+        incr.transform(fill.transform(expr)),
+        Expr::Add {
+            lhs: Box::new(Expr::Neg(Box::new(Expr::Lit(2)))),
+            rhs: Box::new(Expr::Lit(1)),
+        }
+    );
+}
+
+#[test]
+fn derive_query_named_and_tuple_variants() {
+    let expr = Expr::Add {
+        lhs: Box::new(Expr::Lit(3)),
+        rhs: Box::new(Expr::Neg(Box::new(Expr::Lit(4)))),
+    };
+
+    let mut sum = Everything::new(Query::new(|n: &u32| *n), |a, b| a + b);
+    assert_eq!(sum.query(&expr), 7);
+}
@@ -0,0 +1,391 @@
+extern crate scrapmetal;
+
+extern crate scrapmetal_derive;
+
+use scrapmetal::*;
+use scrapmetal_derive::{Term, TermMeta};
+
+/// Not a `Term`, so it can only be used in `#[scrap(skip)]` fields.
+#[derive(Clone, Debug, PartialEq)]
+struct Cache(u32);
+
+#[derive(Clone, Debug, PartialEq, Term)]
+struct Node {
+    value: u32,
+    #[scrap(skip)]
+    id: u32,
+    #[scrap(skip)]
+    cache: Cache,
+    children: Vec<Node>,
+}
+
+#[derive(Clone, Debug, PartialEq, Term)]
+enum Tree {
+    Leaf(u32, #[scrap(skip)] u32),
+    Branch {
+        #[scrap(skip)]
+        depth: u32,
+        kids: Vec<Tree>,
+    },
+}
+
+#[test]
+fn skip_fields_are_not_transformed() {
+    let node = Node {
+        value: 1,
+        id: 10,
+        cache: Cache(100),
+        children: vec![Node {
+            value: 2,
+            id: 20,
+            cache: Cache(200),
+            children: vec![],
+        }],
+    };
+
+    let mut double = Everywhere::new(Transformation::new(|n: u32| n * 2));
+    assert_eq!(
+        double.transform(node),
+        Node {
+            value: 2,
+            id: 10,
+            cache: Cache(100),
+            children: vec![Node {
+                value: 4,
+                id: 20,
+                cache: Cache(200),
+                children: vec![],
+            }],
+        }
+    );
+
+    let tree = Tree::Branch {
+        depth: 7,
+        kids: vec![Tree::Leaf(1, 5)],
+    };
+    assert_eq!(
+        double.transform(tree),
+        Tree::Branch {
+            depth: 7,
+            kids: vec![Tree::Leaf(2, 5)],
+        }
+    );
+}
+
+#[test]
+fn skip_fields_are_not_queried_or_mutated() {
+    let mut tree = Tree::Branch {
+        depth: 7,
+        kids: vec![Tree::Leaf(1, 5), Tree::Leaf(2, 6)],
+    };
+
+    let mut sum = Everything::new(Query::new(|n: &u32| *n), |a, b| a + b);
+    assert_eq!(sum.query(&tree), 3);
+
+    let mut zero = MutateEverything::new(Mutation::new(|n: &mut u32| *n = 0));
+    zero.mutate(&mut tree);
+    assert_eq!(
+        tree,
+        Tree::Branch {
+            depth: 7,
+            kids: vec![Tree::Leaf(0, 5), Tree::Leaf(0, 6)],
+        }
+    );
+}
+
+/// A type parameter that isn't a `Term` and only shows up in skipped fields.
+#[derive(Clone, Debug, PartialEq)]
+struct Interner;
+
+#[derive(Clone, Debug, PartialEq, Term)]
+enum Expr<T> {
+    Atom(T),
+    Call(Box<Expr<T>>, Vec<Expr<T>>),
+}
+
+#[derive(Clone, Debug, PartialEq, Term)]
+struct Annotated<T, I> {
+    value: T,
+    #[scrap(skip)]
+    interner: I,
+}
+
+#[derive(Clone, Debug, PartialEq, Term)]
+#[scrap(bound = "T: Term + Clone")]
+struct Overridden<T> {
+    value: T,
+}
+
+#[test]
+fn generic_types_get_inferred_bounds() {
+    let expr = Annotated {
+        value: Expr::Call(Box::new(Expr::Atom(1u32)), vec![Expr::Atom(2)]),
+        interner: Interner,
+    };
+
+    let mut incr = Everywhere::new(Transformation::new(|n: u32| n + 1));
+    assert_eq!(
+        incr.transform(expr),
+        Annotated {
+            value: Expr::Call(Box::new(Expr::Atom(2)), vec![Expr::Atom(3)]),
+            interner: Interner,
+        }
+    );
+
+    assert_eq!(
+        incr.transform(Overridden { value: vec![1u32, 2] }),
+        Overridden { value: vec![2, 3] }
+    );
+}
+
+/// Stand-in for some other crate whose types we can't derive `Term` for.
+mod other {
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Span {
+        pub lo: u32,
+        pub hi: u32,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Token<T> {
+        Word(T, Span),
+        Eof,
+    }
+}
+
+#[derive(Term)]
+#[scrap(remote = "other::Span")]
+#[allow(dead_code)]
+struct SpanDef {
+    lo: u32,
+    hi: u32,
+}
+
+#[derive(Term)]
+#[scrap(remote = "other::Token<T>")]
+#[allow(dead_code)]
+enum TokenDef<T> {
+    Word(T, #[scrap(with = "SpanDef")] other::Span),
+    Eof,
+}
+
+#[derive(Clone, Debug, PartialEq, Term)]
+struct Tokens {
+    #[scrap(with = "TokenDef<&'static str>")]
+    tokens: other::Token<&'static str>,
+    #[scrap(with = "SpanDef")]
+    span: other::Span,
+}
+
+#[test]
+fn remote_derive() {
+    let mut tokens = Tokens {
+        tokens: other::Token::Word("hi", other::Span { lo: 0, hi: 2 }),
+        span: other::Span { lo: 0, hi: 3 },
+    };
+
+    let mut sum = Everything::new(Query::new(|n: &u32| *n), |a, b| a + b);
+    assert_eq!(sum.query(&tokens), 5);
+
+    let mut shift = MutateEverything::new(Mutation::new(|n: &mut u32| *n += 10));
+    shift.mutate(&mut tokens);
+
+    let mut shout = Everywhere::new(Transformation::new(|s: &'static str| match s {
+        "hi" => "HI",
+        s => s,
+    }));
+    assert_eq!(
+        shout.transform(tokens),
+        Tokens {
+            tokens: other::Token::Word("HI", other::Span { lo: 10, hi: 12 }),
+            span: other::Span { lo: 10, hi: 13 },
+        }
+    );
+
+    let eof = Remote::<_, TokenDef<&'static str>>::new(other::Token::Eof);
+    assert_eq!(shout.transform(eof).into_inner(), other::Token::Eof);
+}
+
+/// Not a `Term`, but fine inside a `#[scrap(leaf)]` field.
+#[derive(Clone, Debug, PartialEq)]
+struct Symbol(u32);
+
+#[derive(Clone, Debug, PartialEq, Term)]
+struct Module {
+    items: Vec<u32>,
+    #[scrap(leaf)]
+    spans: Vec<u32>,
+    #[scrap(leaf)]
+    symbols: Vec<Symbol>,
+}
+
+#[test]
+fn leaf_fields_are_visited_but_not_traversed() {
+    let module = Module {
+        items: vec![1, 2],
+        spans: vec![3, 4],
+        symbols: vec![Symbol(5)],
+    };
+
+    let mut count_u32s = Everything::new(Query::new(|_: &u32| 1), |a, b| a + b);
+    let mut count_vecs = Everything::new(Query::new(|_: &Vec<u32>| 1), |a, b| a + b);
+    assert_eq!(count_u32s.query(&module), 2);
+    assert_eq!(count_vecs.query(&module), 2);
+
+    let mut incr = Everywhere::new(Transformation::new(|n: u32| n + 1));
+    let mut push = Everywhere::new(Transformation::new(|mut v: Vec<u32>| {
+        v.push(0);
+        v
+    }));
+    assert_eq!(
+        push.transform(incr.transform(module)),
+        Module {
+            items: vec![2, 3, 0],
+            spans: vec![3, 4, 0],
+            symbols: vec![Symbol(5)],
+        }
+    );
+}
+
+#[derive(Clone, Debug, PartialEq, Term)]
+#[scrap(transparent)]
+struct Spanned<T> {
+    node: T,
+    #[scrap(skip)]
+    span: (u32, u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Term)]
+#[scrap(transparent)]
+struct Id<T>(T);
+
+#[derive(Clone, Debug, PartialEq, Term)]
+enum Stmt {
+    Expr(u32),
+    Block(Vec<Spanned<Stmt>>),
+}
+
+#[test]
+fn transparent_wrappers() {
+    let spanned = |node, lo| Spanned {
+        node,
+        span: (lo, lo + 1),
+    };
+    let block = spanned(
+        Stmt::Block(vec![spanned(Stmt::Expr(1), 1), spanned(Stmt::Expr(2), 2)]),
+        0,
+    );
+
+    // Typed queries and transformations on `Stmt` fire inside `Spanned<Stmt>`
+    // even when applied directly to the wrapper.
+    let mut is_block = Query::new(|s: &Stmt| matches!(*s, Stmt::Block(_)));
+    assert!(is_block.query(&block));
+
+    let mut count = Everything::new(Query::new(|_: &Stmt| 1), |a, b| a + b);
+    assert_eq!(count.query(&block), 3);
+
+    let mut bump = Transformation::new(|s: Stmt| match s {
+        Stmt::Expr(n) => Stmt::Expr(100 + n),
+        s => s,
+    });
+    assert_eq!(
+        bump.transform(Id(spanned(Stmt::Expr(3), 3))),
+        Id(spanned(Stmt::Expr(103), 3))
+    );
+
+    let mut bump = Everywhere::new(bump);
+    assert_eq!(
+        bump.transform(block),
+        spanned(
+            Stmt::Block(vec![spanned(Stmt::Expr(101), 1), spanned(Stmt::Expr(102), 2)]),
+            0,
+        )
+    );
+
+    // But the wrappers themselves can still be targeted.
+    let mut respan = Everywhere::new(Transformation::new(|s: Spanned<Stmt>| Spanned {
+        span: (0, 0),
+        ..s
+    }));
+    assert_eq!(
+        respan.transform(Id(spanned(Stmt::Expr(3), 3))),
+        Id(Spanned {
+            node: Stmt::Expr(3),
+            span: (0, 0),
+        })
+    );
+}
+
+#[derive(Clone, Debug, PartialEq, Term, TermMeta)]
+#[scrap(order(rhs))]
+struct Assign {
+    lhs: u32,
+    rhs: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Term, TermMeta)]
+enum Op {
+    #[scrap(order(1, 0))]
+    Sub(u32, u32),
+    #[scrap(order(c, a))]
+    Select { a: u32, b: u32, c: u32 },
+}
+
+#[test]
+fn order_of_traversal() {
+    let mut seen = vec![];
+    {
+        let mut record = Everywhere::new(Transformation::new(|n: u32| {
+            seen.push(n);
+            n
+        }));
+        record.transform(Assign { lhs: 1, rhs: 2 });
+        record.transform(Op::Sub(3, 4));
+        record.transform(Op::Select { a: 5, b: 6, c: 7 });
+    }
+    assert_eq!(seen, vec![2, 1, 4, 3, 7, 5, 6]);
+
+    let mut seen = vec![];
+    {
+        let mut record = Everything::new(Query::new(|n: &u32| seen.push(*n)), |_, _| ());
+        record.query(&Op::Select { a: 5, b: 6, c: 7 });
+    }
+    assert_eq!(seen, vec![7, 5, 6]);
+
+    assert_eq!(Assign { lhs: 1, rhs: 2 }.child_names(), &["rhs", "lhs"]);
+    assert_eq!(Op::Sub(3, 4).child_names(), &["1", "0"]);
+}
+
+#[test]
+fn reverse_order_of_traversal() {
+    let mut seen = vec![];
+    {
+        let mut record = Everywhere::new_rev(Transformation::new(|n: u32| {
+            seen.push(n);
+            n
+        }));
+        record.transform(Assign { lhs: 1, rhs: 2 });
+        record.transform(Op::Select { a: 5, b: 6, c: 7 });
+        record.transform(vec![Op::Sub(3, 4), Op::Sub(8, 9)]);
+    }
+    assert_eq!(seen, vec![1, 2, 6, 5, 7, 8, 9, 3, 4]);
+
+    let mut seen = vec![];
+    {
+        let mut record = MutateEverything::new_rev(Mutation::new(|n: &mut u32| seen.push(*n)));
+        record.mutate(&mut (Assign { lhs: 1, rhs: 2 }, 3u32));
+    }
+    assert_eq!(seen, vec![3, 1, 2]);
+
+    // Remote mirror definitions traverse in reverse too.
+    let tokens = Tokens {
+        tokens: other::Token::Word("hi", other::Span { lo: 1, hi: 2 }),
+        span: other::Span { lo: 3, hi: 4 },
+    };
+    let mut seen = vec![];
+    {
+        let mut record = Everything::new_rev(Query::new(|n: &u32| seen.push(*n)), |_, _| ());
+        record.query(&tokens);
+    }
+    assert_eq!(seen, vec![4, 3, 2, 1]);
+}
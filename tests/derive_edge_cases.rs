@@ -17,3 +17,20 @@ struct EmptyStruct {}
 
 #[derive(Term)]
 enum EmptyEnum {}
+
+#[derive(Term)]
+struct NamedFields {
+    f: bool,
+    q: u32,
+    each: Vec<NamedFields>,
+}
+
+#[derive(Term)]
+struct ManyTupleFields(u8, u8, u8, u8, u8, u8, u8, u8);
+
+#[derive(Term)]
+enum AllVariantKinds {
+    Unit,
+    Tuple(bool, Box<AllVariantKinds>),
+    Struct { a: u32, b: Box<AllVariantKinds> },
+}
@@ -0,0 +1,60 @@
+extern crate scrapmetal;
+
+extern crate scrapmetal_derive;
+
+use scrapmetal::*;
+use scrapmetal_derive::{Term, TermMeta};
+
+#[derive(Term, TermMeta)]
+struct Call {
+    callee: &'static str,
+    #[scrap(skip)]
+    _id: u32,
+    args: Vec<Expr>,
+}
+
+#[derive(Term, TermMeta)]
+enum Expr {
+    Lit(u32),
+    Call(Box<Call>),
+    Binary { op: char, lhs: Box<Expr>, rhs: Box<Expr> },
+    Hole,
+}
+
+#[derive(Term, TermMeta)]
+struct Pair<T>(T, T);
+
+#[test]
+fn derive_term_meta() {
+    let call = Call {
+        callee: "f",
+        _id: 0,
+        args: vec![],
+    };
+    assert_eq!(call.type_name(), "Call");
+    assert_eq!(call.variant_name(), None);
+    assert_eq!(call.child_names(), &["callee", "args"]);
+
+    let lit = Expr::Lit(1);
+    assert_eq!(lit.type_name(), "Expr");
+    assert_eq!(lit.variant_name(), Some("Lit"));
+    assert_eq!(lit.child_names(), &["0"]);
+
+    let call = Expr::Call(Box::new(call));
+    assert_eq!(call.variant_name(), Some("Call"));
+
+    let binary = Expr::Binary {
+        op: '+',
+        lhs: Box::new(Expr::Hole),
+        rhs: Box::new(Expr::Hole),
+    };
+    assert_eq!(binary.variant_name(), Some("Binary"));
+    assert_eq!(binary.child_names(), &["op", "lhs", "rhs"]);
+
+    assert_eq!(Expr::Hole.variant_name(), Some("Hole"));
+    assert!(Expr::Hole.child_names().is_empty());
+
+    let pair = Pair(1, 2);
+    assert_eq!(pair.type_name(), "Pair");
+    assert_eq!(pair.child_names(), &["0", "1"]);
+}